@@ -0,0 +1,135 @@
+use crate::{ColorexpError, Result};
+use regex::Regex;
+use std::sync::LazyLock;
+
+static ALIAS_NAME: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[A-Za-z_][A-Za-z0-9_]*$").unwrap());
+static REFERENCE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\(\?&([A-Za-z_][A-Za-z0-9_]*)\)").unwrap());
+
+/// Aliases are named sub-patterns given by --define, like `time=\d\d:\d\d:\d\d`, which patterns
+/// reference as `(?&time)`, like subroutines in PCRE. The references are expanded when the
+/// patterns are loaded, into non-capturing groups, so that e.g. quantifiers apply to all of an
+/// alias. An alias may reference the aliases defined before it, which rules out cycles.
+#[derive(Clone, Debug, Default)]
+pub struct Aliases(Vec<(String, String)>);
+
+impl Aliases {
+    /// define adds the alias given by a spec like `NAME=PATTERN`, replacing an earlier alias of
+    /// the same name.
+    pub fn define(&mut self, spec: &str) -> Result<()> {
+        let bad_spec = |reason: &str| ColorexpError::BadAliasSpec {
+            spec: spec.to_string(),
+            reason: reason.to_string(),
+        };
+        let Some((name, pattern)) = spec.split_once('=') else {
+            return Err(bad_spec("expected an alias like NAME=PATTERN"));
+        };
+        if !ALIAS_NAME.is_match(name) {
+            return Err(bad_spec(
+                "the name must consist of letters, digits and underscores",
+            ));
+        }
+        let pattern = self.expand(pattern)?;
+        self.0.retain(|(n, _)| n != name);
+        self.0.push((name.to_string(), pattern));
+        Ok(())
+    }
+
+    /// expand replaces the references to aliases in the pattern by the aliases. References
+    /// preceded by a backslash, like `\(?&time)`, are left alone.
+    pub fn expand(&self, pattern: &str) -> Result<String> {
+        let mut expanded = String::with_capacity(pattern.len());
+        let mut copied = 0;
+        for caps in REFERENCE.captures_iter(pattern) {
+            let reference = caps.get(0).unwrap();
+            let backslashes = pattern[..reference.start()]
+                .bytes()
+                .rev()
+                .take_while(|&b| b == b'\\')
+                .count();
+            if backslashes % 2 == 1 {
+                continue;
+            }
+            let name = &caps[1];
+            let Some((_, alias)) = self.0.iter().find(|(n, _)| n == name) else {
+                return Err(ColorexpError::UnknownAlias {
+                    name: name.to_string(),
+                    pattern: pattern.to_string(),
+                });
+            };
+            expanded.push_str(&pattern[copied..reference.start()]);
+            expanded.push_str("(?:");
+            expanded.push_str(alias);
+            expanded.push(')');
+            copied = reference.end();
+        }
+        expanded.push_str(&pattern[copied..]);
+        Ok(expanded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MatchOptions, compile_patterns, match_line};
+    use rstest::rstest;
+
+    fn aliases(specs: &[&str]) -> Aliases {
+        let mut aliases = Aliases::default();
+        for spec in specs {
+            aliases.define(spec).unwrap();
+        }
+        aliases
+    }
+
+    #[rstest]
+    #[case::single(r"at (?&time)", r"at (?:\d\d:\d\d)")]
+    #[case::quantified(r"(?&time)+", r"(?:\d\d:\d\d)+")]
+    #[case::twice(r"(?&time)-(?&time)", r"(?:\d\d:\d\d)-(?:\d\d:\d\d)")]
+    #[case::nested(r"(?&stamp)", r"(?:\d{4}-\d\d-\d\d (?:\d\d:\d\d))")]
+    #[case::escaped(r"\(?&time)", r"\(?&time)")]
+    #[case::escaped_backslash(r"\\(?&time)", r"\\(?:\d\d:\d\d)")]
+    #[case::without_references(r"\d+", r"\d+")]
+    fn test_expand(#[case] pattern: &str, #[case] expected: &str) {
+        let aliases = aliases(&[r"time=\d\d:\d\d", r"stamp=\d{4}-\d\d-\d\d (?&time)"]);
+        assert_eq!(aliases.expand(pattern).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_expand_shared_alias() {
+        let aliases = aliases(&[r"time=\d\d:\d\d"]);
+        let patterns = [r"start=(?&time)", r"end=(?&time)"]
+            .iter()
+            .map(|p| aliases.expand(p).unwrap())
+            .collect::<Vec<_>>();
+        let regexps = compile_patterns(&patterns, false, false).unwrap();
+        let line = "start=10:00 end=11:30 end=late";
+        let matched = match_line(line, &regexps, &MatchOptions::default())
+            .iter()
+            .map(|r| &line[r.start_idx..r.end_idx])
+            .collect::<Vec<_>>();
+        assert_eq!(matched, vec!["start=10:00", "end=11:30"]);
+    }
+
+    #[test]
+    fn test_expand_unknown_alias() {
+        let err = aliases(&["a=x"]).expand("(?&a)(?&b)").unwrap_err();
+        assert_eq!(err.to_string(), "unknown alias `b` in pattern `(?&a)(?&b)`");
+    }
+
+    #[rstest]
+    #[case::no_equals("time")]
+    #[case::empty_name(r"=\d")]
+    #[case::bad_name(r"my-time=\d")]
+    #[case::later_alias("a=(?&b)")]
+    fn test_define_invalid(#[case] spec: &str) {
+        assert!(Aliases::default().define(spec).is_err());
+    }
+
+    #[test]
+    fn test_define_replaces() {
+        let aliases = aliases(&["a=x", "a=y"]);
+        assert_eq!(aliases.expand("(?&a)").unwrap(), "(?:y)");
+    }
+}
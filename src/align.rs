@@ -0,0 +1,85 @@
+use crate::RangeWithId;
+use crate::escapes::visible_width;
+
+/// align_first_matches inserts spaces in front of the first range of every line, so that the first
+/// ranges of all lines start in the same column. The ranges are moved accordingly. Lines without
+/// ranges are left as they are. Escape sequences already present in the lines take up no space.
+pub fn align_first_matches(lines: &mut [(String, Vec<RangeWithId>)]) {
+    let column = |line: &str, ranges: &[RangeWithId]| {
+        ranges.first().map(|r| visible_width(&line[..r.start_idx]))
+    };
+    let Some(max_column) = lines
+        .iter()
+        .filter_map(|(line, ranges)| column(line, ranges))
+        .max()
+    else {
+        return;
+    };
+    for (line, ranges) in lines {
+        let Some(col) = column(line, ranges) else {
+            continue;
+        };
+        let padding = max_column - col;
+        line.insert_str(ranges[0].start_idx, &" ".repeat(padding));
+        for r in ranges {
+            r.start_idx += padding;
+            r.end_idx += padding;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MatchOptions, compile_patterns, match_line};
+
+    fn align(lines: &[&str]) -> Vec<(String, Vec<RangeWithId>)> {
+        let regexps = compile_patterns(&["x+".into()], false, false).unwrap();
+        let mut lines = lines
+            .iter()
+            .map(|line| {
+                let ranges = match_line(line, &regexps, &MatchOptions::default());
+                (line.to_string(), ranges)
+            })
+            .collect::<Vec<_>>();
+        align_first_matches(&mut lines);
+        lines
+    }
+
+    #[test]
+    fn test_align_first_matches() {
+        let lines = align(&["a x", "bbb xx x", "x", "none"]);
+        let texts = lines.iter().map(|(l, _)| l.as_str()).collect::<Vec<_>>();
+        assert_eq!(texts, vec!["a   x", "bbb xx x", "    x", "none"]);
+        for (line, ranges) in &lines[..3] {
+            assert_eq!(ranges[0].start_idx, 4);
+            assert!(line[ranges[0].start_idx..].starts_with('x'));
+        }
+        // later ranges are moved as well
+        assert_eq!(lines[0].1.len(), 1);
+        assert_eq!(&lines[1].0[lines[1].1[1].start_idx..], "x");
+        assert!(lines[3].1.is_empty());
+    }
+
+    #[test]
+    fn test_align_first_matches_unicode_width() {
+        // 'ü' takes one column but two bytes, '漢' two columns and three bytes
+        let lines = align(&["ü x", "漢 x", "ab x"]);
+        let texts = lines.iter().map(|(l, _)| l.as_str()).collect::<Vec<_>>();
+        assert_eq!(texts, vec!["ü  x", "漢 x", "ab x"]);
+    }
+
+    #[test]
+    fn test_align_first_matches_escapes() {
+        let lines = align(&["\x1b[32mab\x1b[0m x", "abc x"]);
+        let texts = lines.iter().map(|(l, _)| l.as_str()).collect::<Vec<_>>();
+        assert_eq!(texts, vec!["\x1b[32mab\x1b[0m  x", "abc x"]);
+    }
+
+    #[test]
+    fn test_align_first_matches_without_matches() {
+        let lines = align(&["a", "b"]);
+        assert_eq!(lines[0].0, "a");
+        assert_eq!(lines[1].0, "b");
+    }
+}
@@ -0,0 +1,140 @@
+use crate::{ColorexpError, RangeWithId, Result, add_range};
+
+/// BracketPairs are the pairs of brackets given by --balanced, like `(){}`, whose matching pairs
+/// get colored by their depth, so that the nesting shows. Regexps cannot count, so the brackets are
+/// paired up by a scan of the line, keeping a stack of the open brackets.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BracketPairs(Vec<(char, char)>);
+
+impl BracketPairs {
+    /// parse parses pairs of an opening and a closing bracket, like `()` or `()[]{}`.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let bad_spec = |reason: &str| ColorexpError::BadBracketSpec {
+            spec: spec.to_string(),
+            reason: reason.to_string(),
+        };
+        let chars = spec.chars().collect::<Vec<_>>();
+        if chars.is_empty() || chars.len() % 2 == 1 {
+            return Err(bad_spec(
+                "expected pairs of an opening and a closing bracket, like \"()\"",
+            ));
+        }
+        let pairs = chars
+            .chunks_exact(2)
+            .map(|pair| (pair[0], pair[1]))
+            .collect::<Vec<_>>();
+        for (idx, &(open, close)) in pairs.iter().enumerate() {
+            if open == close {
+                return Err(bad_spec("opening and closing brackets must differ"));
+            }
+            if chars[..2 * idx].iter().any(|&c| c == open || c == close) {
+                return Err(bad_spec("every bracket may only be given once"));
+            }
+        }
+        Ok(Self(pairs))
+    }
+
+    /// match_line adds ranges for the matching pairs of brackets in the line, with the ids
+    /// first_id to first_id + depths - 1 by their depth, starting over after the last one.
+    /// Brackets without a partner, like a closing bracket of another kind than the last opened
+    /// one, or one that is never closed, are left alone, while the brackets still open count for
+    /// the depth. Existing ranges take precedence.
+    pub fn match_line(
+        &self,
+        line: &str,
+        ranges: &mut Vec<RangeWithId>,
+        first_id: usize,
+        depths: usize,
+    ) {
+        // the open brackets, with their index and the index of their pair
+        let mut open = Vec::<(usize, usize)>::new();
+        let mut pairs = Vec::new();
+        for (idx, c) in line.char_indices() {
+            if let Some(pair_idx) = self.0.iter().position(|&(o, _)| o == c) {
+                open.push((idx, pair_idx));
+            } else if let Some(pair_idx) = self.0.iter().position(|&(_, cl)| cl == c)
+                && open
+                    .last()
+                    .is_some_and(|&(_, open_pair)| open_pair == pair_idx)
+            {
+                let (open_idx, _) = open.pop().unwrap();
+                pairs.push((open_idx, idx, c.len_utf8(), open.len()));
+            }
+        }
+        for (open_idx, close_idx, close_len, depth) in pairs {
+            let id = first_id + depth % depths;
+            let open_len = line[open_idx..].chars().next().map_or(1, char::len_utf8);
+            add_range(
+                ranges,
+                RangeWithId {
+                    start_idx: open_idx,
+                    end_idx: open_idx + open_len,
+                    id,
+                },
+            );
+            add_range(
+                ranges,
+                RangeWithId {
+                    start_idx: close_idx,
+                    end_idx: close_idx + close_len,
+                    id,
+                },
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ColorSet, colorize};
+    use rstest::rstest;
+
+    /// depths returns the line with every colored bracket followed by its depth.
+    fn depths(spec: &str, line: &str) -> String {
+        let mut ranges = Vec::new();
+        BracketPairs::parse(spec)
+            .unwrap()
+            .match_line(line, &mut ranges, 0, 2);
+        let mut line = line.to_string();
+        let colors = [ColorSet::new("", "0"), ColorSet::new("", "1")];
+        colorize(&mut line, &[], &colors, &ranges, 0);
+        line
+    }
+
+    #[rstest]
+    #[case::nested("()", "f(g(x), (y))", "f(0g(1x)1, (1y)1)0")]
+    #[case::cycling("()", "((()))", "(0(1()0)1)0")]
+    #[case::kinds("(){}", "{a: (b)}", "{0a: (1b)1}0")]
+    #[case::unclosed("()", "(a (b)", "(a (1b)1")]
+    #[case::unopened("()", "a) (b)", "a) (0b)0")]
+    #[case::crossed("()[]", "([)]", "([1)]1")]
+    #[case::not_given("()", "[a]", "[a]")]
+    #[case::multibyte("«»", "«a«b»»", "«0a«1b»1»0")]
+    fn test_match_line(#[case] spec: &str, #[case] line: &str, #[case] expected: &str) {
+        assert_eq!(depths(spec, line), expected);
+    }
+
+    #[test]
+    fn test_match_line_keeps_matches() {
+        let mut ranges = vec![RangeWithId {
+            start_idx: 0,
+            end_idx: 1,
+            id: 5,
+        }];
+        BracketPairs::parse("()")
+            .unwrap()
+            .match_line("(a)", &mut ranges, 0, 2);
+        assert_eq!(ranges[0].id, 5);
+        assert_eq!(ranges.len(), 2);
+    }
+
+    #[rstest]
+    #[case::empty("")]
+    #[case::odd("(){")]
+    #[case::same("||")]
+    #[case::repeated("()(]")]
+    fn test_parse_invalid(#[case] spec: &str) {
+        assert!(BracketPairs::parse(spec).is_err());
+    }
+}
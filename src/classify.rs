@@ -0,0 +1,90 @@
+use crate::{ColorexpError, Result, compile_pattern};
+use regex::Regex;
+
+/// parse_class splits a --classify rule like `FATAL=red` into the regex matching the lines of the
+/// class and the color spec.
+pub fn parse_class(rule: &str) -> Result<(Regex, &str)> {
+    let Some((pattern, color)) = rule.rsplit_once('=') else {
+        return Err(ColorexpError::BadColorSpec {
+            spec: rule.to_string(),
+            reason: "expected a rule like PATTERN=COLOR".to_string(),
+        });
+    };
+    Ok((compile_pattern(pattern, false, false)?, color))
+}
+
+/// Classes colors whole lines by the first rule whose regex matches them, like a preset for log
+/// levels would. The color of the class is given to the line with tint_line, after the matches
+/// within the line.
+pub struct Classes {
+    /// The regexes matching the lines of the classes, with the ids to give them.
+    pub rules: Vec<(Regex, usize)>,
+}
+
+impl Classes {
+    /// class returns the id of the first rule matching the line, if any.
+    pub fn class(&self, line: &str) -> Option<usize> {
+        self.rules
+            .iter()
+            .find(|(re, _)| re.is_match(line))
+            .map(|(_, id)| *id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::presets::tint_line;
+    use crate::{ColorSet, MatchOptions, colorize, compile_patterns, match_line};
+    use rstest::rstest;
+
+    fn classes() -> Classes {
+        let rules = ["FATAL=red", "WARN|ERROR=yellow"]
+            .iter()
+            .enumerate()
+            .map(|(i, rule)| (parse_class(rule).unwrap().0, 1 + i))
+            .collect();
+        Classes { rules }
+    }
+
+    #[rstest]
+    #[case::simple("FATAL=red", "a FATAL error", "red")]
+    #[case::equals_in_pattern("level=fatal=red", "level=fatal", "red")]
+    fn test_parse_class(#[case] rule: &str, #[case] line: &str, #[case] color: &str) {
+        let (re, c) = parse_class(rule).unwrap();
+        assert!(re.is_match(line));
+        assert_eq!(c, color);
+    }
+
+    #[rstest]
+    #[case::no_equals("FATAL")]
+    #[case::invalid_pattern("(=red")]
+    fn test_parse_class_invalid(#[case] rule: &str) {
+        assert!(parse_class(rule).is_err());
+    }
+
+    #[rstest]
+    #[case::first("FATAL", Some(1))]
+    #[case::second("ERROR", Some(2))]
+    // the first matching rule wins
+    #[case::both("ERROR then FATAL", Some(1))]
+    #[case::none("INFO", None)]
+    fn test_class(#[case] line: &str, #[case] expected: Option<usize>) {
+        assert_eq!(classes().class(line), expected);
+    }
+
+    #[test]
+    fn test_classify_keeps_inner_matches() {
+        let regexps = compile_patterns(&[r"\d+".into()], false, false).unwrap();
+        let colors = [ColorSet::new("<n>", "</n>")];
+        let reserved = [
+            ColorSet::new("<red>", "</red>"),
+            ColorSet::new("<yellow>", "</yellow>"),
+        ];
+        let mut line = "FATAL: code 42".to_string();
+        let mut ranges = match_line(&line, &regexps, &MatchOptions::default());
+        tint_line(&line, &mut ranges, classes().class(&line).unwrap());
+        colorize(&mut line, &colors, &reserved, &ranges, 1);
+        assert_eq!(line, "<red>FATAL: code </red><n>42</n>");
+    }
+}
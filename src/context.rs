@@ -0,0 +1,156 @@
+use crate::RangeWithId;
+use std::cmp::max;
+
+/// ELLIPSIS replaces the parts of a line that --trim-context leaves out, except for those between
+/// the kept parts if --group-separator gives another separator.
+pub const ELLIPSIS: &str = "…";
+
+/// trim_context shortens the line to the ranges and up to context characters around each of them,
+/// and returns it together with the ranges moved accordingly. The parts left out between the kept
+/// parts are replaced by the separator, with ranges with separator_id, if given, and those at the
+/// start and end of the line by ELLIPSIS. Lines without ranges are returned unchanged.
+pub fn trim_context(
+    line: &str,
+    ranges: &[RangeWithId],
+    context: usize,
+    separator: &str,
+    separator_id: Option<usize>,
+) -> (String, Vec<RangeWithId>) {
+    // the parts of the line to keep, merged where they overlap or touch
+    let mut windows: Vec<(usize, usize)> = Vec::new();
+    for r in ranges {
+        let start = chars_before(line, r.start_idx, context);
+        let end = chars_after(line, r.end_idx, context);
+        match windows.last_mut() {
+            Some(last) if start <= last.1 => last.1 = max(last.1, end),
+            _ => windows.push((start, end)),
+        }
+    }
+    if windows.is_empty() {
+        return (line.to_string(), ranges.to_vec());
+    }
+    let mut trimmed = String::with_capacity(line.len());
+    let mut trimmed_ranges = Vec::with_capacity(ranges.len());
+    let mut ranges = ranges.iter().peekable();
+    let push_separator = |trimmed: &mut String, trimmed_ranges: &mut Vec<RangeWithId>| {
+        let start_idx = trimmed.len();
+        trimmed.push_str(separator);
+        if let Some(id) = separator_id
+            && !separator.is_empty()
+        {
+            trimmed_ranges.push(RangeWithId {
+                start_idx,
+                end_idx: trimmed.len(),
+                id,
+            });
+        }
+    };
+    let mut prev_end = None;
+    for (start, end) in windows {
+        match prev_end {
+            Some(_) => push_separator(&mut trimmed, &mut trimmed_ranges),
+            None if start > 0 => trimmed.push_str(ELLIPSIS),
+            None => {}
+        }
+        let offset = trimmed.len();
+        trimmed.push_str(&line[start..end]);
+        while let Some(r) = ranges.next_if(|r| r.end_idx <= end) {
+            trimmed_ranges.push(RangeWithId {
+                start_idx: r.start_idx - start + offset,
+                end_idx: r.end_idx - start + offset,
+                id: r.id,
+            });
+        }
+        prev_end = Some(end);
+    }
+    if prev_end.is_some_and(|end| end < line.len()) {
+        trimmed.push_str(ELLIPSIS);
+    }
+    (trimmed, trimmed_ranges)
+}
+
+/// chars_before returns the index of the count-th character before idx, or 0 if there are fewer.
+fn chars_before(line: &str, idx: usize, count: usize) -> usize {
+    line[..idx]
+        .char_indices()
+        .rev()
+        .take(count)
+        .last()
+        .map_or(idx, |(i, _)| i)
+}
+
+/// chars_after returns the index after the count-th character after idx, or the length of the
+/// line if there are fewer.
+fn chars_after(line: &str, idx: usize, count: usize) -> usize {
+    line[idx..]
+        .char_indices()
+        .nth(count)
+        .map_or(line.len(), |(i, _)| idx + i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn r(start_idx: usize, end_idx: usize, id: usize) -> RangeWithId {
+        RangeWithId {
+            start_idx,
+            end_idx,
+            id,
+        }
+    }
+
+    #[rstest]
+    #[case::middle("aaaaaXbbbbb", vec![r(5, 6, 0)], 2, "…aaXbb…", vec![r(5, 6, 0)])]
+    #[case::at_start("Xbbbbb", vec![r(0, 1, 0)], 2, "Xbb…", vec![r(0, 1, 0)])]
+    #[case::at_end("aaaaaX", vec![r(5, 6, 0)], 2, "…aaX", vec![r(5, 6, 0)])]
+    #[case::context_exceeds_line("aXb", vec![r(1, 2, 0)], 5, "aXb", vec![r(1, 2, 0)])]
+    #[case::zero_context("aaXbb", vec![r(2, 3, 0)], 0, "…X…", vec![r(3, 4, 0)])]
+    #[case::separate_windows(
+        "aXaaaaaaYa",
+        vec![r(1, 2, 0), r(8, 9, 1)],
+        1,
+        "aXa…aYa",
+        vec![r(1, 2, 0), r(7, 8, 1)]
+    )]
+    #[case::merged_windows("aXaaYa", vec![r(1, 2, 0), r(4, 5, 1)], 1, "aXaaYa", vec![r(1, 2, 0), r(4, 5, 1)])]
+    #[case::multibyte_context("ääXöö", vec![r(4, 5, 0)], 1, "…äXö…", vec![r(5, 6, 0)])]
+    #[case::no_ranges("aaaa", vec![], 1, "aaaa", vec![])]
+    fn test_trim_context(
+        #[case] line: &str,
+        #[case] ranges: Vec<RangeWithId>,
+        #[case] context: usize,
+        #[case] expected_line: &str,
+        #[case] expected_ranges: Vec<RangeWithId>,
+    ) {
+        let (trimmed, trimmed_ranges) = trim_context(line, &ranges, context, ELLIPSIS, None);
+        assert_eq!(trimmed, expected_line);
+        assert_eq!(trimmed_ranges, expected_ranges);
+        // the matches stay visible
+        for (r, t) in ranges.iter().zip(&trimmed_ranges) {
+            assert_eq!(
+                &line[r.start_idx..r.end_idx],
+                &trimmed[t.start_idx..t.end_idx]
+            );
+        }
+    }
+
+    #[rstest]
+    // only the part between the kept parts gets the separator
+    #[case::custom(" [...] ", None, "…X [...] Y…", vec![r(3, 4, 0), r(11, 12, 1)])]
+    #[case::empty("", None, "…XY…", vec![r(3, 4, 0), r(4, 5, 1)])]
+    #[case::colored("|", Some(7), "…X|Y…", vec![r(3, 4, 0), r(4, 5, 7), r(5, 6, 1)])]
+    fn test_trim_context_separator(
+        #[case] separator: &str,
+        #[case] separator_id: Option<usize>,
+        #[case] expected_line: &str,
+        #[case] expected_ranges: Vec<RangeWithId>,
+    ) {
+        let ranges = [r(2, 3, 0), r(7, 8, 1)];
+        let (trimmed, trimmed_ranges) =
+            trim_context("aaXaaaaYaa", &ranges, 0, separator, separator_id);
+        assert_eq!(trimmed, expected_line);
+        assert_eq!(trimmed_ranges, expected_ranges);
+    }
+}
@@ -0,0 +1,146 @@
+use crate::{RangeWithId, add_range};
+
+/// ControlNotation is the way show_control makes control characters visible.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ControlNotation {
+    /// Caret notation, like `^A` for `\x01` and `^?` for DEL.
+    Caret,
+    /// The Unicode control pictures, like `␁` for `\x01` and `␡` for DEL.
+    Pictures,
+}
+
+/// is_shown returns whether show_control makes c visible: the ASCII control characters, except
+/// for tab, and ESC, which starts the escape sequences that may already color the input.
+fn is_shown(c: char) -> bool {
+    c.is_ascii_control() && c != '\t' && c != '\x1b'
+}
+
+/// visible returns how the control character c is written in the given notation.
+fn visible(c: char, notation: ControlNotation) -> String {
+    let b = c as u32;
+    match notation {
+        ControlNotation::Caret => format!("^{}", char::from_u32(b ^ 0x40).unwrap()),
+        ControlNotation::Pictures if b == 0x7f => "\u{2421}".to_string(),
+        ControlNotation::Pictures => char::from_u32(0x2400 + b).unwrap().to_string(),
+    }
+}
+
+/// show_control replaces the control characters of the line, except for tab and ESC, by their
+/// visible form in the given notation, with ranges with the given id, and moves the other ranges
+/// accordingly. As this is done after matching, patterns still match the original characters.
+/// The control characters take precedence over the ranges covering them.
+pub fn show_control(
+    line: &mut String,
+    ranges: &mut Vec<RangeWithId>,
+    id: usize,
+    notation: ControlNotation,
+) {
+    if !line.contains(is_shown) {
+        return;
+    }
+    let mut shown = String::with_capacity(line.len() + 8);
+    let mut control_ranges = Vec::new();
+    // the index in shown of every index in line, and of its end
+    let mut new_idx = Vec::with_capacity(line.len() + 1);
+    for c in line.chars() {
+        let start_idx = shown.len();
+        if is_shown(c) {
+            shown.push_str(&visible(c, notation));
+            control_ranges.push(RangeWithId {
+                start_idx,
+                end_idx: shown.len(),
+                id,
+            });
+        } else {
+            shown.push(c);
+        }
+        new_idx.extend(std::iter::repeat_n(start_idx, c.len_utf8()));
+    }
+    new_idx.push(shown.len());
+    for r in ranges.iter() {
+        add_range(
+            &mut control_ranges,
+            RangeWithId {
+                start_idx: new_idx[r.start_idx],
+                end_idx: new_idx[r.end_idx],
+                id: r.id,
+            },
+        );
+    }
+    *line = shown;
+    *ranges = control_ranges;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn r(start_idx: usize, end_idx: usize, id: usize) -> RangeWithId {
+        RangeWithId {
+            start_idx,
+            end_idx,
+            id,
+        }
+    }
+
+    #[rstest]
+    #[case::caret_soh('\x01', ControlNotation::Caret, "^A")]
+    #[case::caret_nul('\0', ControlNotation::Caret, "^@")]
+    #[case::caret_cr('\r', ControlNotation::Caret, "^M")]
+    #[case::caret_del('\x7f', ControlNotation::Caret, "^?")]
+    #[case::picture_soh('\x01', ControlNotation::Pictures, "␁")]
+    #[case::picture_us('\x1f', ControlNotation::Pictures, "␟")]
+    #[case::picture_del('\x7f', ControlNotation::Pictures, "␡")]
+    fn test_visible(#[case] c: char, #[case] notation: ControlNotation, #[case] expected: &str) {
+        assert_eq!(visible(c, notation), expected);
+    }
+
+    #[rstest]
+    #[case::caret(ControlNotation::Caret, "a^Ab", vec![r(1, 3, 9)])]
+    #[case::pictures(ControlNotation::Pictures, "a␁b", vec![r(1, 4, 9)])]
+    fn test_show_control(
+        #[case] notation: ControlNotation,
+        #[case] expected_line: &str,
+        #[case] expected_ranges: Vec<RangeWithId>,
+    ) {
+        let mut line = "a\x01b".to_string();
+        let mut ranges = Vec::new();
+        show_control(&mut line, &mut ranges, 9, notation);
+        assert_eq!(line, expected_line);
+        assert_eq!(ranges, expected_ranges);
+    }
+
+    #[test]
+    fn test_show_control_moves_ranges() {
+        // a match before, one around and one after the control character
+        let mut line = "ab\x01cd\x02ef".to_string();
+        let mut ranges = vec![r(0, 1, 0), r(1, 5, 1), r(6, 8, 2)];
+        show_control(&mut line, &mut ranges, 9, ControlNotation::Caret);
+        assert_eq!(line, "ab^Acd^Bef");
+        assert_eq!(
+            ranges,
+            vec![
+                r(0, 1, 0),
+                r(1, 2, 1),
+                r(2, 4, 9),
+                r(4, 6, 1),
+                r(6, 8, 9),
+                r(8, 10, 2)
+            ]
+        );
+    }
+
+    #[rstest]
+    #[case::plain("no controls")]
+    #[case::tab("a\tb")]
+    #[case::escape("\x1b[31mred\x1b[0m")]
+    #[case::multibyte("äöü")]
+    fn test_show_control_unchanged(#[case] line: &str) {
+        let mut shown = line.to_string();
+        let mut ranges = vec![r(0, 1, 0)];
+        show_control(&mut shown, &mut ranges, 9, ControlNotation::Caret);
+        assert_eq!(shown, line);
+        assert_eq!(ranges, vec![r(0, 1, 0)]);
+    }
+}
@@ -0,0 +1,211 @@
+#[cfg(unix)]
+use crate::interrupt::{RESTORE, Restore, restore_on_interrupt};
+use std::io::{self, Write};
+
+/// Dashboard reserves the last line of the terminal for a status line showing the number of
+/// matches per pattern, while the lines above keep scrolling as usual. A started dashboard that is
+/// dropped without being finished, e.g. because of an error, restores the terminal on stdout, as
+/// does SIGINT while it is started.
+pub struct Dashboard {
+    rows: u16,
+    cols: u16,
+    started: bool,
+    /// The buffer written on SIGINT, and its length before the dashboard registered in it.
+    #[cfg(unix)]
+    interrupt: (&'static Restore, usize),
+}
+
+impl Dashboard {
+    /// new returns a dashboard for a terminal of the given size, or None if the terminal is too
+    /// small to reserve a line.
+    pub fn new(rows: u16, cols: u16) -> Option<Self> {
+        (rows >= 2 && cols >= 1).then_some(Self {
+            rows,
+            cols,
+            started: false,
+            #[cfg(unix)]
+            interrupt: (&RESTORE, 0),
+        })
+    }
+
+    /// start restricts scrolling to all but the last line of the terminal, and has SIGINT restore
+    /// the terminal.
+    pub fn start(&mut self, out: &mut impl Write) -> io::Result<()> {
+        self.started = true;
+        #[cfg(unix)]
+        {
+            let (restore, _) = self.interrupt;
+            self.interrupt.1 = restore.push(self.restore_sequence().as_bytes());
+            restore_on_interrupt();
+        }
+        // the newline scrolls the screen in case the cursor is on the last line, which is about to
+        // be reserved; setting the scrolling region moves the cursor, hence the save and restore
+        write!(out, "\n\x1b[1A\x1b7\x1b[1;{}r\x1b8", self.rows - 1)?;
+        out.flush()
+    }
+
+    /// update redraws the status line, leaving the cursor where it was.
+    pub fn update(
+        &self,
+        out: &mut impl Write,
+        patterns: &[String],
+        counts: &[usize],
+    ) -> io::Result<()> {
+        write!(
+            out,
+            "\x1b7\x1b[{};1H\x1b[2K{}\x1b8",
+            self.rows,
+            format_status(patterns, counts, self.cols as usize)
+        )?;
+        out.flush()
+    }
+
+    /// finish clears the status line and restores the scrolling region, then prints the final
+    /// status as a regular line.
+    pub fn finish(
+        &mut self,
+        out: &mut impl Write,
+        patterns: &[String],
+        counts: &[usize],
+    ) -> io::Result<()> {
+        self.restore(out)?;
+        writeln!(
+            out,
+            "{}",
+            format_status(patterns, counts, self.cols as usize)
+        )?;
+        out.flush()
+    }
+
+    /// restore clears the status line and restores the scrolling region.
+    fn restore(&mut self, out: &mut impl Write) -> io::Result<()> {
+        self.started = false;
+        #[cfg(unix)]
+        {
+            let (restore, len) = self.interrupt;
+            restore.truncate(len);
+        }
+        write!(out, "{}", self.restore_sequence())
+    }
+
+    /// restore_sequence clears the status line, restores the scrolling region and shows the cursor,
+    /// which may have been hidden when colorexp was interrupted.
+    fn restore_sequence(&self) -> String {
+        format!("\x1b7\x1b[{};1H\x1b[2K\x1b[r\x1b8\x1b[?25h", self.rows)
+    }
+}
+
+impl Drop for Dashboard {
+    fn drop(&mut self) {
+        if self.started {
+            // there is nowhere to report errors to while dropping
+            let mut out = io::stdout();
+            let _ = self.restore(&mut out).and_then(|()| out.flush());
+        }
+    }
+}
+
+/// format_status formats the match counts as `pattern: count` entries, truncated to the given
+/// number of characters.
+pub fn format_status(patterns: &[String], counts: &[usize], width: usize) -> String {
+    let status = patterns
+        .iter()
+        .zip(counts)
+        .map(|(p, c)| format!("{p}: {c}"))
+        .collect::<Vec<_>>()
+        .join(" | ");
+    if status.chars().count() <= width {
+        return status;
+    }
+    let mut truncated: String = status.chars().take(width.saturating_sub(1)).collect();
+    if width > 0 {
+        truncated.push('…');
+    }
+    truncated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::fits(80, "foo: 3 | b.r: 0")]
+    #[case::exact(15, "foo: 3 | b.r: 0")]
+    #[case::truncated(10, "foo: 3 | …")]
+    #[case::single(1, "…")]
+    #[case::empty(0, "")]
+    fn test_format_status(#[case] width: usize, #[case] expected: &str) {
+        let patterns = vec!["foo".to_string(), "b.r".to_string()];
+        assert_eq!(format_status(&patterns, &[3, 0], width), expected);
+    }
+
+    #[test]
+    fn test_format_status_multibyte() {
+        let patterns = vec!["äöü".to_string()];
+        assert_eq!(format_status(&patterns, &[12], 6), "äöü: …");
+    }
+
+    #[test]
+    fn test_dashboard_too_small() {
+        assert!(Dashboard::new(1, 80).is_none());
+        assert!(Dashboard::new(24, 0).is_none());
+    }
+
+    #[test]
+    fn test_dashboard_layout() {
+        let mut dashboard = Dashboard::new(24, 20).unwrap();
+        let patterns = vec!["a".to_string()];
+        let mut out = Vec::new();
+        dashboard.start(&mut out).unwrap();
+        assert_eq!(out, b"\n\x1b[1A\x1b7\x1b[1;23r\x1b8");
+
+        out.clear();
+        dashboard.update(&mut out, &patterns, &[2]).unwrap();
+        assert_eq!(out, b"\x1b7\x1b[24;1H\x1b[2Ka: 2\x1b8");
+
+        out.clear();
+        dashboard.finish(&mut out, &patterns, &[5]).unwrap();
+        assert_eq!(out, b"\x1b7\x1b[24;1H\x1b[2K\x1b[r\x1b8\x1b[?25ha: 5\n");
+    }
+
+    #[test]
+    fn test_dashboard_restored_on_drop_only_if_unfinished() {
+        let mut dashboard = Dashboard::new(24, 20).unwrap();
+        let mut out = Vec::new();
+        dashboard.start(&mut out).unwrap();
+        assert!(dashboard.started);
+        dashboard.finish(&mut out, &[], &[]).unwrap();
+        assert!(!dashboard.started);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_dashboard_restored_on_interrupt() {
+        use std::io::Read;
+        use std::os::fd::AsRawFd;
+        use std::os::unix::net::UnixStream;
+
+        let written = |restore: &Restore| {
+            let (writer, mut reader) = UnixStream::pair().unwrap();
+            restore.write(writer.as_raw_fd());
+            drop(writer);
+            let mut written = String::new();
+            reader.read_to_string(&mut written).unwrap();
+            written
+        };
+        // a buffer of its own, as the tests run in parallel
+        let restore: &'static Restore = Box::leak(Box::new(Restore::new()));
+        restore.push(b"\x1b[0m");
+        let mut dashboard = Dashboard::new(24, 20).unwrap();
+        dashboard.interrupt = (restore, 0);
+        let mut out = Vec::new();
+        dashboard.start(&mut out).unwrap();
+        assert_eq!(
+            written(restore),
+            "\x1b[0m\x1b7\x1b[24;1H\x1b[2K\x1b[r\x1b8\x1b[?25h"
+        );
+        dashboard.finish(&mut out, &[], &[]).unwrap();
+        assert_eq!(written(restore), "\x1b[0m");
+    }
+}
@@ -0,0 +1,180 @@
+use crate::{RangeWithId, add_range};
+use regex::Regex;
+
+// the minimum length of a token taken to be encoded, so that short words are not decoded
+const MIN_TOKEN_LEN: usize = 8;
+
+/// Encoding is the encoding of the tokens decoded by Decoder.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Encoding {
+    /// Standard base64, with or without padding.
+    Base64,
+    /// Hexadecimal, two digits of either case per byte.
+    Hex,
+}
+
+impl Encoding {
+    /// token_pattern returns a pattern matching the tokens that may be encoded this way.
+    fn token_pattern(self) -> String {
+        match self {
+            Encoding::Base64 => format!("[A-Za-z0-9+/]{{{MIN_TOKEN_LEN},}}={{0,2}}"),
+            Encoding::Hex => format!("[0-9A-Fa-f]{{{MIN_TOKEN_LEN},}}"),
+        }
+    }
+
+    /// decode returns the text encoded by the token, or None if it is not a valid encoding of
+    /// UTF-8 text.
+    pub fn decode(self, token: &str) -> Option<String> {
+        let bytes = match self {
+            Encoding::Base64 => decode_base64(token)?,
+            Encoding::Hex => decode_hex(token)?,
+        };
+        String::from_utf8(bytes).ok()
+    }
+}
+
+fn decode_base64(token: &str) -> Option<Vec<u8>> {
+    let digits = token.trim_end_matches('=');
+    // a single digit left over does not make a byte, and padding only fills the last group
+    if digits.len() % 4 == 1 || (digits.len() < token.len() && !token.len().is_multiple_of(4)) {
+        return None;
+    }
+    let mut bytes = Vec::with_capacity(digits.len() * 3 / 4);
+    let mut acc = 0u32;
+    let mut bits = 0;
+    for b in digits.bytes() {
+        let value = match b {
+            b'A'..=b'Z' => b - b'A',
+            b'a'..=b'z' => b - b'a' + 26,
+            b'0'..=b'9' => b - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        acc = (acc << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+    Some(bytes)
+}
+
+fn decode_hex(token: &str) -> Option<Vec<u8>> {
+    if !token.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..token.len())
+        .step_by(2)
+        .map(|idx| u8::from_str_radix(token.get(idx..idx + 2)?, 16).ok())
+        .collect()
+}
+
+/// Decoder highlights the encoded tokens of a line whose decoded text is matched by a pattern.
+pub struct Decoder {
+    encoding: Encoding,
+    tokens: Regex,
+    /// The id of the first color of each of the regexps, see regexp_ids.
+    ids: Vec<usize>,
+}
+
+impl Decoder {
+    pub fn new(encoding: Encoding, ids: Vec<usize>) -> Self {
+        Self {
+            encoding,
+            tokens: Regex::new(&encoding.token_pattern()).unwrap(),
+            ids,
+        }
+    }
+
+    /// match_line adds a range for each token of the line that decodes to text matched by one of
+    /// the regexps, with the id of the first of them. Tokens that are not validly encoded are left
+    /// alone, and the existing ranges take precedence.
+    pub fn match_line(&self, line: &str, regexps: &[Regex], ranges: &mut Vec<RangeWithId>) {
+        for token in self.tokens.find_iter(line) {
+            let Some(decoded) = self.encoding.decode(token.as_str()) else {
+                continue;
+            };
+            if let Some(id) = regexps
+                .iter()
+                .zip(&self.ids)
+                .find(|(re, _)| re.is_match(&decoded))
+                .map(|(_, id)| *id)
+            {
+                add_range(
+                    ranges,
+                    RangeWithId {
+                        start_idx: token.start(),
+                        end_idx: token.end(),
+                        id,
+                    },
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MatchOptions, compile_patterns, match_line_into};
+    use rstest::rstest;
+
+    fn r(start_idx: usize, end_idx: usize, id: usize) -> RangeWithId {
+        RangeWithId {
+            start_idx,
+            end_idx,
+            id,
+        }
+    }
+
+    #[rstest]
+    #[case::padded(Encoding::Base64, "aGVsbG8=", Some("hello"))]
+    #[case::unpadded(Encoding::Base64, "aGVsbG8", Some("hello"))]
+    #[case::no_padding_needed(Encoding::Base64, "c2VjcmV0IHBhc3N3b3Jk", Some("secret password"))]
+    #[case::bad_padding(Encoding::Base64, "aGVsbG8==", None)]
+    #[case::bad_length(Encoding::Base64, "aGVsb", None)]
+    #[case::not_utf8(Encoding::Base64, "//79", None)]
+    #[case::hex(Encoding::Hex, "70617373776F7264", Some("password"))]
+    #[case::odd_hex(Encoding::Hex, "7061737", None)]
+    #[case::not_hex(Encoding::Hex, "7x", None)]
+    fn test_decode(
+        #[case] encoding: Encoding,
+        #[case] token: &str,
+        #[case] expected: Option<&str>,
+    ) {
+        assert_eq!(encoding.decode(token).as_deref(), expected);
+    }
+
+    #[rstest]
+    #[case::base64(Encoding::Base64, "auth c2VjcmV0IHBhc3N3b3Jk ok", vec![r(5, 25, 0)])]
+    #[case::hex(Encoding::Hex, "auth 70617373776f7264 ok", vec![r(5, 21, 0)])]
+    #[case::not_matching(Encoding::Base64, "auth aGVsbG8gd29ybGQ= ok", vec![])]
+    #[case::invalid(Encoding::Base64, "auth cGFzc3dvcmQ=== ok", vec![])]
+    #[case::too_short(Encoding::Hex, "70617373", vec![])]
+    fn test_match_line(
+        #[case] encoding: Encoding,
+        #[case] line: &str,
+        #[case] expected: Vec<RangeWithId>,
+    ) {
+        let regexps = compile_patterns(&["password".into()], false, false).unwrap();
+        let mut ranges = Vec::new();
+        Decoder::new(encoding, vec![0]).match_line(line, &regexps, &mut ranges);
+        assert_eq!(ranges, expected);
+    }
+
+    #[test]
+    fn test_match_line_keeps_plain_matches() {
+        // with the patterns "secret" and "password", "password" has id 0
+        let regexps =
+            compile_patterns(&["secret".into(), "password".into()], false, false).unwrap();
+        let line = "secret c2VjcmV0IHBhc3N3b3Jk";
+        let mut ranges = Vec::new();
+        match_line_into(line, &regexps, &MatchOptions::default(), &mut ranges);
+        Decoder::new(Encoding::Base64, vec![0, 1]).match_line(line, &regexps, &mut ranges);
+        // the decoded token is matched by both, and gets the color of "password"
+        assert_eq!(ranges, vec![r(0, 6, 1), r(7, 27, 0)]);
+    }
+}
@@ -0,0 +1,57 @@
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Errors reported by colorexp.
+#[derive(Debug, Error)]
+pub enum ColorexpError {
+    /// A pattern could not be compiled into a regular expression.
+    #[error("invalid pattern `{pattern}`")]
+    InvalidPattern {
+        pattern: String,
+        #[source]
+        source: regex::Error,
+    },
+
+    /// A color specification could not be parsed.
+    #[error("invalid color `{spec}`: {reason}")]
+    BadColorSpec { spec: String, reason: String },
+
+    /// An alias given by --define could not be parsed.
+    #[error("invalid alias `{spec}`: {reason}")]
+    BadAliasSpec { spec: String, reason: String },
+
+    /// The brackets given by --balanced could not be parsed.
+    #[error("invalid brackets `{spec}`: {reason}")]
+    BadBracketSpec { spec: String, reason: String },
+
+    /// A template given by --rewrite could not be used for its pattern.
+    #[error("invalid template `{template}`: {reason}")]
+    BadRewriteTemplate { template: String, reason: String },
+
+    /// A pattern references an alias that is not defined.
+    #[error("unknown alias `{name}` in pattern `{pattern}`")]
+    UnknownAlias { name: String, pattern: String },
+
+    /// Reading from or writing to a file failed.
+    #[error("I/O error on `{}`", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    /// A command given by --filter-command or --unsafe-replace could not be run.
+    #[error("failed to run command `{command}`")]
+    Command {
+        command: String,
+        #[source]
+        source: io::Error,
+    },
+
+    /// The given options cannot be used together.
+    #[error("invalid argument combination: {0}")]
+    InvalidArgumentCombination(String),
+}
+
+pub type Result<T> = std::result::Result<T, ColorexpError>;
@@ -0,0 +1,319 @@
+use crate::RangeWithId;
+use unicode_width::UnicodeWidthChar;
+
+/// sequence_len returns the length in bytes of the escape sequence at the start of s, or None if s
+/// does not start with a complete escape sequence. Recognized are CSI sequences like `ESC[31m`, OSC
+/// sequences like `ESC]8;;url ESC\`, terminated by BEL or ST, and two byte sequences like `ESC7`.
+pub fn sequence_len(s: &str) -> Option<usize> {
+    let bytes = s.as_bytes();
+    if bytes.first() != Some(&0x1b) {
+        return None;
+    }
+    match bytes.get(1)? {
+        b'[' => {
+            // parameter and intermediate bytes, followed by the final byte
+            let end = bytes[2..].iter().position(|b| !(0x20..=0x3f).contains(b))? + 2;
+            (0x40..=0x7e).contains(&bytes[end]).then_some(end + 1)
+        }
+        b']' => {
+            let body = &bytes[2..];
+            body.iter().enumerate().find_map(|(i, b)| match b {
+                0x07 => Some(i + 3),
+                0x1b if body.get(i + 1) == Some(&b'\\') => Some(i + 4),
+                _ => None,
+            })
+        }
+        0x20..=0x7e => Some(2),
+        _ => None,
+    }
+}
+
+/// malformed_len returns the length of the malformed or incomplete escape sequence at the start of
+/// s, which starts with ESC, but not with a complete sequence: the ESC and the parameter bytes of a
+/// CSI sequence, or all of an unterminated OSC sequence.
+fn malformed_len(s: &str) -> usize {
+    let bytes = s.as_bytes();
+    match bytes.get(1) {
+        Some(b'[') => {
+            2 + bytes[2..]
+                .iter()
+                .take_while(|b| (0x20..=0x3f).contains(*b))
+                .count()
+        }
+        Some(b']') => s.len(),
+        _ => 1,
+    }
+}
+
+/// mark_malformed returns the line with the ESC of every malformed or incomplete escape sequence
+/// written as `\e`, so that it shows, together with ranges with the given id for those sequences.
+/// Complete sequences are left intact. None is returned if there are no malformed sequences.
+pub fn mark_malformed(line: &str, id: usize) -> Option<(String, Vec<RangeWithId>)> {
+    if !line.contains('\x1b') {
+        return None;
+    }
+    let mut marked = String::with_capacity(line.len() + 2);
+    let mut ranges = Vec::new();
+    let mut rest = line;
+    while let Some(esc_idx) = rest.find('\x1b') {
+        marked.push_str(&rest[..esc_idx]);
+        rest = &rest[esc_idx..];
+        let len = match sequence_len(rest) {
+            Some(len) => {
+                marked.push_str(&rest[..len]);
+                len
+            }
+            None => {
+                let len = malformed_len(rest);
+                let start_idx = marked.len();
+                marked.push_str("\\e");
+                marked.push_str(&rest[1..len]);
+                ranges.push(RangeWithId {
+                    start_idx,
+                    end_idx: marked.len(),
+                    id,
+                });
+                len
+            }
+        };
+        rest = &rest[len..];
+    }
+    marked.push_str(rest);
+    (!ranges.is_empty()).then_some((marked, ranges))
+}
+
+/// strip_sequences returns s without its escape sequences.
+pub fn strip_sequences(s: &str) -> String {
+    let mut stripped = String::with_capacity(s.len());
+    let mut idx = 0;
+    while idx < s.len() {
+        if let Some(len) = sequence_len(&s[idx..]) {
+            idx += len;
+            continue;
+        }
+        let c = s[idx..].chars().next().unwrap();
+        stripped.push(c);
+        idx += c.len_utf8();
+    }
+    stripped
+}
+
+/// sanitize makes the escape sequences of untrusted input harmless for --sanitize, by writing ESC
+/// as `\e`, like encode does, and the other C1 control characters, of which terminals take e.g.
+/// U+009B for the start of a CSI sequence, like `\x9b`. This way, input cannot control the
+/// terminal, e.g. clear the screen, change its title or hide text, while the sequences still
+/// show. Unlike encode, backslashes are kept, as there is no need to decode the result.
+pub fn sanitize(line: &str) -> String {
+    let mut sanitized = String::with_capacity(line.len());
+    for c in line.chars() {
+        match c {
+            '\x1b' => sanitized.push_str("\\e"),
+            '\u{80}'..='\u{9f}' => sanitized.push_str(&format!("\\x{:02x}", c as u32)),
+            c => sanitized.push(c),
+        }
+    }
+    sanitized
+}
+
+/// visible_width returns the number of terminal columns taken up by s, not counting escape
+/// sequences.
+pub fn visible_width(s: &str) -> usize {
+    let mut width = 0;
+    let mut idx = 0;
+    while idx < s.len() {
+        if let Some(len) = sequence_len(&s[idx..]) {
+            idx += len;
+            continue;
+        }
+        let c = s[idx..].chars().next().unwrap();
+        width += c.width().unwrap_or(0);
+        idx += c.len_utf8();
+    }
+    width
+}
+
+/// byte_offset_of_column returns the byte offset in s of the first character starting at or after
+/// the given visible column, skipping escape sequences, which take up no columns. If the line is
+/// not that wide, the length of s is returned.
+pub fn byte_offset_of_column(s: &str, column: usize) -> usize {
+    let mut width = 0;
+    let mut idx = 0;
+    while idx < s.len() {
+        if let Some(len) = sequence_len(&s[idx..]) {
+            idx += len;
+            continue;
+        }
+        if width >= column {
+            return idx;
+        }
+        let c = s[idx..].chars().next().unwrap();
+        width += c.width().unwrap_or(0);
+        idx += c.len_utf8();
+    }
+    s.len()
+}
+
+/// encode makes escape sequences visible by replacing the ESC character with `\e`. Backslashes are
+/// doubled, so that the encoding can be reversed by decode.
+pub fn encode(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => encoded.push_str("\\\\"),
+            '\x1b' => encoded.push_str("\\e"),
+            c => encoded.push(c),
+        }
+    }
+    encoded
+}
+
+/// decode reverses encode. Backslashes not followed by `\` or `e` are kept as they are.
+pub fn decode(s: &str) -> String {
+    let mut decoded = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.peek() {
+                Some('\\') => {
+                    chars.next();
+                    decoded.push('\\');
+                    continue;
+                }
+                Some('e') => {
+                    chars.next();
+                    decoded.push('\x1b');
+                    continue;
+                }
+                _ => {}
+            }
+        }
+        decoded.push(c);
+    }
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::plain("abc", "abc")]
+    #[case::colored("\x1b[31mabc\x1b[0m", "\\e[31mabc\\e[0m")]
+    #[case::backslashes("a\\e\\\\b", "a\\\\e\\\\\\\\b")]
+    fn test_encode(#[case] s: &str, #[case] expected: &str) {
+        assert_eq!(encode(s), expected);
+    }
+
+    #[rstest]
+    #[case::unknown_escape("a\\nb", "a\\nb")]
+    #[case::trailing_backslash("ab\\", "ab\\")]
+    fn test_decode(#[case] s: &str, #[case] expected: &str) {
+        assert_eq!(decode(s), expected);
+    }
+
+    #[rstest]
+    #[case::csi("\x1b[31mabc", Some(5))]
+    #[case::csi_with_params("\x1b[38;5;208m", Some(11))]
+    #[case::csi_private("\x1b[?25l", Some(6))]
+    #[case::osc_bel("\x1b]0;title\x07x", Some(10))]
+    #[case::osc_st("\x1b]8;;http://x\x1b\\x", Some(15))]
+    #[case::two_bytes("\x1b7", Some(2))]
+    #[case::truncated_csi("\x1b[31", None)]
+    #[case::unterminated_osc("\x1b]8;;http://x", None)]
+    #[case::lone_esc("\x1b", None)]
+    #[case::no_escape("abc", None)]
+    fn test_sequence_len(#[case] s: &str, #[case] expected: Option<usize>) {
+        assert_eq!(sequence_len(s), expected);
+    }
+
+    #[rstest]
+    #[case::truncated_csi("a\x1b[31", "a\\e[31", vec![(1, 6)])]
+    #[case::invalid_final_byte("\x1b[3\x01x", "\\e[3\x01x", vec![(0, 4)])]
+    #[case::unterminated_osc("\x1b]8;;http://x", "\\e]8;;http://x", vec![(0, 14)])]
+    #[case::lone_esc_at_end("ab\x1b", "ab\\e", vec![(2, 4)])]
+    #[case::valid_kept(
+        "\x1b[31mok\x1b[0m \x1b[1",
+        "\x1b[31mok\x1b[0m \\e[1",
+        vec![(12, 16)]
+    )]
+    fn test_mark_malformed(
+        #[case] line: &str,
+        #[case] expected_line: &str,
+        #[case] expected_ranges: Vec<(usize, usize)>,
+    ) {
+        let (marked, ranges) = mark_malformed(line, 7).unwrap();
+        assert_eq!(marked, expected_line);
+        let expected_ranges = expected_ranges
+            .into_iter()
+            .map(|(start_idx, end_idx)| RangeWithId {
+                start_idx,
+                end_idx,
+                id: 7,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(ranges, expected_ranges);
+    }
+
+    #[rstest]
+    #[case::plain("abc")]
+    #[case::valid("\x1b[31mabc\x1b[0m\x1b]0;t\x07\x1b7")]
+    fn test_mark_malformed_none(#[case] line: &str) {
+        assert_eq!(mark_malformed(line, 0), None);
+    }
+
+    #[rstest]
+    #[case::plain("abc", 3)]
+    #[case::colored("\x1b[31mab\x1b[0mc", 3)]
+    #[case::wide("漢字", 4)]
+    fn test_visible_width(#[case] s: &str, #[case] expected: usize) {
+        assert_eq!(visible_width(s), expected);
+    }
+
+    #[rstest]
+    #[case::plain("abc", "abc")]
+    #[case::colored("\x1b[31mab\x1b[0mc", "abc")]
+    #[case::hyperlink("\x1b]8;;https://x\x1b\\a\x1b]8;;\x1b\\", "a")]
+    #[case::incomplete("a\x1b[3", "a\x1b[3")]
+    fn test_strip_sequences(#[case] s: &str, #[case] expected: &str) {
+        assert_eq!(strip_sequences(s), expected);
+    }
+
+    #[rstest]
+    #[case::plain("a\\b", "a\\b")]
+    #[case::clear_screen("x\x1b[2Jy", "x\\e[2Jy")]
+    #[case::title("\x1b]0;owned\x07", "\\e]0;owned\x07")]
+    #[case::c1_csi("\u{9b}31m", "\\x9b31m")]
+    #[case::unicode("café", "café")]
+    fn test_sanitize(#[case] line: &str, #[case] expected: &str) {
+        let sanitized = sanitize(line);
+        assert_eq!(sanitized, expected);
+        assert_eq!(strip_sequences(&sanitized), sanitized);
+    }
+
+    #[rstest]
+    #[case::plain("abcd", 2, 2)]
+    #[case::beyond_end("ab", 5, 2)]
+    #[case::start("\x1b[31mab", 0, 5)]
+    #[case::after_escape("a\x1b[31mbc", 1, 6)]
+    #[case::between_escapes("\x1b[31ma\x1b[0m\x1b[32mb", 1, 15)]
+    #[case::multibyte("äöü", 2, 4)]
+    #[case::wide("漢字x", 1, 3)]
+    #[case::wide_second("漢字x", 4, 6)]
+    fn test_byte_offset_of_column(#[case] s: &str, #[case] column: usize, #[case] expected: usize) {
+        assert_eq!(byte_offset_of_column(s, column), expected);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let regexps = crate::compile_patterns(&["b+".into()], false, false).unwrap();
+        let colors = [crate::ColorSet::new("\x1b[31m", crate::RESET_FOREGROUND)];
+        let mut line = "a\\ebb c".to_string();
+        let ranges = crate::match_line(&line, &regexps, &crate::MatchOptions::default());
+        crate::colorize(&mut line, &colors, &[], &ranges, 1);
+
+        let encoded = encode(&line);
+        assert_eq!(encoded, "a\\\\e\\e[31mbb\\e[0m c");
+        assert_eq!(decode(&encoded), line);
+    }
+}
@@ -0,0 +1,120 @@
+use crate::{MatchOptions, RangeWithId, group_id, regexp_ids};
+use regex::Regex;
+
+/// has_named_groups returns whether any of the regexps has a named capturing group, without which
+/// extract finds no fields.
+pub fn has_named_groups(regexps: &[Regex]) -> bool {
+    regexps
+        .iter()
+        .any(|re| re.capture_names().any(|name| name.is_some()))
+}
+
+/// extract returns the fields of the line for --extract: the texts of the named capturing groups
+/// of the first match of every regexp, separated by tabs, with the ranges coloring every field
+/// like match_line colors its group. The fields are in the order of the patterns, which is the
+/// reverse of the order of the regexps. Regexps that do not match, and groups that do not
+/// participate in a match, give empty fields, so that the columns line up. Lines that no regexp
+/// matches have no fields.
+pub fn extract(
+    line: &str,
+    regexps: &[Regex],
+    options: &MatchOptions,
+) -> Option<(String, Vec<RangeWithId>)> {
+    let ids = regexp_ids(regexps, options);
+    let mut fields = String::new();
+    let mut ranges = Vec::new();
+    let mut columns = 0;
+    let mut matched = false;
+    for (re, ids) in regexps.iter().zip(&ids).rev() {
+        let caps = re.captures(line);
+        matched |= caps.is_some();
+        for (g_idx, name) in re.capture_names().enumerate() {
+            if name.is_none() {
+                continue;
+            }
+            if columns > 0 {
+                fields.push('\t');
+            }
+            columns += 1;
+            if let Some(text) = caps.as_ref().and_then(|caps| caps.get(g_idx))
+                && !text.is_empty()
+            {
+                let start_idx = fields.len();
+                fields.push_str(text.as_str());
+                ranges.push(RangeWithId {
+                    start_idx,
+                    end_idx: fields.len(),
+                    id: group_id(ids, g_idx),
+                });
+            }
+        }
+    }
+    matched.then_some((fields, ranges))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compile_patterns, match_line};
+
+    fn r(start_idx: usize, end_idx: usize, id: usize) -> RangeWithId {
+        RangeWithId {
+            start_idx,
+            end_idx,
+            id,
+        }
+    }
+
+    fn regexps(patterns: &[&str]) -> Vec<Regex> {
+        let patterns = patterns.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        compile_patterns(&patterns, false, false).unwrap()
+    }
+
+    fn vary() -> MatchOptions {
+        MatchOptions {
+            vary_group_colors: true,
+            ..MatchOptions::default()
+        }
+    }
+
+    #[test]
+    fn test_extract_two_groups() {
+        let regexps = regexps(&[r"user=(?<user>\w+) took (?<ms>\d+)ms"]);
+        let (fields, ranges) = extract("at 10:00 user=ann took 42ms", &regexps, &vary()).unwrap();
+        assert_eq!(fields, "ann\t42");
+        // every field gets the color of its group, like when highlighting the match
+        let highlighted = match_line("user=ann took 42ms", &regexps, &vary());
+        assert_eq!(
+            ranges,
+            vec![r(0, 3, highlighted[0].id), r(4, 6, highlighted[1].id)]
+        );
+        assert_ne!(ranges[0].id, ranges[1].id);
+    }
+
+    #[test]
+    fn test_extract_several_patterns() {
+        let regexps = regexps(&[r"(?<level>[A-Z]+):", r"(unnamed) id=(?<id>\d+)"]);
+        let (fields, ranges) = extract("ERROR: unnamed id=7", &regexps, &vary()).unwrap();
+        assert_eq!(fields, "ERROR\t7");
+        assert_eq!(ranges, vec![r(0, 5, 2), r(6, 7, 0)]);
+        // a pattern without a match gives an empty field
+        let (fields, ranges) = extract("unnamed id=7", &regexps, &vary()).unwrap();
+        assert_eq!(fields, "\t7");
+        assert_eq!(ranges, vec![r(1, 2, 0)]);
+        let (fields, ranges) = extract("INFO: done", &regexps, &vary()).unwrap();
+        assert_eq!(fields, "INFO\t");
+        assert_eq!(ranges, vec![r(0, 4, 2)]);
+    }
+
+    #[test]
+    fn test_extract_without_match() {
+        let regexps = regexps(&[r"(?<n>\d+)"]);
+        assert_eq!(extract("no digits", &regexps, &vary()), None);
+    }
+
+    #[test]
+    fn test_has_named_groups() {
+        assert!(has_named_groups(&regexps(&["a", "(?<n>b)"])));
+        assert!(!has_named_groups(&regexps(&["a", "(b)"])));
+    }
+}
@@ -0,0 +1,222 @@
+use crate::{ColorexpError, RangeWithId, Result};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::process::{Command, Output, Stdio};
+use std::thread;
+
+/// FilterCommand decides whether to highlight a match by running a shell command with the matched
+/// text on its stdin: the match is highlighted if the command succeeds. Results are cached by the
+/// matched text, as running a command per match is slow.
+pub struct FilterCommand {
+    command: String,
+    cache: HashMap<String, bool>,
+}
+
+impl FilterCommand {
+    pub fn new(command: impl Into<String>) -> Self {
+        Self {
+            command: command.into(),
+            cache: HashMap::new(),
+        }
+    }
+
+    /// accepts returns whether the command succeeds for the text.
+    pub fn accepts(&mut self, text: &str) -> Result<bool> {
+        if let Some(&accepted) = self.cache.get(text) {
+            return Ok(accepted);
+        }
+        let accepted = self.run(text).map_err(|source| ColorexpError::Command {
+            command: self.command.clone(),
+            source,
+        })?;
+        self.cache.insert(text.to_string(), accepted);
+        Ok(accepted)
+    }
+
+    fn run(&self, text: &str) -> io::Result<bool> {
+        Ok(run_command(&self.command, text, Stdio::null())?
+            .status
+            .success())
+    }
+
+    /// retain_accepted removes the ranges whose text the command does not accept.
+    pub fn retain_accepted(&mut self, line: &str, ranges: &mut Vec<RangeWithId>) -> Result<()> {
+        let mut accepted = Vec::with_capacity(ranges.len());
+        for r in ranges.iter() {
+            if self.accepts(&line[r.start_idx..r.end_idx])? {
+                accepted.push(*r);
+            }
+        }
+        *ranges = accepted;
+        Ok(())
+    }
+}
+
+/// ReplaceCommand replaces matches by the output of a shell command run with the matched text on
+/// its stdin, without a trailing newline. If the command fails, the match is kept as it is. Results
+/// are cached by the matched text.
+pub struct ReplaceCommand {
+    command: String,
+    cache: HashMap<String, String>,
+}
+
+impl ReplaceCommand {
+    pub fn new(command: impl Into<String>) -> Self {
+        Self {
+            command: command.into(),
+            cache: HashMap::new(),
+        }
+    }
+
+    /// replacement returns the text to replace the matched text with.
+    pub fn replacement(&mut self, text: &str) -> Result<&str> {
+        if !self.cache.contains_key(text) {
+            let output = run_command(&self.command, text, Stdio::piped()).map_err(|source| {
+                ColorexpError::Command {
+                    command: self.command.clone(),
+                    source,
+                }
+            })?;
+            let replacement = if output.status.success() {
+                let stdout = String::from_utf8_lossy(&output.stdout);
+                let stdout = stdout.strip_suffix('\n').unwrap_or(&stdout);
+                stdout.strip_suffix('\r').unwrap_or(stdout).to_string()
+            } else {
+                text.to_string()
+            };
+            self.cache.insert(text.to_string(), replacement);
+        }
+        Ok(&self.cache[text])
+    }
+
+    /// replace_ranges replaces the text of the ranges with ids below pattern_color_count in the
+    /// line, and moves all ranges accordingly.
+    pub fn replace_ranges(
+        &mut self,
+        line: &mut String,
+        ranges: &mut [RangeWithId],
+        pattern_color_count: usize,
+    ) -> Result<()> {
+        let mut replaced = String::with_capacity(line.len());
+        let mut copied = 0;
+        for r in ranges.iter_mut() {
+            replaced.push_str(&line[copied..r.start_idx]);
+            let start_idx = replaced.len();
+            let text = &line[r.start_idx..r.end_idx];
+            if r.id < pattern_color_count {
+                replaced.push_str(self.replacement(text)?);
+            } else {
+                replaced.push_str(text);
+            }
+            copied = r.end_idx;
+            r.start_idx = start_idx;
+            r.end_idx = replaced.len();
+        }
+        replaced.push_str(&line[copied..]);
+        *line = replaced;
+        Ok(())
+    }
+}
+
+// run_command runs the command with sh, writing the text to its stdin. The text is written by
+// another thread while the output is read, as a command may not read all of its input before the
+// pipe of its output is full
+fn run_command(command: &str, text: &str, stdout: Stdio) -> io::Result<Output> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(stdout)
+        .spawn()?;
+    let Some(mut stdin) = child.stdin.take() else {
+        return child.wait_with_output();
+    };
+    thread::scope(|scope| {
+        let writer = scope.spawn(move || match stdin.write_all(text.as_bytes()) {
+            // commands may exit without reading their input
+            Err(err) if err.kind() != io::ErrorKind::BrokenPipe => Err(err),
+            _ => Ok(()),
+        });
+        let output = child.wait_with_output();
+        writer.join().expect("writing to the command panicked")?;
+        output
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn r(start_idx: usize, end_idx: usize, id: usize) -> RangeWithId {
+        RangeWithId {
+            start_idx,
+            end_idx,
+            id,
+        }
+    }
+
+    #[rstest]
+    #[case::succeeding("true", vec![r(0, 3, 0), r(4, 7, 1)])]
+    #[case::failing("false", vec![])]
+    #[case::reading_stdin("grep -q o", vec![r(0, 3, 0)])]
+    fn test_retain_accepted(#[case] command: &str, #[case] expected: Vec<RangeWithId>) {
+        let mut filter = FilterCommand::new(command);
+        let mut ranges = vec![r(0, 3, 0), r(4, 7, 1)];
+        filter.retain_accepted("foo bar", &mut ranges).unwrap();
+        assert_eq!(ranges, expected);
+    }
+
+    #[test]
+    fn test_accepts_caches_results() {
+        // the command succeeds the first time only, as it creates the file it checks for
+        let path = std::env::temp_dir().join(format!("colorexp-{}-filter", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let command = format!("test ! -e '{0}' && touch '{0}'", path.display());
+        let mut filter = FilterCommand::new(command);
+        assert!(filter.accepts("x").unwrap());
+        assert!(filter.accepts("x").unwrap());
+        assert!(!filter.accepts("y").unwrap());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_replace_ranges() {
+        let mut replace = ReplaceCommand::new("tr a-z A-Z");
+        let mut line = "a foo and a barbaz".to_string();
+        // the last range is not one of the patterns, e.g. of --kv
+        let mut ranges = vec![r(2, 5, 0), r(12, 15, 1), r(15, 18, 2)];
+        replace.replace_ranges(&mut line, &mut ranges, 2).unwrap();
+        assert_eq!(line, "a FOO and a BARbaz");
+        assert_eq!(ranges, vec![r(2, 5, 0), r(12, 15, 1), r(15, 18, 2)]);
+    }
+
+    #[test]
+    fn test_replace_ranges_bigger_than_pipe() {
+        let mut replace = ReplaceCommand::new("cat");
+        let text = "x".repeat(200_000);
+        let mut line = text.clone();
+        let mut ranges = vec![r(0, text.len(), 0)];
+        replace.replace_ranges(&mut line, &mut ranges, 1).unwrap();
+        assert_eq!(line, text);
+    }
+
+    #[rstest]
+    #[case::longer("printf '<%s>' \"$(cat)\"", "x_y", "x_<y>")]
+    #[case::shorter("printf ''", "x_y", "x_")]
+    #[case::failing("exit 1", "x_y", "x_y")]
+    #[case::trailing_newline("cat; echo", "x_y", "x_y")]
+    fn test_replace_ranges_lengths(
+        #[case] command: &str,
+        #[case] line: &str,
+        #[case] expected: &str,
+    ) {
+        let mut replace = ReplaceCommand::new(command);
+        let mut line = line.to_string();
+        let mut ranges = vec![r(0, 1, 1), r(2, 3, 0)];
+        replace.replace_ranges(&mut line, &mut ranges, 1).unwrap();
+        assert_eq!(line, expected);
+        assert_eq!(ranges[1].end_idx, line.len());
+        assert_eq!(&line[ranges[0].start_idx..ranges[0].end_idx], "x");
+    }
+}
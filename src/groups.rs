@@ -0,0 +1,103 @@
+use regex_syntax::ast::{Ast, GroupKind, parse::Parser};
+
+/// limit_group_depth turns the capturing groups of the pattern that are nested deeper than
+/// max_depth into non-capturing groups, so that they are not colorized. Outermost groups have a
+/// depth of 1. Patterns that cannot be parsed are returned unchanged, leaving the error reporting
+/// to the regex compilation.
+pub fn limit_group_depth(pattern: &str, max_depth: usize) -> String {
+    let Ok(ast) = Parser::new().parse(pattern) else {
+        return pattern.to_string();
+    };
+    let mut group_openings = Vec::new();
+    collect_deep_groups(&ast, 0, max_depth, &mut group_openings);
+    group_openings.sort_unstable();
+
+    let mut limited = String::with_capacity(pattern.len() + group_openings.len() * 2);
+    let mut last_idx = 0;
+    for (start, end) in group_openings {
+        limited.push_str(&pattern[last_idx..start]);
+        limited.push_str("(?:");
+        last_idx = end;
+    }
+    limited.push_str(&pattern[last_idx..]);
+    limited
+}
+
+/// collect_deep_groups collects the byte ranges of the openings, e.g. `(` or `(?<name>`, of the
+/// capturing groups nested deeper than max_depth.
+fn collect_deep_groups(
+    ast: &Ast,
+    depth: usize,
+    max_depth: usize,
+    group_openings: &mut Vec<(usize, usize)>,
+) {
+    match ast {
+        Ast::Group(group) => {
+            let mut depth = depth;
+            if !matches!(group.kind, GroupKind::NonCapturing(_)) {
+                depth += 1;
+                if depth > max_depth {
+                    group_openings.push((group.span.start.offset, group.ast.span().start.offset));
+                }
+            }
+            collect_deep_groups(&group.ast, depth, max_depth, group_openings);
+        }
+        Ast::Repetition(repetition) => {
+            collect_deep_groups(&repetition.ast, depth, max_depth, group_openings)
+        }
+        Ast::Alternation(alternation) => {
+            for ast in &alternation.asts {
+                collect_deep_groups(ast, depth, max_depth, group_openings);
+            }
+        }
+        Ast::Concat(concat) => {
+            for ast in &concat.asts {
+                collect_deep_groups(ast, depth, max_depth, group_openings);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MatchOptions, RangeWithId, compile_patterns, match_line};
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::nested("((a)(b))", 1, "((?:a)(?:b))")]
+    #[case::deep_enough("((a)(b))", 2, "((a)(b))")]
+    #[case::no_groups("((a)(b))", 0, "(?:(?:a)(?:b))")]
+    #[case::named("(?<x>(?P<y>a)b)", 1, "(?<x>(?:a)b)")]
+    #[case::non_capturing("(?:(a))(?i:(b))", 1, "(?:(a))(?i:(b))")]
+    #[case::repetition("((a)+|(b)*)", 1, "((?:a)+|(?:b)*)")]
+    #[case::escaped_parens(r"\((a)\)", 0, r"\((?:a)\)")]
+    #[case::class("[(](a)", 0, "[(](?:a)")]
+    #[case::invalid("((a)", 0, "((a)")]
+    fn test_limit_group_depth(
+        #[case] pattern: &str,
+        #[case] max_depth: usize,
+        #[case] expected: &str,
+    ) {
+        assert_eq!(limit_group_depth(pattern, max_depth), expected);
+    }
+
+    #[test]
+    fn test_match_line_group_depth() {
+        let pattern = limit_group_depth("((a)(b))", 1);
+        let regexps = compile_patterns(&[pattern], false, false).unwrap();
+        let options = MatchOptions {
+            vary_group_colors: true,
+            ..MatchOptions::default()
+        };
+        assert_eq!(
+            match_line("xab", &regexps, &options),
+            vec![RangeWithId {
+                start_idx: 1,
+                end_idx: 3,
+                id: 0
+            }]
+        );
+    }
+}
@@ -0,0 +1,155 @@
+use crate::palette::Markup;
+use crate::{ColorSet, RESET_BACKGROUND, RangeWithId, add_range};
+use regex::Regex;
+
+/// the number of colors the gradient is divided into
+pub const STEPS: usize = 32;
+
+// blue, cyan, green, yellow, red
+const GRADIENT: [(u8, u8, u8); 5] = [
+    (0, 0, 255),
+    (0, 255, 255),
+    (0, 255, 0),
+    (255, 255, 0),
+    (255, 0, 0),
+];
+
+/// gradient_color returns the color at position t of the cold-to-hot gradient, from 0 (blue) to 1
+/// (red). Positions outside of that range are clamped.
+pub fn gradient_color(t: f64) -> (u8, u8, u8) {
+    let pos = gradient_pos(t) * (GRADIENT.len() - 1) as f64;
+    let idx = (pos as usize).min(GRADIENT.len() - 2);
+    let frac = pos - idx as f64;
+    let (from, to) = (GRADIENT[idx], GRADIENT[idx + 1]);
+    let mix = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * frac).round() as u8;
+    (mix(from.0, to.0), mix(from.1, to.1), mix(from.2, to.2))
+}
+
+/// heatmap_colors returns the STEPS background colors of the gradient as 24-bit escape sequences,
+/// or as markup tags like `[[bg-#0000ff]]` if markup is given.
+pub fn heatmap_colors(markup: Option<&Markup>) -> Vec<ColorSet> {
+    (0..STEPS)
+        .map(|step| {
+            let (r, g, b) = gradient_color(step as f64 / (STEPS - 1) as f64);
+            match markup {
+                Some(Markup { open, close }) => ColorSet::new(
+                    format!("{open}bg-#{r:02x}{g:02x}{b:02x}{close}"),
+                    format!("{open}/{close}"),
+                ),
+                None => ColorSet::new(format!("\x1b[48;2;{r};{g};{b}m"), RESET_BACKGROUND),
+            }
+        })
+        .collect()
+}
+
+/// heat_step returns the step of the gradient for the value in the range from min to max.
+pub fn heat_step(value: f64, min: f64, max: f64) -> usize {
+    let t = if max > min {
+        (value - min) / (max - min)
+    } else {
+        0.0
+    };
+    (gradient_pos(t) * (STEPS - 1) as f64).round() as usize
+}
+
+fn gradient_pos(t: f64) -> f64 {
+    if t.is_nan() { 0.0 } else { t.clamp(0.0, 1.0) }
+}
+
+/// Heatmap colors the given capturing group (a number or a name) of every match by its numeric
+/// value, using the ids first_id + heat_step(value).
+pub struct Heatmap {
+    pub group: String,
+    pub min: f64,
+    pub max: f64,
+    pub first_id: usize,
+}
+
+impl Heatmap {
+    /// match_line adds a range for the group of each match of the regexps whose text is a number.
+    /// Existing ranges take precedence.
+    pub fn match_line(&self, line: &str, regexps: &[Regex], ranges: &mut Vec<RangeWithId>) {
+        let group_idx = self.group.parse::<usize>().ok();
+        for re in regexps {
+            for caps in re.captures_iter(line) {
+                let group = match group_idx {
+                    Some(idx) => caps.get(idx),
+                    None => caps.name(&self.group),
+                };
+                let Some(group) = group else {
+                    continue;
+                };
+                let Ok(value) = group.as_str().trim().parse::<f64>() else {
+                    continue;
+                };
+                add_range(
+                    ranges,
+                    RangeWithId {
+                        start_idx: group.start(),
+                        end_idx: group.end(),
+                        id: self.first_id + heat_step(value, self.min, self.max),
+                    },
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::cold(0.0, (0, 0, 255))]
+    #[case::hot(1.0, (255, 0, 0))]
+    #[case::middle(0.5, (0, 255, 0))]
+    #[case::below(-3.0, (0, 0, 255))]
+    #[case::above(7.0, (255, 0, 0))]
+    fn test_gradient_color(#[case] t: f64, #[case] expected: (u8, u8, u8)) {
+        assert_eq!(gradient_color(t), expected);
+    }
+
+    #[rstest]
+    #[case::min(10.0, 0)]
+    #[case::max(20.0, STEPS - 1)]
+    #[case::below_min(-5.0, 0)]
+    #[case::above_max(1e9, STEPS - 1)]
+    #[case::middle(15.0, STEPS / 2)]
+    fn test_heat_step(#[case] value: f64, #[case] expected: usize) {
+        assert_eq!(heat_step(value, 10.0, 20.0), expected);
+    }
+
+    #[test]
+    fn test_heatmap_colors_endpoints() {
+        let colors = heatmap_colors(None);
+        assert_eq!(colors.len(), STEPS);
+        assert_eq!(colors[0].on, "\x1b[48;2;0;0;255m");
+        assert_eq!(colors[STEPS - 1].on, "\x1b[48;2;255;0;0m");
+        let markup = Markup {
+            open: "[[".to_string(),
+            close: "]]".to_string(),
+        };
+        assert_eq!(heatmap_colors(Some(&markup))[0].on, "[[bg-#0000ff]]");
+    }
+
+    #[rstest]
+    #[case::index("1")]
+    #[case::name("ms")]
+    fn test_heatmap_match_line(#[case] group: &str) {
+        let heatmap = Heatmap {
+            group: group.to_string(),
+            min: 0.0,
+            max: 100.0,
+            first_id: 3,
+        };
+        let regexps = vec![Regex::new(r"took (?<ms>\S+)ms").unwrap()];
+        let mut ranges = Vec::new();
+        heatmap.match_line("took 0ms took 100ms took xms", &regexps, &mut ranges);
+        let ranges = ranges
+            .iter()
+            .map(|r| (r.start_idx, r.id))
+            .collect::<Vec<_>>();
+        assert_eq!(ranges, vec![(5, 3), (14, 3 + STEPS - 1)]);
+    }
+}
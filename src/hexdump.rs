@@ -0,0 +1,220 @@
+use crate::{MatchOptions, RangeWithId, add_range, groups_to_colorize};
+use regex::Regex;
+
+/// HexdumpLine is a parsed line of `xxd` output, like
+/// `00000000: 4865 6c6c 6f0a                           Hello.`, holding the positions of every
+/// byte in both the hex and the text column.
+#[derive(Debug, Eq, PartialEq)]
+pub struct HexdumpLine {
+    /// The bytes as lowercase hex digits, without any separators.
+    pub hex: String,
+    /// The index of the first hex digit of every byte in the line.
+    pub hex_positions: Vec<usize>,
+    /// The index of the text column in the line, with one character per byte.
+    pub text_start: usize,
+}
+
+impl HexdumpLine {
+    /// parse returns None if the line is not formatted like `xxd` output.
+    pub fn parse(line: &str) -> Option<Self> {
+        let (offset, _) = line.split_once(": ")?;
+        if offset.is_empty() || !offset.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        let bytes = line.as_bytes();
+        let mut hex = String::new();
+        let mut hex_positions = Vec::new();
+        let mut i = offset.len() + 2;
+        // the hex column ends with two spaces, groups of bytes are separated by one
+        while i + 1 < bytes.len() {
+            if bytes[i].is_ascii_hexdigit() && bytes[i + 1].is_ascii_hexdigit() {
+                hex.push(bytes[i].to_ascii_lowercase() as char);
+                hex.push(bytes[i + 1].to_ascii_lowercase() as char);
+                hex_positions.push(i);
+                i += 2;
+            } else if bytes[i] == b' ' && bytes[i + 1] != b' ' {
+                i += 1;
+            } else {
+                break;
+            }
+        }
+        // the text column is right-aligned with the hex column of the last line of a dump, which
+        // is padded with spaces
+        let text_start = line.len().checked_sub(hex_positions.len())?;
+        if hex_positions.is_empty()
+            || text_start < i
+            || !line.is_char_boundary(text_start)
+            || !line[text_start..].is_ascii()
+        {
+            return None;
+        }
+        Some(Self {
+            hex,
+            hex_positions,
+            text_start,
+        })
+    }
+
+    /// add_bytes adds ranges for the bytes start_byte..end_byte to both columns.
+    fn add_bytes(
+        &self,
+        ranges: &mut Vec<RangeWithId>,
+        start_byte: usize,
+        end_byte: usize,
+        id: usize,
+    ) {
+        for byte in start_byte..end_byte {
+            let start_idx = self.hex_positions[byte];
+            add_range(
+                ranges,
+                RangeWithId {
+                    start_idx,
+                    end_idx: start_idx + 2,
+                    id,
+                },
+            );
+            add_range(
+                ranges,
+                RangeWithId {
+                    start_idx: self.text_start + byte,
+                    end_idx: self.text_start + byte + 1,
+                    id,
+                },
+            );
+        }
+    }
+}
+
+/// match_hexdump_line matches the regexps against the hex digits of the bytes of a line of `xxd`
+/// output, and highlights every matching byte in both the hex and the text column. Matches must
+/// start at a byte, and a match ending within a byte highlights the whole byte. Capturing groups
+/// are not colorized separately. Lines that are not formatted like `xxd` output get no ranges.
+pub fn match_hexdump_line(
+    line: &str,
+    regexps: &[Regex],
+    options: &MatchOptions,
+    ranges: &mut Vec<RangeWithId>,
+) {
+    ranges.clear();
+    let Some(dump) = HexdumpLine::parse(line) else {
+        return;
+    };
+    let mut color_idx = 0;
+    for re in regexps {
+        let mut start = 0;
+        while let Some(m) = re.find_at(&dump.hex, start) {
+            if m.start() % 2 == 1 || m.is_empty() {
+                // matches within a byte are skipped, but may hide a match starting at the next one
+                start = m.start() + 1;
+            } else {
+                dump.add_bytes(ranges, m.start() / 2, m.end().div_ceil(2), color_idx);
+                start = m.end();
+            }
+            if start > dump.hex.len() {
+                break;
+            }
+        }
+        color_idx += if options.vary_group_colors {
+            groups_to_colorize(re, options).1
+        } else {
+            1
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile_patterns;
+    use rstest::rstest;
+
+    const LINE: &str = "00000000: 4865 6c6c 6f2c 2077 6f72 6c64 210a 4865  Hello, world!.He";
+
+    fn r(start_idx: usize, end_idx: usize, id: usize) -> RangeWithId {
+        RangeWithId {
+            start_idx,
+            end_idx,
+            id,
+        }
+    }
+
+    #[rstest]
+    #[case::full(LINE, Some(16), Some(51))]
+    #[case::last(
+        "00000010: 6c6c 0a                                  ll.",
+        Some(3),
+        Some(51)
+    )]
+    #[case::grouped_by_byte("00000000: 48 69  Hi", Some(2), Some(17))]
+    #[case::uppercase("0000000A: 4F4B  OK", Some(2), Some(16))]
+    #[case::no_offset("4865 6c6c  Hell", None, None)]
+    #[case::no_bytes("00000000: ", None, None)]
+    #[case::plain_text("note: this is not a dump", None, None)]
+    fn test_parse(
+        #[case] line: &str,
+        #[case] byte_count: Option<usize>,
+        #[case] text_start: Option<usize>,
+    ) {
+        let dump = HexdumpLine::parse(line);
+        assert_eq!(dump.as_ref().map(|d| d.hex_positions.len()), byte_count);
+        assert_eq!(dump.as_ref().map(|d| d.text_start), text_start);
+        if let Some(dump) = dump {
+            assert_eq!(dump.hex.len(), 2 * dump.hex_positions.len());
+            assert!(dump.hex.bytes().all(|b| !b.is_ascii_uppercase()));
+        }
+    }
+
+    #[test]
+    fn test_match_hexdump_line_both_columns() {
+        // "l" is 6c
+        let regexps = compile_patterns(&["6c".into()], false, false).unwrap();
+        let mut ranges = Vec::new();
+        match_hexdump_line(LINE, &regexps, &MatchOptions::default(), &mut ranges);
+        assert_eq!(
+            ranges,
+            vec![
+                r(15, 17, 0),
+                r(17, 19, 0),
+                r(35, 37, 0),
+                r(53, 54, 0),
+                r(54, 55, 0),
+                r(61, 62, 0),
+            ]
+        );
+        assert_eq!(
+            ranges
+                .iter()
+                .map(|r| &LINE[r.start_idx..r.end_idx])
+                .collect::<Vec<_>>(),
+            vec!["6c", "6c", "6c", "l", "l", "l"]
+        );
+    }
+
+    #[rstest]
+    // "c6" occurs within the bytes 6c 6c, but does not start at a byte
+    #[case::within_byte("c6", vec![])]
+    // the match of "c6c" within the bytes 6c 6c must not hide the one of "6c6f" at the next byte
+    #[case::after_misaligned("c6c|6c6f", vec![r(17, 19, 0), r(20, 22, 0), r(54, 55, 0), r(55, 56, 0)])]
+    #[case::partial_byte("6c6", vec![
+        r(15, 17, 0), r(17, 19, 0), r(35, 37, 0), r(37, 39, 0),
+        r(53, 54, 0), r(54, 55, 0), r(61, 62, 0), r(62, 63, 0),
+    ])]
+    #[case::empty("", vec![])]
+    fn test_match_hexdump_line_alignment(
+        #[case] pattern: &str,
+        #[case] expected: Vec<RangeWithId>,
+    ) {
+        let regexps = compile_patterns(&[pattern.into()], false, false).unwrap();
+        let mut ranges = Vec::new();
+        match_hexdump_line(LINE, &regexps, &MatchOptions::default(), &mut ranges);
+        assert_eq!(ranges, expected);
+    }
+
+    #[test]
+    fn test_match_hexdump_line_not_a_dump() {
+        let regexps = compile_patterns(&["6c".into()], false, false).unwrap();
+        let mut ranges = vec![r(0, 1, 0)];
+        match_hexdump_line("6c6c", &regexps, &MatchOptions::default(), &mut ranges);
+        assert!(ranges.is_empty());
+    }
+}
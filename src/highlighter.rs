@@ -0,0 +1,520 @@
+//! A highlighter for embedding colorexp into other programs.
+//!
+//! ```
+//! use colorexp::highlighter::Highlighter;
+//!
+//! let highlighter = Highlighter::builder()
+//!     .pattern("error")
+//!     .pattern(r"\d+")
+//!     .build()?;
+//! let line = highlighter.highlight_line("error 42");
+//! assert_eq!(line, "\x1b[31merror\x1b[0m \x1b[32m42\x1b[0m");
+//! # Ok::<(), colorexp::ColorexpError>(())
+//! ```
+
+use crate::palette::{Markup, TerminalBackground, palette};
+use crate::{
+    ColorSet, MatchOptions, RangeWithId, Result, colorize, compile_patterns, count_matches,
+    match_line, match_line_filtered, pattern_color_count, vary_group_colors_by_default,
+};
+use regex::Regex;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::sync::Arc;
+
+/// ColorMode selects the kinds of colors of the palette, like --no-highlight and
+/// --only-highlight do.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum ColorMode {
+    /// Foreground colors, followed by background colors once they are used up.
+    #[default]
+    Both,
+    /// Only foreground colors.
+    Foreground,
+    /// Only background colors.
+    Background,
+}
+
+/// HighlighterBuilder configures a Highlighter, see Highlighter::builder. Everything not
+/// configured is like colorexp does it by default.
+#[derive(Clone, Debug, Default)]
+pub struct HighlighterBuilder {
+    patterns: Vec<String>,
+    fixed_strings: bool,
+    ignore_case: bool,
+    color_mode: ColorMode,
+    fg_colors: Vec<String>,
+    bg_colors: Vec<String>,
+    background: TerminalBackground,
+    vary_group_colors: Option<bool>,
+    full_match_highlight: bool,
+    markup: Option<Markup>,
+}
+
+impl HighlighterBuilder {
+    /// pattern adds a pattern, which gets the next color. Like with colorexp, the patterns given
+    /// later take precedence where matches overlap.
+    pub fn pattern(mut self, pattern: impl Into<String>) -> Self {
+        self.patterns.push(pattern.into());
+        self
+    }
+
+    /// patterns adds several patterns, see pattern.
+    pub fn patterns(mut self, patterns: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.patterns.extend(patterns.into_iter().map(Into::into));
+        self
+    }
+
+    /// fixed_strings makes the patterns match literally, instead of as regular expressions.
+    pub fn fixed_strings(mut self, fixed_strings: bool) -> Self {
+        self.fixed_strings = fixed_strings;
+        self
+    }
+
+    pub fn ignore_case(mut self, ignore_case: bool) -> Self {
+        self.ignore_case = ignore_case;
+        self
+    }
+
+    pub fn color_mode(mut self, color_mode: ColorMode) -> Self {
+        self.color_mode = color_mode;
+        self
+    }
+
+    /// fg_colors replaces the default foreground colors by colors given like to --fg-colors.
+    pub fn fg_colors(mut self, colors: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.fg_colors = colors.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// bg_colors replaces the default background colors by colors given like to --bg-colors.
+    pub fn bg_colors(mut self, colors: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.bg_colors = colors.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// background selects the default colors readable on the background of the terminal.
+    pub fn background(mut self, background: TerminalBackground) -> Self {
+        self.background = background;
+        self
+    }
+
+    /// vary_group_colors gives every capturing group a color of its own. By default, this is
+    /// done if there is exactly one pattern, with groups.
+    pub fn vary_group_colors(mut self, vary_group_colors: bool) -> Self {
+        self.vary_group_colors = Some(vary_group_colors);
+        self
+    }
+
+    /// full_match_highlight highlights all of every match, even of patterns with groups.
+    pub fn full_match_highlight(mut self, full_match_highlight: bool) -> Self {
+        self.full_match_highlight = full_match_highlight;
+        self
+    }
+
+    /// markup highlights with textual tags instead of escape sequences, see Markup.
+    pub fn markup(mut self, markup: Markup) -> Self {
+        self.markup = Some(markup);
+        self
+    }
+
+    /// build compiles the patterns and builds the palette, failing on invalid patterns or
+    /// colors.
+    pub fn build(self) -> Result<Highlighter> {
+        let regexps = compile_patterns(&self.patterns, self.fixed_strings, self.ignore_case)?;
+        let vary_group_colors = !self.full_match_highlight
+            && self
+                .vary_group_colors
+                .unwrap_or_else(|| vary_group_colors_by_default(&regexps));
+        let colors = palette(
+            &self.fg_colors,
+            &self.bg_colors,
+            self.color_mode != ColorMode::Background,
+            self.color_mode != ColorMode::Foreground,
+            self.markup.as_ref(),
+            self.background,
+        )?;
+        Ok(Highlighter {
+            pattern_color_count: pattern_color_count(&regexps, vary_group_colors),
+            regexps,
+            colors,
+            options: MatchOptions {
+                vary_group_colors,
+                full_match_highlight: self.full_match_highlight,
+                ..MatchOptions::default()
+            },
+            filter: None,
+        })
+    }
+}
+
+/// MatchInfo describes a match (or capturing group) about to be highlighted, for the filter of a
+/// Highlighter.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MatchInfo<'a> {
+    /// The highlighted text.
+    pub text: &'a str,
+    /// The index of the pattern in the order the patterns were added in.
+    pub pattern: usize,
+    /// The byte offsets of the text in the line.
+    pub start: usize,
+    pub end: usize,
+}
+
+/// ColorSpec is the color a filter gives a match.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ColorSpec {
+    /// The color of the pattern (or group).
+    Default,
+    /// A color of its own, like one returned by palette::color_set.
+    Color(ColorSet),
+}
+
+/// Stats counts what Highlighter::process read.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Stats {
+    pub lines: usize,
+    /// The lines with anything highlighted.
+    pub matching_lines: usize,
+    /// The matches of every pattern, in the order the patterns were added in, whether the filter
+    /// has them highlighted or not, like --stats counts them.
+    pub match_counts: Vec<usize>,
+}
+
+impl Stats {
+    /// matches returns the matches of all patterns.
+    pub fn matches(&self) -> usize {
+        self.match_counts.iter().sum()
+    }
+}
+
+type Filter = dyn Fn(&MatchInfo) -> Option<ColorSpec> + Send + Sync;
+
+/// Highlighter highlights lines like colorexp does, with patterns compiled and colors picked
+/// once, to be reused for any number of lines.
+///
+/// ```
+/// use colorexp::highlighter::{ColorMode, Highlighter};
+///
+/// let highlighter = Highlighter::builder()
+///     .pattern("warn(?:ing)?")
+///     .color_mode(ColorMode::Background)
+///     .ignore_case(true)
+///     .build()?;
+/// assert_eq!(
+///     highlighter.highlight_line("a WARNING"),
+///     "a \x1b[41mWARNING\x1b[49m"
+/// );
+/// # Ok::<(), colorexp::ColorexpError>(())
+/// ```
+#[derive(Clone)]
+pub struct Highlighter {
+    regexps: Vec<Regex>,
+    colors: Vec<ColorSet>,
+    options: MatchOptions,
+    pattern_color_count: usize,
+    filter: Option<Arc<Filter>>,
+}
+
+impl fmt::Debug for Highlighter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Highlighter")
+            .field("regexps", &self.regexps)
+            .field("colors", &self.colors)
+            .field("options", &self.options)
+            .field("pattern_color_count", &self.pattern_color_count)
+            .field("filter", &self.filter.is_some())
+            .finish()
+    }
+}
+
+impl Highlighter {
+    pub fn builder() -> HighlighterBuilder {
+        HighlighterBuilder::default()
+    }
+
+    /// with_filter sets a filter deciding for every match (or capturing group) whether and how
+    /// it is highlighted: not at all if the filter returns None, and otherwise in the color it
+    /// returns. The filter sees whole matches, before overlapping ones are cut, and where it
+    /// rejects a match, the matches of patterns it takes precedence over are highlighted instead.
+    /// E.g. to only highlight numbers above a threshold:
+    ///
+    /// ```
+    /// use colorexp::highlighter::{ColorSpec, Highlighter};
+    ///
+    /// let highlighter = Highlighter::builder()
+    ///     .pattern(r"\d+ms")
+    ///     .build()?
+    ///     .with_filter(|m| {
+    ///         let ms = m.text.trim_end_matches("ms").parse::<u32>().ok()?;
+    ///         (ms > 100).then_some(ColorSpec::Default)
+    ///     });
+    /// assert_eq!(
+    ///     highlighter.highlight_line("5ms 500ms"),
+    ///     "5ms \x1b[31m500ms\x1b[0m"
+    /// );
+    /// # Ok::<(), colorexp::ColorexpError>(())
+    /// ```
+    pub fn with_filter(
+        mut self,
+        filter: impl Fn(&MatchInfo) -> Option<ColorSpec> + Send + Sync + 'static,
+    ) -> Self {
+        self.filter = Some(Arc::new(filter));
+        self
+    }
+
+    /// highlight_line returns the line with its matches highlighted. The line must not contain
+    /// its terminator, as a newline would be highlighted by patterns like `.*`.
+    pub fn highlight_line(&self, line: &str) -> String {
+        let (ranges, filter_colors) = self.ranges(line);
+        let mut highlighted = line.to_string();
+        colorize(
+            &mut highlighted,
+            &self.colors,
+            &filter_colors,
+            &ranges,
+            self.pattern_color_count,
+        );
+        highlighted
+    }
+
+    /// process highlights the lines of the input like highlight_line, and writes them to the
+    /// output, with their terminators, until the end of the input. Input that is not UTF-8 is
+    /// decoded lossily.
+    pub fn process<R: BufRead, W: Write>(&self, mut input: R, mut output: W) -> io::Result<Stats> {
+        let mut stats = Stats {
+            match_counts: vec![0; self.regexps.len()],
+            ..Stats::default()
+        };
+        let mut buf = Vec::new();
+        while input.read_until(b'\n', &mut buf)? > 0 {
+            let line = String::from_utf8_lossy(&buf);
+            let content = line.trim_end_matches(['\n', '\r']);
+            let terminator = &line[content.len()..];
+            let (ranges, filter_colors) = self.ranges(content);
+            stats.lines += 1;
+            if !ranges.is_empty() {
+                stats.matching_lines += 1;
+            }
+            count_matches(content, &self.regexps, &mut stats.match_counts);
+            let mut highlighted = content.to_string();
+            colorize(
+                &mut highlighted,
+                &self.colors,
+                &filter_colors,
+                &ranges,
+                self.pattern_color_count,
+            );
+            output.write_all(highlighted.as_bytes())?;
+            output.write_all(terminator.as_bytes())?;
+            buf.clear();
+        }
+        output.flush()?;
+        Ok(stats)
+    }
+
+    /// ranges returns the ranges to highlight in the line, and the colors given by the filter,
+    /// to be used like reserved colors.
+    fn ranges(&self, line: &str) -> (Vec<RangeWithId>, Vec<ColorSet>) {
+        let Some(filter) = &self.filter else {
+            return (match_line(line, &self.regexps, &self.options), Vec::new());
+        };
+        let mut filter_colors = Vec::new();
+        let ranges = match_line_filtered(
+            line,
+            &self.regexps,
+            &self.options,
+            &mut |regexp_idx, mut r| {
+                let info = MatchInfo {
+                    text: &line[r.start_idx..r.end_idx],
+                    pattern: self.regexps.len() - 1 - regexp_idx,
+                    start: r.start_idx,
+                    end: r.end_idx,
+                };
+                match filter(&info)? {
+                    ColorSpec::Default => {}
+                    ColorSpec::Color(color) => {
+                        r.id = self.pattern_color_count + filter_colors.len();
+                        filter_colors.push(color);
+                    }
+                }
+                Some(r)
+            },
+        );
+        (ranges, filter_colors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::default(Highlighter::builder(), "\x1b[31ma\x1b[0m \x1b[32mb\x1b[0m")]
+    #[case::background(
+        Highlighter::builder().color_mode(ColorMode::Background),
+        "\x1b[41ma\x1b[49m \x1b[44mb\x1b[49m"
+    )]
+    #[case::custom_colors(
+        Highlighter::builder().fg_colors(["bright-red", "208"]),
+        "\x1b[91ma\x1b[0m \x1b[38;5;208mb\x1b[0m"
+    )]
+    #[case::markup(
+        Highlighter::builder().markup(Markup { open: "<".into(), close: ">".into() }),
+        "<red>a</> <green>b</>"
+    )]
+    fn test_highlight_line(#[case] builder: HighlighterBuilder, #[case] expected: &str) {
+        let highlighter = builder.patterns(["a", "b"]).build().unwrap();
+        assert_eq!(highlighter.highlight_line("a b"), expected);
+    }
+
+    #[test]
+    fn test_group_colors() {
+        let builder = Highlighter::builder().pattern(r"(\w+)=(\d+)");
+        let line = "x=1";
+        assert_eq!(
+            builder.clone().build().unwrap().highlight_line(line),
+            "\x1b[31mx\x1b[0m=\x1b[32m1\x1b[0m"
+        );
+        assert_eq!(
+            builder
+                .clone()
+                .vary_group_colors(false)
+                .build()
+                .unwrap()
+                .highlight_line(line),
+            "\x1b[31mx\x1b[0m=\x1b[31m1\x1b[0m"
+        );
+        assert_eq!(
+            builder
+                .full_match_highlight(true)
+                .build()
+                .unwrap()
+                .highlight_line(line),
+            "\x1b[31mx=1\x1b[0m"
+        );
+    }
+
+    #[rstest]
+    #[case::invalid_pattern(Highlighter::builder().pattern("("))]
+    #[case::invalid_color(Highlighter::builder().pattern("a").fg_colors(["blurple"]))]
+    fn test_build_invalid(#[case] builder: HighlighterBuilder) {
+        assert!(builder.build().is_err());
+    }
+
+    #[test]
+    fn test_filter_by_length() {
+        let highlighter = Highlighter::builder()
+            .pattern(r"\w+")
+            .build()
+            .unwrap()
+            .with_filter(|m| (m.text.len() > 3).then_some(ColorSpec::Default));
+        assert_eq!(
+            highlighter.highlight_line("a tiny word list"),
+            "a \x1b[31mtiny\x1b[0m \x1b[31mword\x1b[0m \x1b[31mlist\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn test_filter_infos_and_colors() {
+        let infos = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = Arc::clone(&infos);
+        let highlighter = Highlighter::builder()
+            .patterns(["a+", "b+"])
+            .build()
+            .unwrap()
+            .with_filter(move |m| {
+                seen.lock()
+                    .unwrap()
+                    .push((m.text.to_string(), m.pattern, m.start, m.end));
+                match m.pattern {
+                    0 => Some(ColorSpec::Color(ColorSet::new("<", ">"))),
+                    _ => Some(ColorSpec::Default),
+                }
+            });
+        assert_eq!(
+            highlighter.highlight_line("aa bbb"),
+            "<aa> \x1b[32mbbb\x1b[0m"
+        );
+        // the patterns taking precedence come first
+        assert_eq!(
+            *infos.lock().unwrap(),
+            vec![("bbb".to_string(), 1, 3, 6), ("aa".to_string(), 0, 0, 2)]
+        );
+    }
+
+    #[test]
+    fn test_filter_overlapping_patterns() {
+        let infos = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = Arc::clone(&infos);
+        // "b+" is given later and takes precedence over "a+b+" where they overlap
+        let highlighter = Highlighter::builder()
+            .patterns(["a+b+", "b+"])
+            .build()
+            .unwrap()
+            .with_filter(move |m| {
+                seen.lock().unwrap().push((m.text.to_string(), m.pattern));
+                (m.text != "bb").then_some(ColorSpec::Default)
+            });
+        // the rejected "bb" leaves the whole "aabb" of the other pattern, which sees it in full
+        assert_eq!(highlighter.highlight_line("aabb"), "\x1b[31maabb\x1b[0m");
+        assert_eq!(
+            *infos.lock().unwrap(),
+            vec![("bb".to_string(), 1), ("aabb".to_string(), 0)]
+        );
+        // otherwise the match of the other pattern is cut by it
+        assert_eq!(
+            highlighter.highlight_line("aab"),
+            "\x1b[31maa\x1b[0m\x1b[32mb\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn test_process() {
+        let highlighter = Highlighter::builder()
+            .patterns(["a+", "b"])
+            .build()
+            .unwrap()
+            .with_filter(|m| (m.text != "a").then_some(ColorSpec::Default));
+        let mut output = Vec::new();
+        let stats = highlighter
+            .process(&b"aa a\r\nnone\nb b\xff"[..], &mut output)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "\x1b[31maa\x1b[0m a\r\nnone\n\x1b[32mb\x1b[0m \x1b[32mb\x1b[0m\u{fffd}"
+        );
+        assert_eq!(
+            stats,
+            Stats {
+                lines: 3,
+                matching_lines: 2,
+                match_counts: vec![2, 2],
+            }
+        );
+        assert_eq!(stats.matches(), 4);
+    }
+
+    #[test]
+    fn test_process_empty() {
+        let highlighter = Highlighter::builder().pattern("a").build().unwrap();
+        let mut output = Vec::new();
+        let stats = highlighter.process(io::empty(), &mut output).unwrap();
+        assert!(output.is_empty());
+        assert_eq!(stats.lines, 0);
+    }
+
+    #[test]
+    fn test_fixed_strings() {
+        let highlighter = Highlighter::builder()
+            .pattern("a.c")
+            .fixed_strings(true)
+            .build()
+            .unwrap();
+        assert_eq!(
+            highlighter.highlight_line("abc a.c"),
+            "abc \x1b[31ma.c\x1b[0m"
+        );
+    }
+}
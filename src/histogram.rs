@@ -0,0 +1,104 @@
+use crate::RangeWithId;
+use crate::escapes::visible_width;
+use std::collections::HashMap;
+
+/// Histogram counts how often every matched text occurs, remembering the id of the range it was
+/// first seen in.
+#[derive(Debug, Default)]
+pub struct Histogram {
+    entries: HashMap<String, (usize, usize)>,
+}
+
+impl Histogram {
+    pub fn add(&mut self, value: &str, id: usize) {
+        match self.entries.get_mut(value) {
+            Some((count, _)) => *count += 1,
+            None => {
+                self.entries.insert(value.to_string(), (1, id));
+            }
+        }
+    }
+
+    /// rows returns the values with their counts and ids, the most frequent values first, and
+    /// values that are equally frequent in alphabetical order.
+    pub fn rows(&self) -> Vec<(&str, usize, usize)> {
+        let mut rows = self
+            .entries
+            .iter()
+            .map(|(value, (count, id))| (value.as_str(), *count, *id))
+            .collect::<Vec<_>>();
+        rows.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        rows
+    }
+
+    /// format returns the lines of the frequency table, each made of a value, its count and a bar
+    /// of up to bar_width block characters, scaled to the highest count. The ranges cover the
+    /// bars, with the ids of their values.
+    pub fn format(&self, bar_width: usize) -> Vec<(String, Vec<RangeWithId>)> {
+        let rows = self.rows();
+        let Some(&(_, max_count, _)) = rows.first() else {
+            return Vec::new();
+        };
+        let value_width = rows.iter().map(|(v, _, _)| visible_width(v)).max();
+        let value_width = value_width.unwrap_or_default();
+        let count_width = max_count.to_string().len();
+        rows.into_iter()
+            .map(|(value, count, id)| {
+                let padding = " ".repeat(value_width - visible_width(value));
+                let mut line = format!("{value}{padding} {count:>count_width$} ");
+                let bar_len = (count * bar_width).div_ceil(max_count).max(1);
+                let start_idx = line.len();
+                line.push_str(&"█".repeat(bar_len));
+                let range = RangeWithId {
+                    start_idx,
+                    end_idx: line.len(),
+                    id,
+                };
+                (line, vec![range])
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn histogram(values: &[(&str, usize)]) -> Histogram {
+        let mut histogram = Histogram::default();
+        for (value, id) in values {
+            histogram.add(value, *id);
+        }
+        histogram
+    }
+
+    #[test]
+    fn test_rows() {
+        let histogram = histogram(&[("b", 0), ("a", 1), ("c", 0), ("b", 0), ("c", 2), ("d", 0)]);
+        assert_eq!(
+            histogram.rows(),
+            vec![("b", 2, 0), ("c", 2, 0), ("a", 1, 1), ("d", 1, 0)]
+        );
+    }
+
+    #[test]
+    fn test_format() {
+        let mut histogram = histogram(&[("error", 0), ("warn", 1)]);
+        for _ in 0..3 {
+            histogram.add("error", 0);
+        }
+        let lines = histogram.format(8);
+        assert_eq!(
+            lines.iter().map(|(l, _)| l.as_str()).collect::<Vec<_>>(),
+            vec!["error 4 ████████", "warn  1 ██"]
+        );
+        let (line, ranges) = &lines[1];
+        assert_eq!(&line[ranges[0].start_idx..ranges[0].end_idx], "██");
+        assert_eq!(ranges[0].id, 1);
+    }
+
+    #[test]
+    fn test_format_empty() {
+        assert!(Histogram::default().format(8).is_empty());
+    }
+}
@@ -0,0 +1,159 @@
+use crate::RangeWithId;
+use regex::Regex;
+use std::sync::LazyLock;
+
+// a scheme followed by "://" and anything but whitespace and control characters
+static URL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[a-zA-Z][a-zA-Z0-9+.-]*://[^\s\x00-\x1f\x7f]+$").unwrap());
+
+/// is_url returns whether s is a URL, like `https://example.com/path`.
+pub fn is_url(s: &str) -> bool {
+    URL.is_match(s)
+}
+
+/// add_hyperlinks wraps every highlighted URL of the line in the OSC 8 escape sequences that make
+/// it clickable in terminals supporting them, and moves the ranges accordingly. Only the ranges
+/// whose ids are linkable can be URLs, so that e.g. the ranges tinting the text between the matches
+/// are not taken for part of them. Adjacent linkable ranges, e.g. of the groups of a URL pattern,
+/// are taken together. Terminals not supporting OSC 8 ignore the sequences, so that the URLs are
+/// just highlighted.
+pub fn add_hyperlinks(
+    line: &mut String,
+    ranges: &mut [RangeWithId],
+    linkable: impl Fn(usize) -> bool,
+) {
+    let mut linked = String::with_capacity(line.len());
+    let mut copied = 0;
+    let mut shift = 0;
+    let mut i = 0;
+    while i < ranges.len() {
+        if !linkable(ranges[i].id) {
+            ranges[i].start_idx += shift;
+            ranges[i].end_idx += shift;
+            i += 1;
+            continue;
+        }
+        // the run of adjacent linkable ranges starting at i
+        let mut end = i + 1;
+        while end < ranges.len()
+            && linkable(ranges[end].id)
+            && ranges[end].start_idx == ranges[end - 1].end_idx
+        {
+            end += 1;
+        }
+        let (start_idx, end_idx) = (ranges[i].start_idx, ranges[end - 1].end_idx);
+        let text = &line[start_idx..end_idx];
+        let mut text_shift = shift;
+        if is_url(text) {
+            linked.push_str(&line[copied..start_idx]);
+            linked.push_str(&format!("\x1b]8;;{text}\x1b\\"));
+            text_shift = linked.len() - start_idx;
+            linked.push_str(text);
+            linked.push_str("\x1b]8;;\x1b\\");
+            copied = end_idx;
+            shift = linked.len() - end_idx;
+        }
+        for r in &mut ranges[i..end] {
+            r.start_idx += text_shift;
+            r.end_idx += text_shift;
+        }
+        i = end;
+    }
+    if copied > 0 {
+        linked.push_str(&line[copied..]);
+        *line = linked;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn r(start_idx: usize, end_idx: usize, id: usize) -> RangeWithId {
+        RangeWithId {
+            start_idx,
+            end_idx,
+            id,
+        }
+    }
+
+    #[rstest]
+    #[case::https("https://example.com/a?b=c#d", true)]
+    #[case::other_scheme("git+ssh://host/repo", true)]
+    #[case::no_scheme("example.com", false)]
+    #[case::no_host("https://", false)]
+    #[case::whitespace("https://example.com x", false)]
+    #[case::escape("https://example.com\x1b[0m", false)]
+    fn test_is_url(#[case] s: &str, #[case] expected: bool) {
+        assert_eq!(is_url(s), expected);
+    }
+
+    #[test]
+    fn test_add_hyperlinks() {
+        let mut line = "see https://a.io and x".to_string();
+        let mut ranges = vec![r(4, 16, 0), r(21, 22, 1)];
+        add_hyperlinks(&mut line, &mut ranges, |_| true);
+        let open = "\x1b]8;;https://a.io\x1b\\";
+        let close = "\x1b]8;;\x1b\\";
+        assert_eq!(line, format!("see {open}https://a.io{close} and x"));
+        let shift = open.len() + close.len();
+        assert_eq!(
+            ranges,
+            vec![
+                r(4 + open.len(), 16 + open.len(), 0),
+                r(21 + shift, 22 + shift, 1)
+            ]
+        );
+        assert_eq!(
+            &line[ranges[0].start_idx..ranges[0].end_idx],
+            "https://a.io"
+        );
+        assert_eq!(&line[ranges[1].start_idx..ranges[1].end_idx], "x");
+    }
+
+    #[test]
+    fn test_add_hyperlinks_adjacent_ranges() {
+        let mut line = "https://a.io/p".to_string();
+        let mut ranges = vec![r(0, 8, 0), r(8, 14, 1)];
+        add_hyperlinks(&mut line, &mut ranges, |_| true);
+        let open = "\x1b]8;;https://a.io/p\x1b\\";
+        assert_eq!(line, format!("{open}https://a.io/p\x1b]8;;\x1b\\"));
+        assert_eq!(
+            ranges,
+            vec![
+                r(open.len(), open.len() + 8, 0),
+                r(open.len() + 8, open.len() + 14, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_add_hyperlinks_no_urls() {
+        let mut line = "no links here".to_string();
+        let mut ranges = vec![r(0, 2, 0)];
+        add_hyperlinks(&mut line, &mut ranges, |_| true);
+        assert_eq!(line, "no links here");
+        assert_eq!(ranges, vec![r(0, 2, 0)]);
+    }
+
+    #[test]
+    fn test_add_hyperlinks_between_tint_ranges() {
+        // like --dim-unmatched, the text around the URL is tinted with id 1
+        let mut line = "x https://a.io y".to_string();
+        let mut ranges = vec![r(0, 2, 1), r(2, 14, 0), r(14, 16, 1)];
+        add_hyperlinks(&mut line, &mut ranges, |id| id == 0);
+        let open = "\x1b]8;;https://a.io\x1b\\";
+        let close = "\x1b]8;;\x1b\\";
+        assert_eq!(line, format!("x {open}https://a.io{close} y"));
+        let shift = open.len() + close.len();
+        assert_eq!(
+            ranges,
+            vec![
+                r(0, 2, 1),
+                r(2 + open.len(), 14 + open.len(), 0),
+                r(14 + shift, 16 + shift, 1)
+            ]
+        );
+    }
+}
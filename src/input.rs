@@ -0,0 +1,604 @@
+use crate::transcode::{InputEncoding, Transcoder};
+use crate::{ColorexpError, Result};
+use regex::{Regex, RegexBuilder};
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::iter;
+use std::path::{Path, PathBuf};
+
+/// open_input opens the file at the given path for reading, or stdin if the path is `-`. Files
+/// ending in `.gz` are decompressed on the fly, and so is any input if decompress is set.
+pub fn open_input(path: &Path, decompress: bool) -> Result<Box<dyn BufRead>> {
+    let reader: Box<dyn Read> = if path.as_os_str() == "-" {
+        Box::new(io::stdin().lock())
+    } else {
+        let file = File::open(path).map_err(|source| ColorexpError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Box::new(file)
+    };
+    if decompress || path.extension().is_some_and(|ext| ext == "gz") {
+        return gunzip(reader);
+    }
+    Ok(Box::new(BufReader::new(reader)))
+}
+
+/// open_decoded is like open_input, but decodes the input from the given encoding, if any, so that
+/// it can be read as UTF-8.
+fn open_decoded(
+    path: &Path,
+    decompress: bool,
+    encoding: Option<InputEncoding>,
+) -> Result<Box<dyn BufRead>> {
+    let reader = open_input(path, decompress)?;
+    Ok(match encoding {
+        Some(encoding) => Box::new(BufReader::new(Transcoder::new(reader, encoding))),
+        None => reader,
+    })
+}
+
+/// Line is a line (or record) of input without its terminator, which is kept separately so that
+/// output lines can be terminated the same way as the input lines, including a missing final
+/// newline.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Line {
+    pub text: String,
+    pub terminator: Cow<'static, str>,
+    /// The index of the input the line was read from.
+    pub input: usize,
+}
+
+impl Line {
+    fn new(mut text: String) -> Self {
+        let terminator = if text.ends_with("\r\n") {
+            "\r\n"
+        } else if text.ends_with('\n') {
+            "\n"
+        } else if text.ends_with('\r') {
+            "\r"
+        } else {
+            ""
+        };
+        text.truncate(text.len() - terminator.len());
+        Self {
+            text,
+            terminator: Cow::Borrowed(terminator),
+            input: 0,
+        }
+    }
+}
+
+/// lines returns the lines of the reader like BufRead::lines does, but keeps their terminators.
+pub fn lines(mut reader: impl BufRead) -> impl Iterator<Item = io::Result<Line>> {
+    iter::from_fn(move || {
+        let mut text = String::new();
+        match reader.read_line(&mut text) {
+            Ok(0) => None,
+            Ok(_) => Some(Ok(Line::new(text))),
+            Err(err) => Some(Err(err)),
+        }
+    })
+}
+
+/// cr_lines is like lines, but a carriage return not followed by a newline terminates a line as
+/// well, so that lines updated in place, like progress bars, are returned without waiting for a
+/// newline. A carriage return at the end of the data available so far is taken as a terminator
+/// without waiting for more data, so a CRLF split across reads results in a line terminated by
+/// the carriage return followed by an empty line terminated by the newline.
+pub fn cr_lines(mut reader: impl BufRead) -> impl Iterator<Item = io::Result<Line>> {
+    iter::from_fn(move || {
+        let mut bytes = Vec::new();
+        match read_cr_line(&mut reader, &mut bytes) {
+            Ok(0) => None,
+            Ok(_) => Some(String::from_utf8(bytes).map(Line::new).map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "stream did not contain valid UTF-8",
+                )
+            })),
+            Err(err) => Some(Err(err)),
+        }
+    })
+}
+
+fn read_cr_line(reader: &mut impl BufRead, bytes: &mut Vec<u8>) -> io::Result<usize> {
+    loop {
+        let buf = match reader.fill_buf() {
+            Ok(buf) => buf,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        if buf.is_empty() {
+            return Ok(bytes.len());
+        }
+        let Some(idx) = buf.iter().position(|&b| b == b'\n' || b == b'\r') else {
+            let len = buf.len();
+            bytes.extend_from_slice(buf);
+            reader.consume(len);
+            continue;
+        };
+        let end = if buf[idx] == b'\r' && buf.get(idx + 1) == Some(&b'\n') {
+            idx + 2
+        } else {
+            idx + 1
+        };
+        bytes.extend_from_slice(&buf[..end]);
+        reader.consume(end);
+        return Ok(bytes.len());
+    }
+}
+
+/// read_lines returns the lines of all the inputs, one input after the other, decoded from the
+/// encoding if given. If split_cr is set, lines are split like cr_lines does.
+pub fn read_lines(
+    paths: &[PathBuf],
+    decompress: bool,
+    encoding: Option<InputEncoding>,
+    split_cr: bool,
+) -> impl Iterator<Item = Result<Line>> + '_ {
+    paths.iter().enumerate().flat_map(move |(input, path)| {
+        let lines: Box<dyn Iterator<Item = Result<Line>>> =
+            match open_decoded(path, decompress, encoding) {
+                Ok(reader) if split_cr => Box::new(cr_lines(reader).map(move |line| {
+                    line.map_err(|source| ColorexpError::Io {
+                        path: path.clone(),
+                        source,
+                    })
+                })),
+                Ok(reader) => Box::new(lines(reader).map(move |line| {
+                    line.map_err(|source| ColorexpError::Io {
+                        path: path.clone(),
+                        source,
+                    })
+                })),
+                Err(err) => Box::new(iter::once(Err(err))),
+            };
+        lines.map(move |line| line.map(|line| Line { input, ..line }))
+    })
+}
+
+/// read_word_list returns the words in the file at the given path, one per line. Surrounding
+/// whitespace and empty lines are ignored.
+pub fn read_word_list(path: &Path) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    for line in lines(open_input(path, false)?) {
+        let line = line.map_err(|source| ColorexpError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let word = line.text.trim();
+        if !word.is_empty() {
+            words.push(word.to_string());
+        }
+    }
+    Ok(words)
+}
+
+/// read_patterns returns the patterns in the reader, one per line, read from the given path.
+/// Empty lines are ignored, as an empty pattern would match everywhere.
+pub fn read_patterns(reader: impl BufRead, path: &Path) -> Result<Vec<String>> {
+    let mut patterns = Vec::new();
+    for line in lines(reader) {
+        let line = line.map_err(|source| ColorexpError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        if !line.text.is_empty() {
+            patterns.push(line.text);
+        }
+    }
+    Ok(patterns)
+}
+
+/// split_patterns splits the value of the COLOREXP_PATTERNS environment variable into patterns,
+/// which are separated by newlines or colons. A colon within a pattern is written as `\:`, other
+/// backslash escapes are kept as they are. Empty patterns are ignored.
+pub fn split_patterns(value: &str) -> Vec<String> {
+    let mut patterns = Vec::new();
+    let mut pattern = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(':') => pattern.push(':'),
+                Some(escaped) => {
+                    pattern.push(c);
+                    pattern.push(escaped);
+                }
+                None => pattern.push(c),
+            },
+            ':' | '\n' => patterns.push(std::mem::take(&mut pattern)),
+            _ => pattern.push(c),
+        }
+    }
+    patterns.push(pattern);
+    patterns.retain(|p| !p.is_empty());
+    patterns
+}
+
+/// fd_path returns the path under which the file descriptor can be opened.
+pub fn fd_path(fd: u32) -> PathBuf {
+    PathBuf::from(format!("/dev/fd/{fd}"))
+}
+
+/// read_records returns the records of all the inputs, one input after the other. Records are
+/// separated by matches of the separator, which become the terminators of the records. Each
+/// input is read entirely before its records are returned, decoded like by read_lines.
+pub fn read_records<'a>(
+    paths: &'a [PathBuf],
+    decompress: bool,
+    encoding: Option<InputEncoding>,
+    separator: &'a Regex,
+) -> impl Iterator<Item = Result<Line>> + 'a {
+    paths.iter().enumerate().flat_map(move |(input_idx, path)| {
+        let mut input = String::new();
+        let records = open_decoded(path, decompress, encoding).and_then(|mut reader| {
+            reader
+                .read_to_string(&mut input)
+                .map_err(|source| ColorexpError::Io {
+                    path: path.clone(),
+                    source,
+                })
+        });
+        let records: Box<dyn Iterator<Item = Result<Line>>> =
+            match records {
+                Ok(_) => Box::new(split_records(&input, separator).into_iter().map(
+                    move |record| {
+                        Ok(Line {
+                            input: input_idx,
+                            ..record
+                        })
+                    },
+                )),
+                Err(err) => Box::new(iter::once(Err(err))),
+            };
+        records
+    })
+}
+
+/// compile_record_separator compiles the separator of read_records. As records span lines, `^`
+/// and `$` match at the start and end of every line within the input, not just of the input.
+pub fn compile_record_separator(separator: &str) -> Result<Regex> {
+    RegexBuilder::new(separator)
+        .multi_line(true)
+        .build()
+        .map_err(|source| ColorexpError::InvalidPattern {
+            pattern: separator.to_string(),
+            source,
+        })
+}
+
+/// split_records splits the input into records separated by matches of the separator. The last
+/// record has no terminator if the input does not end with a separator.
+pub fn split_records(input: &str, separator: &Regex) -> Vec<Line> {
+    let mut records = Vec::new();
+    let mut start = 0;
+    for m in separator.find_iter(input) {
+        // an empty separator would split the input into single characters, or empty records
+        if m.is_empty() {
+            continue;
+        }
+        records.push(Line {
+            text: input[start..m.start()].to_string(),
+            terminator: Cow::Owned(m.as_str().to_string()),
+            input: 0,
+        });
+        start = m.end();
+    }
+    if start < input.len() {
+        records.push(Line {
+            text: input[start..].to_string(),
+            terminator: Cow::Borrowed(""),
+            input: 0,
+        });
+    }
+    records
+}
+
+#[cfg(feature = "gzip")]
+fn gunzip(reader: Box<dyn Read>) -> Result<Box<dyn BufRead>> {
+    Ok(Box::new(BufReader::new(flate2::read::MultiGzDecoder::new(
+        reader,
+    ))))
+}
+
+#[cfg(not(feature = "gzip"))]
+fn gunzip(_reader: Box<dyn Read>) -> Result<Box<dyn BufRead>> {
+    Err(ColorexpError::InvalidArgumentCombination(
+        "decompressing input requires colorexp to be built with the gzip feature".to_string(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("colorexp-{}-{name}", std::process::id()))
+    }
+
+    #[test]
+    fn test_open_input_plain() {
+        let path = temp_path("plain.log");
+        std::fs::write(&path, "a\nb\n").unwrap();
+        let lines = open_input(&path, false)
+            .unwrap()
+            .lines()
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(lines, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_read_lines() {
+        let paths = [temp_path("first.log"), temp_path("second.log")];
+        std::fs::write(&paths[0], "a\nb\n").unwrap();
+        std::fs::write(&paths[1], "c").unwrap();
+        let lines = read_lines(&paths, false, None, false).collect::<Result<Vec<_>>>();
+        for path in &paths {
+            std::fs::remove_file(path).unwrap();
+        }
+        let lines = lines
+            .unwrap()
+            .into_iter()
+            .map(|l| (l.text, l.input))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            vec![
+                ("a".to_string(), 0),
+                ("b".to_string(), 0),
+                ("c".to_string(), 1)
+            ]
+        );
+    }
+
+    #[rstest]
+    #[case::terminated("a\nb\n", &[("a", "\n"), ("b", "\n")])]
+    #[case::unterminated("a\nb", &[("a", "\n"), ("b", "")])]
+    #[case::crlf("a\r\nb\n", &[("a", "\r\n"), ("b", "\n")])]
+    #[case::empty_lines("\n\n", &[("", "\n"), ("", "\n")])]
+    #[case::empty("", &[])]
+    fn test_lines(#[case] input: &str, #[case] expected: &[(&str, &str)]) {
+        let lines = lines(input.as_bytes())
+            .map(|line| line.map(|l| (l.text, l.terminator.into_owned())))
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(lines, owned(expected));
+    }
+
+    fn owned(lines: &[(&str, &str)]) -> Vec<(String, String)> {
+        lines
+            .iter()
+            .map(|(text, terminator)| (text.to_string(), terminator.to_string()))
+            .collect()
+    }
+
+    #[rstest]
+    #[case::paragraphs("a\nb\n\nc\n", r"\n\n+", &[("a\nb", "\n\n"), ("c\n", "")])]
+    #[case::trailing_separator("a\n\nb\n\n", r"\n\n+", &[("a", "\n\n"), ("b", "\n\n")])]
+    #[case::leading_separator("\n\na", r"\n\n+", &[("", "\n\n"), ("a", "")])]
+    #[case::no_separator("a\nb", r"\n\n+", &[("a\nb", "")])]
+    #[case::empty_separator("ab", r"x*", &[("ab", "")])]
+    #[case::empty("", r"\n\n+", &[])]
+    fn test_split_records(
+        #[case] input: &str,
+        #[case] separator: &str,
+        #[case] expected: &[(&str, &str)],
+    ) {
+        let records = split_records(input, &Regex::new(separator).unwrap())
+            .into_iter()
+            .map(|l| (l.text, l.terminator.into_owned()))
+            .collect::<Vec<_>>();
+        assert_eq!(records, owned(expected));
+    }
+
+    #[rstest]
+    #[case::dashes_line("a\n--\nb--\n", r"^--\n", &[("a\n", "--\n"), ("b--\n", "")])]
+    #[case::line_end("a;\nb;c", r";$\n", &[("a", ";\n"), ("b;c", "")])]
+    fn test_split_records_multi_line(
+        #[case] input: &str,
+        #[case] separator: &str,
+        #[case] expected: &[(&str, &str)],
+    ) {
+        let records = split_records(input, &compile_record_separator(separator).unwrap())
+            .into_iter()
+            .map(|l| (l.text, l.terminator.into_owned()))
+            .collect::<Vec<_>>();
+        assert_eq!(records, owned(expected));
+    }
+
+    #[test]
+    fn test_match_records() {
+        use crate::{MatchOptions, compile_patterns, match_line};
+
+        let path = temp_path("paragraphs.log");
+        std::fs::write(&path, "start\nerror\n\nstart\nok").unwrap();
+        let separator = Regex::new(r"\n\n").unwrap();
+        let records = read_records(std::slice::from_ref(&path), false, None, &separator)
+            .collect::<Result<Vec<_>>>();
+        std::fs::remove_file(&path).unwrap();
+        let regexps = compile_patterns(&[r"start\nerror".into()], false, false).unwrap();
+        let matches = records
+            .unwrap()
+            .iter()
+            .map(|record| match_line(&record.text, &regexps, &MatchOptions::default()).len())
+            .collect::<Vec<_>>();
+        assert_eq!(matches, vec![1, 0]);
+    }
+
+    #[test]
+    fn test_read_lines_preserves_bytes() {
+        let path = temp_path("no-final-newline.log");
+        let input = "a\r\nb\n\nc";
+        std::fs::write(&path, input).unwrap();
+        let mut output = Vec::new();
+        for line in read_lines(std::slice::from_ref(&path), false, None, false) {
+            let line = line.unwrap();
+            write!(output, "{}{}", line.text, line.terminator).unwrap();
+        }
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(output.len(), input.len());
+        assert_eq!(output, input.as_bytes());
+    }
+
+    #[test]
+    fn test_read_lines_unterminated_match_at_end() {
+        use crate::{
+            ColorSet, MatchOptions, RESET_FOREGROUND, colorize, compile_patterns, match_line,
+        };
+
+        let path = temp_path("unterminated.log");
+        std::fs::write(&path, "an error").unwrap();
+        let lines =
+            read_lines(std::slice::from_ref(&path), false, None, false).collect::<Result<Vec<_>>>();
+        std::fs::remove_file(&path).unwrap();
+        let mut line = lines.unwrap().pop().unwrap().text;
+        let regexps = compile_patterns(&["error$".into()], false, false).unwrap();
+        let ranges = match_line(&line, &regexps, &MatchOptions::default());
+        colorize(
+            &mut line,
+            &[ColorSet::new("\x1b[31m", RESET_FOREGROUND)],
+            &[],
+            &ranges,
+            1,
+        );
+        assert_eq!(line, "an \x1b[31merror\x1b[0m");
+    }
+
+    #[test]
+    fn test_read_lines_latin1() {
+        use crate::{MatchOptions, compile_patterns, match_line};
+
+        let path = temp_path("latin1.log");
+        std::fs::write(&path, b"caf\xe9 ok\nna\xefve\n").unwrap();
+        let lines = read_lines(
+            std::slice::from_ref(&path),
+            false,
+            Some(InputEncoding::Latin1),
+            false,
+        )
+        .collect::<Result<Vec<_>>>();
+        std::fs::remove_file(&path).unwrap();
+        let lines = lines.unwrap();
+        assert_eq!(lines[0].text, "café ok");
+        assert_eq!(lines[1].text, "naïve");
+        // the offsets are those of the decoded text, in which é takes two bytes
+        let regexps = compile_patterns(&["é o".into()], false, false).unwrap();
+        let ranges = match_line(&lines[0].text, &regexps, &MatchOptions::default());
+        assert_eq!((ranges[0].start_idx, ranges[0].end_idx), (3, 7));
+    }
+
+    #[rstest]
+    #[case::progress("10%\r50%\rdone\n", &[("10%", "\r"), ("50%", "\r"), ("done", "\n")])]
+    #[case::crlf("a\r\nb\r", &[("a", "\r\n"), ("b", "\r")])]
+    #[case::unterminated("a\rb", &[("a", "\r"), ("b", "")])]
+    #[case::empty_segments("\r\r\n", &[("", "\r"), ("", "\r\n")])]
+    fn test_cr_lines(#[case] input: &str, #[case] expected: &[(&str, &str)]) {
+        let lines = cr_lines(input.as_bytes())
+            .map(|line| line.map(|l| (l.text, l.terminator.into_owned())))
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(lines, owned(expected));
+    }
+
+    #[test]
+    fn test_cr_lines_split_crlf() {
+        // the carriage return is the last byte available, so it terminates the line right away
+        let reader = io::BufReader::with_capacity(2, "a\r\nb".as_bytes());
+        let lines = cr_lines(reader)
+            .map(|line| line.map(|l| (l.text, l.terminator.into_owned())))
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(lines, owned(&[("a", "\r"), ("", "\n"), ("b", "")]));
+    }
+
+    #[test]
+    fn test_cr_lines_invalid_utf8() {
+        let err = cr_lines(&b"\xff\n"[..]).next().unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_read_word_list() {
+        let path = temp_path("words.txt");
+        std::fs::write(&path, "alpha\n  beta \n\ngamma delta\r\n").unwrap();
+        let words = read_word_list(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(words.unwrap(), vec!["alpha", "beta", "gamma delta"]);
+    }
+
+    #[test]
+    fn test_read_patterns() {
+        let reader = io::Cursor::new("foo\n\n b+ \r\n(x|y)");
+        let patterns = read_patterns(reader, &fd_path(3)).unwrap();
+        assert_eq!(patterns, vec!["foo", " b+ ", "(x|y)"]);
+    }
+
+    #[rstest]
+    #[case::colons("foo:b+", vec!["foo", "b+"])]
+    #[case::newlines("foo\nb+\n", vec!["foo", "b+"])]
+    #[case::mixed("a:b\nc", vec!["a", "b", "c"])]
+    #[case::escaped_colon(r"\d\:\d:x", vec![r"\d:\d", "x"])]
+    #[case::escaped_backslash(r"a\\:b", vec![r"a\\", "b"])]
+    #[case::trailing_backslash(r"a\", vec![r"a\"])]
+    #[case::empty_patterns("::a::", vec!["a"])]
+    #[case::empty("", vec![])]
+    fn test_split_patterns(#[case] value: &str, #[case] expected: Vec<&str>) {
+        assert_eq!(split_patterns(value), expected);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_read_patterns_from_fd() {
+        use std::os::fd::AsRawFd;
+
+        let path = temp_path("patterns.txt");
+        std::fs::write(&path, "a\nb\n").unwrap();
+        let file = File::open(&path).unwrap();
+        let fd_path = fd_path(file.as_raw_fd() as u32);
+        let patterns = read_patterns(open_input(&fd_path, false).unwrap(), &fd_path);
+        drop(file);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(patterns.unwrap(), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_open_input_missing_file() {
+        let path = temp_path("missing.log");
+        let err = open_input(&path, false).err().unwrap();
+        assert!(
+            matches!(err, ColorexpError::Io { path: ref p, .. } if *p == path),
+            "{err:?}"
+        );
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_open_input_gzip() {
+        use crate::{MatchOptions, compile_patterns, match_line};
+        use flate2::{Compression, write::GzEncoder};
+
+        let path = temp_path("fixture.log.gz");
+        let mut encoder = GzEncoder::new(File::create(&path).unwrap(), Compression::default());
+        encoder.write_all(b"no match\nan error here\n").unwrap();
+        encoder.finish().unwrap();
+
+        let regexps = compile_patterns(&["error".into()], false, false).unwrap();
+        let lines = open_input(&path, false)
+            .unwrap()
+            .lines()
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(lines, vec!["no match", "an error here"]);
+        let ranges = match_line(&lines[1], &regexps, &MatchOptions::default());
+        assert_eq!((ranges[0].start_idx, ranges[0].end_idx), (3, 8));
+    }
+}
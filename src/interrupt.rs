@@ -0,0 +1,139 @@
+use std::os::raw::c_int;
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+
+/// RESET turns off all colors and other attributes of the terminal.
+pub const RESET: &str = "\x1b[0m";
+
+/// RESTORE holds the bytes written to stdout on SIGINT. Modes that change the state of the
+/// terminal register the escape sequences undoing their changes in it, and remove them once they
+/// have undone the changes themselves.
+pub static RESTORE: Restore = Restore::new();
+
+const CAPACITY: usize = 256;
+
+/// Restore is a buffer of bytes that can be read from a signal handler, as it only consists of
+/// atomics. It is only written to from one thread at a time.
+pub struct Restore {
+    bytes: [AtomicU8; CAPACITY],
+    len: AtomicUsize,
+}
+
+impl Restore {
+    /// new returns an empty buffer.
+    pub const fn new() -> Self {
+        Self {
+            bytes: [const { AtomicU8::new(0) }; CAPACITY],
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    /// push appends the bytes, and returns the length of the buffer before, to be passed to
+    /// truncate when the bytes are no longer needed. Bytes that do not fit are left out as a whole,
+    /// as a partial escape sequence would do more harm than none.
+    pub fn push(&self, bytes: &[u8]) -> usize {
+        let len = self.len.load(Ordering::Acquire);
+        if bytes.len() <= CAPACITY - len {
+            for (slot, &byte) in self.bytes[len..].iter().zip(bytes) {
+                slot.store(byte, Ordering::Relaxed);
+            }
+            // the handler only reads the bytes up to the length, which are all stored by now
+            self.len.store(len + bytes.len(), Ordering::Release);
+        }
+        len
+    }
+
+    /// truncate removes the bytes pushed after the buffer had the given length.
+    pub fn truncate(&self, len: usize) {
+        self.len.fetch_min(len, Ordering::Release);
+    }
+
+    /// write writes the bytes to the file descriptor, using only async-signal-safe calls. Errors
+    /// are ignored, as there is nothing left to do about them.
+    pub(crate) fn write(&self, fd: c_int) {
+        let len = self.len.load(Ordering::Acquire);
+        let mut buf = [0u8; CAPACITY];
+        for (byte, slot) in buf.iter_mut().zip(&self.bytes[..len]) {
+            *byte = slot.load(Ordering::Relaxed);
+        }
+        // SAFETY: the buffer is valid for its length
+        unsafe {
+            libc::write(fd, buf.as_ptr().cast(), len);
+        }
+    }
+}
+
+impl Default for Restore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// reset_on_interrupt registers RESET in RESTORE and installs the handler for SIGINT, so that
+/// interrupting colorexp in the middle of a highlighted match does not leave the terminal colored.
+pub fn reset_on_interrupt() {
+    RESTORE.push(RESET.as_bytes());
+    restore_on_interrupt();
+}
+
+/// restore_on_interrupt installs a handler for SIGINT that writes RESTORE to stdout before exiting.
+/// The exit status is 130, like that of other programs killed by SIGINT.
+pub fn restore_on_interrupt() {
+    // SAFETY: the handler only makes async-signal-safe calls
+    unsafe {
+        libc::signal(
+            libc::SIGINT,
+            on_interrupt as *const () as libc::sighandler_t,
+        );
+    }
+}
+
+extern "C" fn on_interrupt(_signal: c_int) {
+    RESTORE.write(libc::STDOUT_FILENO);
+    // SAFETY: _exit is async-signal-safe, unlike exit
+    unsafe { libc::_exit(130) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::os::fd::AsRawFd;
+    use std::os::unix::net::UnixStream;
+
+    /// written returns what restore writes to a file descriptor.
+    fn written(restore: &Restore) -> String {
+        let (writer, mut reader) = UnixStream::pair().unwrap();
+        restore.write(writer.as_raw_fd());
+        drop(writer);
+        let mut written = String::new();
+        reader.read_to_string(&mut written).unwrap();
+        written
+    }
+
+    #[test]
+    fn test_restore_write() {
+        let restore = Restore::new();
+        assert_eq!(written(&restore), "");
+        restore.push(RESET.as_bytes());
+        assert_eq!(written(&restore), "\x1b[0m");
+    }
+
+    #[test]
+    fn test_restore_truncate() {
+        let restore = Restore::new();
+        restore.push(b"a");
+        let len = restore.push(b"bc");
+        assert_eq!(len, 1);
+        assert_eq!(written(&restore), "abc");
+        restore.truncate(len);
+        assert_eq!(written(&restore), "a");
+    }
+
+    #[test]
+    fn test_restore_too_long() {
+        let restore = Restore::new();
+        restore.push(b"a");
+        restore.push(&[b'b'; CAPACITY]);
+        assert_eq!(written(&restore), "a");
+    }
+}
@@ -0,0 +1,1893 @@
+//! Multicolor regexp highlighting of text lines. See highlighter::Highlighter for highlighting
+//! lines from other programs.
+
+pub mod aliases;
+pub mod align;
+pub mod brackets;
+pub mod classify;
+pub mod context;
+pub mod control;
+pub mod dashboard;
+pub mod decode;
+mod error;
+pub mod escapes;
+pub mod extract;
+pub mod filter;
+pub mod groups;
+pub mod heatmap;
+pub mod hexdump;
+pub mod highlighter;
+pub mod histogram;
+pub mod hyperlinks;
+pub mod input;
+#[cfg(unix)]
+pub mod interrupt;
+pub mod manifest;
+pub mod markdown;
+pub mod named_colors;
+pub mod normalize;
+pub mod output;
+pub mod palette;
+pub mod pattern_groups;
+pub mod positions;
+pub mod presets;
+pub mod priority;
+pub mod profile;
+pub mod regex_help;
+pub mod repeated;
+pub mod rewrite;
+pub mod sample;
+pub mod stats;
+pub mod svg;
+pub mod tags;
+pub mod transcode;
+pub mod value_map;
+pub mod watch;
+
+pub use error::{ColorexpError, Result};
+use regex::{Regex, RegexBuilder, RegexSet, RegexSetBuilder, SetMatches, escape};
+use regex_automata::{Anchored, Input, MatchKind, meta};
+use std::cmp::{max, min};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::{Arc, LazyLock};
+
+pub static FOREGROUND_COLORS: &[&str] = &[
+    //"\x1b[30m", // Black
+    "\x1b[31m", // Red
+    "\x1b[32m", // Green
+    "\x1b[33m", // Yellow
+    "\x1b[34m", // Blue
+    "\x1b[35m", // Magenta
+    "\x1b[36m", // Cyan
+                //"\x1b[37m", // White
+];
+
+pub static BACKGROUND_COLORS: &[&str] = &[
+    //"\x1b[40m", // Black
+    "\x1b[41m", // Red
+    "\x1b[44m", // Blue
+    "\x1b[45m", // Magenta
+    "\x1b[42m", // Green
+    "\x1b[43m", // Yellow
+    "\x1b[46m", // Cyan
+                //"\x1b[47m", // White
+];
+
+pub const RESET_FOREGROUND: &str = "\x1b[0m";
+pub const RESET_BACKGROUND: &str = "\x1b[49m";
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct RangeWithId {
+    pub start_idx: usize,
+    pub end_idx: usize,
+    pub id: usize,
+}
+
+/// add_range adds a new range to the ordered list of non-overlapping ranges.
+/// It ensures that the list stays ordered and any existing ranges are subtracted
+/// from the new range, potentially splitting it into multiple pieces.
+pub fn add_range(ranges: &mut Vec<RangeWithId>, mut new_range: RangeWithId) {
+    let mut inserted = false;
+
+    let mut i = 0;
+    while i < ranges.len() {
+        let existing_range = *unsafe { ranges.get_unchecked(i) };
+
+        if new_range.end_idx <= existing_range.start_idx {
+            // The new range is entirely before the existing range.
+            if !inserted {
+                ranges.insert(i, new_range);
+                i += 1;
+                inserted = true;
+            }
+        } else if new_range.start_idx >= existing_range.end_idx {
+            // The new range is entirely after the existing range.
+        } else {
+            // There is an overlap; we may need to split the new range.
+            if !inserted && new_range.start_idx < existing_range.start_idx {
+                // Add the non-overlapping piece before the existing range.
+                ranges.insert(
+                    i,
+                    RangeWithId {
+                        start_idx: new_range.start_idx,
+                        end_idx: existing_range.start_idx,
+                        id: new_range.id,
+                    },
+                );
+                i += 1;
+            }
+            if new_range.end_idx > existing_range.end_idx {
+                // Update the new range to start from the end of the existing range.
+                new_range.start_idx = existing_range.end_idx;
+            } else {
+                // The new range is fully covered by the existing range; nothing left to add.
+                inserted = true;
+                new_range.start_idx = new_range.end_idx;
+            }
+        }
+        i += 1;
+    }
+
+    // If the new range was not inserted because it is after all existing ranges,
+    // or if it still has a remaining piece after processing overlaps, add it now.
+    if !inserted {
+        ranges.push(new_range);
+    }
+}
+
+/// MatchOptions controls which matches of the patterns are found, and how they are colorized.
+#[derive(Clone, Debug, Default)]
+pub struct MatchOptions {
+    /// Use a different color for every capturing group of a pattern.
+    pub vary_group_colors: bool,
+    /// Colorize the entire match, even if the pattern contains capturing groups.
+    pub full_match_highlight: bool,
+    /// Colorize the entire match of the regexps with these patterns only, like full_match_highlight
+    /// does for all of them. Their groups are still there for everything else reading them.
+    pub full_match_regexps: Vec<String>,
+    /// Also find matches that overlap with previous matches of the same pattern.
+    pub overlapping: bool,
+    /// Skip colorizing matches (or groups) shorter than this number of bytes.
+    pub min_length: usize,
+    /// Stop matching a line once it has this many ranges. Patterns that take precedence are
+    /// matched first.
+    pub max_ranges: Option<usize>,
+    /// Add this to the ids of the ranges of every second match of a pattern in a line, so that
+    /// successive matches can be told apart.
+    pub alternate_offset: Option<usize>,
+    /// Only colorize the nth match (counting from 1) of every pattern in a line.
+    pub nth: Option<usize>,
+    /// Colorize the longest match of every pattern at the position of its leftmost match, rather
+    /// than the match of its first matching alternative.
+    pub longest_match: Option<LongestMatches>,
+}
+
+/// LongestMatches finds the longest matches of regexps, for MatchOptions::longest_match. The regex
+/// crate picks the first alternative that matches, so that `ab|abc` matches `ab` in `abcd`, while
+/// the longest match is `abc`. The leftmost match is still found by the regexp, from where the
+/// longest one is searched for, so that its start does not change.
+#[derive(Clone, Debug)]
+pub struct LongestMatches {
+    /// By the patterns of the regexps, as they are also matched on their own, e.g. by --profile.
+    regexps: Arc<HashMap<String, meta::Regex>>,
+}
+
+impl LongestMatches {
+    /// new prepares finding the longest matches of the regexps, which must all have been compiled
+    /// with the given ignore_case. Regexps too big to be compiled again keep their usual matches.
+    pub fn new(regexps: &[Regex], ignore_case: bool) -> Self {
+        let regexps = regexps
+            .iter()
+            .filter_map(|re| {
+                let longest = meta::Regex::builder()
+                    .configure(meta::Regex::config().match_kind(MatchKind::All))
+                    .syntax(
+                        regex_automata::util::syntax::Config::new().case_insensitive(ignore_case),
+                    )
+                    .build(re.as_str())
+                    .ok()?;
+                Some((re.as_str().to_string(), longest))
+            })
+            .collect();
+        Self {
+            regexps: Arc::new(regexps),
+        }
+    }
+}
+
+/// groups_to_colorize returns the index of the first group of the regexp that gets colorized, and
+/// the number of colorized groups.
+fn groups_to_colorize(re: &Regex, options: &MatchOptions) -> (usize, usize) {
+    let num_groups = re.captures_len() - 1; // subtract implicit group
+    if options.full_match_highlight
+        || options
+            .full_match_regexps
+            .iter()
+            .any(|pattern| pattern == re.as_str())
+    {
+        return (0, 1);
+    }
+    let first_group_to_colorize = min(1, num_groups);
+    (
+        first_group_to_colorize,
+        num_groups + 1 - first_group_to_colorize,
+    )
+}
+
+/// regexp_ids returns the ids match_line gives the ranges of each of the regexps.
+pub fn regexp_ids(regexps: &[Regex], options: &MatchOptions) -> Vec<Range<usize>> {
+    let mut color_idx = 0;
+    regexps
+        .iter()
+        .map(|re| {
+            let count = if options.vary_group_colors {
+                groups_to_colorize(re, options).1
+            } else {
+                1
+            };
+            color_idx += count;
+            color_idx - count..color_idx
+        })
+        .collect()
+}
+
+/// group_id returns the id match_line gives the group with the given index of a regexp with the
+/// given ids: the first id if all groups share a color, otherwise the id of the group, where the
+/// full match gets the color of the first group.
+pub(crate) fn group_id(ids: &Range<usize>, g_idx: usize) -> usize {
+    ids.start + ids.len().saturating_sub(g_idx.max(1))
+}
+
+/// match_line returns the ranges of the matches of the regexps in the line. See match_line_into
+/// for a version that reuses the ranges of a previous line.
+pub fn match_line(line: &str, regexps: &[Regex], options: &MatchOptions) -> Vec<RangeWithId> {
+    let mut ranges = Vec::default();
+    match_line_into(line, regexps, options, &mut ranges);
+    ranges
+}
+
+/// match_line_into clears the ranges and fills them with the ranges of the matches of the regexps
+/// in the line, so that the allocation can be reused from line to line.
+pub fn match_line_into(
+    line: &str,
+    regexps: &[Regex],
+    options: &MatchOptions,
+    ranges: &mut Vec<RangeWithId>,
+) {
+    match_selected_into(line, regexps, options, ranges, None, None);
+}
+
+/// RangeFilter decides whether and how the range of a whole match (or capturing group) of the
+/// regexp with the given index is colorized, before it is added to the ranges: not at all if it
+/// returns None, and otherwise as the range it returns.
+pub type RangeFilter<'a> = dyn FnMut(usize, RangeWithId) -> Option<RangeWithId> + 'a;
+
+/// match_line_filtered is match_line, passing every range through the filter before it is added.
+/// As ranges of regexps that take precedence are only subtracted from later ones if they are
+/// added, the matches of later regexps show where the filter rejects those of earlier ones.
+pub fn match_line_filtered(
+    line: &str,
+    regexps: &[Regex],
+    options: &MatchOptions,
+    filter: &mut RangeFilter,
+) -> Vec<RangeWithId> {
+    let mut ranges = Vec::default();
+    match_selected_into(line, regexps, options, &mut ranges, None, Some(filter));
+    ranges
+}
+
+/// match_selected_into is match_line_into, skipping the regexps not among the selected ones, if
+/// given. The skipped regexps keep their colors, so that nothing else changes. Ranges are passed
+/// through the filter, if given, see match_line_filtered.
+fn match_selected_into(
+    line: &str,
+    regexps: &[Regex],
+    options: &MatchOptions,
+    ranges: &mut Vec<RangeWithId>,
+    selected: Option<&SetMatches>,
+    mut filter: Option<&mut RangeFilter>,
+) {
+    ranges.clear();
+    let mut color_idx = 0;
+    for (re_idx, re) in regexps.iter().enumerate() {
+        let (first_group_to_colorize, groups_to_colorize) = groups_to_colorize(re, options);
+        if selected.is_some_and(|selected| !selected.matched(re_idx)) {
+            color_idx += if options.vary_group_colors {
+                groups_to_colorize
+            } else {
+                1
+            };
+            continue;
+        }
+        let mut occurrence = 0;
+        // returns whether to stop matching the regexp, because the maximum number of ranges has
+        // been reached, or the nth match has been colorized
+        let mut colorize_match = |group: &dyn Fn(usize) -> Option<Range<usize>>| {
+            let alternate_offset = options
+                .alternate_offset
+                .filter(|_| occurrence % 2 == 1)
+                .unwrap_or_default();
+            occurrence += 1;
+            if options.nth.is_some_and(|nth| occurrence < nth) {
+                return false;
+            }
+            // if there is no capturing group, the full match will be colorized (group 0)
+            // if there are capturing groups, all groups but group 0 (the full match) will be colorized, unless
+            // full_match_highlight == true
+            for i in 0..groups_to_colorize {
+                let mut cur_color_idx = color_idx;
+                if options.vary_group_colors {
+                    cur_color_idx += groups_to_colorize - 1 - i;
+                }
+                let g_idx = i + first_group_to_colorize;
+                if let Some(g) = group(g_idx)
+                    && g.len() >= options.min_length
+                {
+                    let range = RangeWithId {
+                        start_idx: g.start,
+                        end_idx: g.end,
+                        id: cur_color_idx + alternate_offset,
+                    };
+                    let range = match &mut filter {
+                        Some(filter) => filter(re_idx, range),
+                        None => Some(range),
+                    };
+                    if let Some(range) = range {
+                        add_range(ranges, range);
+                    }
+                }
+            }
+            options.nth.is_some() || options.max_ranges.is_some_and(|max| ranges.len() >= max)
+        };
+        let longest = options
+            .longest_match
+            .as_ref()
+            .and_then(|longest| longest.regexps.get(re.as_str()));
+        if let Some(longest) = longest {
+            // search for the longest match from the start of every leftmost match, and restart
+            // the search after it, or one character after its start with --overlapping
+            let mut caps = longest.create_captures();
+            let mut start = 0;
+            while let Some(m) = re.find_at(line, start) {
+                let input = Input::new(line).range(m.start()..).anchored(Anchored::Yes);
+                longest.search_captures(&input, &mut caps);
+                if colorize_match(&|g_idx| caps.get_group(g_idx).map(|span| span.range())) {
+                    break;
+                }
+                let end = caps.get_match().map_or(m.end(), |m| m.end());
+                if options.overlapping || end == m.start() {
+                    match line[m.start()..].chars().next() {
+                        Some(c) => start = m.start() + c.len_utf8(),
+                        None => break,
+                    }
+                } else {
+                    start = end;
+                }
+            }
+        } else if options.overlapping {
+            // restart the search one character after the start of every match, which means that
+            // the line may be searched up to once per character
+            let mut start = 0;
+            while let Some(match_) = re.captures_at(line, start) {
+                if colorize_match(&|g_idx| match_.get(g_idx).map(|g| g.range())) {
+                    break;
+                }
+                let match_start = match_.get(0).map_or(start, |m| m.start());
+                match line[match_start..].chars().next() {
+                    Some(c) => start = match_start + c.len_utf8(),
+                    None => break,
+                }
+            }
+        } else {
+            for match_ in re.captures_iter(line) {
+                if colorize_match(&|g_idx| match_.get(g_idx).map(|g| g.range())) {
+                    break;
+                }
+            }
+        }
+        if let Some(max) = options.max_ranges
+            && ranges.len() >= max
+        {
+            // adding a range may have split it into several
+            ranges.truncate(max);
+            break;
+        }
+        if options.vary_group_colors {
+            color_idx += groups_to_colorize;
+        } else {
+            color_idx += 1;
+        }
+    }
+}
+
+/// CombinedPatterns speeds up matching many patterns: it finds all the regexps matching a line in a
+/// single pass over it, so that only those are run over the line to find their matches.
+///
+/// Running the regexps one after the other, rather than a single alternation of all of them, is
+/// what keeps the precedence of the patterns, as matches of patterns given earlier are still
+/// highlighted where they overlap with those of patterns given later.
+pub struct CombinedPatterns {
+    set: RegexSet,
+}
+
+impl CombinedPatterns {
+    /// new combines the regexps, which must all have been compiled with the given ignore_case. It
+    /// returns None if there are too few regexps for combining them to pay off, or if the
+    /// combination gets too big.
+    pub fn new(regexps: &[Regex], ignore_case: bool) -> Option<Self> {
+        if regexps.len() < 2 {
+            return None;
+        }
+        let set = RegexSetBuilder::new(regexps.iter().map(|re| re.as_str()))
+            .case_insensitive(ignore_case)
+            .build()
+            .ok()?;
+        Some(Self { set })
+    }
+
+    /// match_line_into gives the same ranges as the function of the same name.
+    pub fn match_line_into(
+        &self,
+        line: &str,
+        regexps: &[Regex],
+        options: &MatchOptions,
+        ranges: &mut Vec<RangeWithId>,
+    ) {
+        let selected = self.set.matches(line);
+        if selected.matched_any() {
+            match_selected_into(line, regexps, options, ranges, Some(&selected), None);
+        } else {
+            ranges.clear();
+        }
+    }
+}
+
+/// named_group_ids returns the names of the colorized named capturing groups, in the order the
+/// patterns were given, together with the ids match_line uses for their ranges.
+pub fn named_group_ids<'a>(regexps: &'a [Regex], options: &MatchOptions) -> Vec<(&'a str, usize)> {
+    let mut named_group_ids = Vec::new();
+    let mut color_idx = 0;
+    for re in regexps {
+        let (first_group_to_colorize, groups_to_colorize) = groups_to_colorize(re, options);
+        for (g_idx, name) in re.capture_names().enumerate() {
+            let Some(i) = g_idx.checked_sub(first_group_to_colorize) else {
+                continue;
+            };
+            if let Some(name) = name
+                && i < groups_to_colorize
+            {
+                let id = if options.vary_group_colors {
+                    color_idx + groups_to_colorize - 1 - i
+                } else {
+                    color_idx
+                };
+                named_group_ids.push((name, id));
+            }
+        }
+        if options.vary_group_colors {
+            color_idx += groups_to_colorize;
+        } else {
+            color_idx += 1;
+        }
+    }
+    // the regexps are in reverse order
+    named_group_ids.sort_by_key(|(_, id)| usize::MAX - id);
+    named_group_ids
+}
+
+/// group_legend returns the names of the colorized named capturing groups, each in its color.
+pub fn group_legend(
+    regexps: &[Regex],
+    options: &MatchOptions,
+    colors: &[ColorSet],
+    pattern_color_count: usize,
+) -> String {
+    named_group_ids(regexps, options)
+        .into_iter()
+        .map(|(name, id)| {
+            let mut legend = name.to_string();
+            let range = RangeWithId {
+                start_idx: 0,
+                end_idx: name.len(),
+                id,
+            };
+            colorize(&mut legend, colors, &[], &[range], pattern_color_count);
+            legend
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// keep_duplicates removes the ranges whose text occurs only once among the ranges, and gives the
+/// remaining ones the given id, so that repeated tokens stand out.
+pub fn keep_duplicates(line: &str, ranges: &mut Vec<RangeWithId>, id: usize) {
+    let mut counts = std::collections::HashMap::new();
+    for r in ranges.iter() {
+        *counts.entry(&line[r.start_idx..r.end_idx]).or_insert(0) += 1;
+    }
+    ranges.retain(|r| counts[&line[r.start_idx..r.end_idx]] > 1);
+    for r in ranges.iter_mut() {
+        r.id = id;
+    }
+}
+
+/// pad_ranges extends every range by up to `before` bytes before and `after` bytes after it, clamped
+/// to the line and its character boundaries. The padding uses the id of its range, but never
+/// replaces any part of another range.
+pub fn pad_ranges(line: &str, ranges: &mut Vec<RangeWithId>, before: usize, after: usize) {
+    if before == 0 && after == 0 {
+        return;
+    }
+    let matched = ranges.clone();
+    for r in matched {
+        let start_idx = line.ceil_char_boundary(r.start_idx.saturating_sub(before));
+        if start_idx < r.start_idx {
+            add_range(
+                ranges,
+                RangeWithId {
+                    start_idx,
+                    end_idx: r.start_idx,
+                    id: r.id,
+                },
+            );
+        }
+        let end_idx = line.floor_char_boundary(r.end_idx.saturating_add(after).min(line.len()));
+        if end_idx > r.end_idx {
+            add_range(
+                ranges,
+                RangeWithId {
+                    start_idx: r.end_idx,
+                    end_idx,
+                    id: r.id,
+                },
+            );
+        }
+    }
+}
+
+/// count_matches adds the number of matches of each regexp in the line to counts, which are in the
+/// order the patterns were given, i.e. the reverse order of the regexps.
+pub fn count_matches(line: &str, regexps: &[Regex], counts: &mut [usize]) {
+    for (re, count) in regexps.iter().rev().zip(counts) {
+        *count += re.find_iter(line).count();
+    }
+}
+
+/// count_ranges adds the number of ranges of each regexp to counts, which are in the order the
+/// patterns were given, like for count_matches. These are the highlighted matches (or capturing
+/// groups), after everything changing what is highlighted, like --nth or --min-length. The ids of
+/// the regexps are those returned by regexp_ids, shifted by alternate_offset for every second
+/// match, and ranges with other ids are not counted.
+pub fn count_ranges(
+    ranges: &[RangeWithId],
+    ids: &[Range<usize>],
+    alternate_offset: Option<usize>,
+    counts: &mut [usize],
+) {
+    let pattern_color_count = ids.last().map_or(0, |ids| ids.end);
+    for r in ranges {
+        let id = match alternate_offset {
+            Some(offset) if (offset..offset + pattern_color_count).contains(&r.id) => r.id - offset,
+            _ => r.id,
+        };
+        if id < pattern_color_count {
+            let regexp_idx = ids.partition_point(|ids| ids.end <= id);
+            counts[ids.len() - 1 - regexp_idx] += 1;
+        }
+    }
+}
+
+/// vary_group_colors_by_default returns whether capturing groups get different colors, unless
+/// configured otherwise. That is the case if exactly one pattern is given, and it has capturing
+/// groups - without groups, there is nothing to vary.
+pub fn vary_group_colors_by_default(regexps: &[Regex]) -> bool {
+    regexps.len() == 1 && regexps[0].captures_len() > 1
+}
+
+/// pattern_color_count returns the number of colors used by the regexps.
+pub fn pattern_color_count(regexps: &[Regex], vary_group_colors: bool) -> usize {
+    let mut pattern_color_count = regexps.len();
+    if vary_group_colors {
+        for re in regexps {
+            pattern_color_count += max(0, re.captures_len() as i32 - 2) as usize;
+        }
+    }
+    pattern_color_count
+}
+
+/// color_keys returns a key for every color used for patterns, in the order of the colors: the
+/// pattern, followed by the number of the group if group colors vary.
+pub fn color_keys(patterns: &[String], regexps: &[Regex], vary_group_colors: bool) -> Vec<String> {
+    patterns
+        .iter()
+        .zip(regexps.iter().rev())
+        .flat_map(|(p, re)| {
+            let colors = if vary_group_colors {
+                max(1, re.captures_len().saturating_sub(1))
+            } else {
+                1
+            };
+            (1..=colors).map(move |g| {
+                if colors > 1 {
+                    format!("{p}#{g}")
+                } else {
+                    p.clone()
+                }
+            })
+        })
+        .collect()
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ColorSet {
+    pub on: String,
+    pub off: String,
+}
+
+impl ColorSet {
+    pub fn new(on: impl Into<String>, off: impl Into<String>) -> Self {
+        Self {
+            on: on.into(),
+            off: off.into(),
+        }
+    }
+}
+
+/// ReservedColors holds colors that are used independently of the patterns, e.g. by presets. Their
+/// range ids follow the ones used for the patterns.
+pub struct ReservedColors {
+    first_id: usize,
+    pub colors: Vec<ColorSet>,
+}
+
+impl ReservedColors {
+    pub fn new(pattern_color_count: usize) -> Self {
+        Self {
+            first_id: pattern_color_count,
+            colors: Vec::new(),
+        }
+    }
+
+    /// reserve adds a color, and returns the range id that selects it.
+    pub fn reserve(&mut self, color: ColorSet) -> usize {
+        self.colors.push(color);
+        self.first_id + self.colors.len() - 1
+    }
+}
+
+/// color_index returns the index of the color for ranges with the given id, which must be below
+/// pattern_color_count, in a palette of color_count colors.
+pub fn color_index(id: usize, pattern_color_count: usize, color_count: usize) -> usize {
+    let mut color_idx = (pattern_color_count - id) as i32 - 1; // The quick ...
+    while color_idx < 0 {
+        color_idx += color_count as i32;
+    }
+    color_idx as usize % color_count
+}
+
+/// ColorCycle gives the ranges of the patterns the colors of the palette in the order in which
+/// they occur, instead of by pattern. The colors are a copy of the palette among the reserved
+/// colors, starting at first_id.
+pub struct ColorCycle {
+    first_id: usize,
+    color_count: usize,
+    pattern_color_count: usize,
+    per_line: bool,
+    next: usize,
+}
+
+impl ColorCycle {
+    /// new returns a cycle through color_count colors. If per_line is set, every line starts with
+    /// the first color, otherwise the cycle continues from line to line.
+    pub fn new(
+        first_id: usize,
+        color_count: usize,
+        pattern_color_count: usize,
+        per_line: bool,
+    ) -> Self {
+        Self {
+            first_id,
+            color_count,
+            pattern_color_count,
+            per_line,
+            next: 0,
+        }
+    }
+
+    /// recolor replaces the ids of the ranges of a line that are below pattern_color_count by the
+    /// ids of the next colors.
+    pub fn recolor(&mut self, ranges: &mut [RangeWithId]) {
+        if self.per_line {
+            self.next = 0;
+        }
+        if self.color_count == 0 {
+            return;
+        }
+        for r in ranges
+            .iter_mut()
+            .filter(|r| r.id < self.pattern_color_count)
+        {
+            r.id = self.first_id + self.next;
+            self.next = (self.next + 1) % self.color_count;
+        }
+    }
+}
+
+/// split_into_chars replaces every range by one range per character. The ids of the new ranges
+/// are determined by char_id, which is passed the id of the original range and the index of the
+/// character within it.
+pub fn split_into_chars(
+    line: &str,
+    ranges: &mut Vec<RangeWithId>,
+    char_id: impl Fn(usize, usize) -> usize,
+) {
+    let mut split = Vec::with_capacity(ranges.len());
+    for r in ranges.iter() {
+        for (i, (idx, c)) in line[r.start_idx..r.end_idx].char_indices().enumerate() {
+            let start_idx = r.start_idx + idx;
+            split.push(RangeWithId {
+                start_idx,
+                end_idx: start_idx + c.len_utf8(),
+                id: char_id(r.id, i),
+            });
+        }
+    }
+    *ranges = split;
+}
+
+/// colorize inserts the escape sequences for the ranges into s. Ranges with ids below
+/// pattern_color_count get their color from colors, those with higher ids use the corresponding
+/// reserved color. No reset is emitted between adjacent ranges that end up with the same color.
+pub fn colorize(
+    s: &mut String,
+    colors: &[ColorSet],
+    reserved_colors: &[ColorSet],
+    ranges: &[RangeWithId],
+    pattern_color_count: usize,
+) {
+    colorize_ranges(
+        s,
+        colors,
+        reserved_colors,
+        ranges,
+        pattern_color_count,
+        false,
+    );
+}
+
+/// colorize_minimal is like colorize, but does not turn off a color when the next range directly
+/// follows and its color turns off the same way, i.e. replaces the previous color anyway. This
+/// saves escape sequences when differently colored ranges are adjacent, but must not be used with
+/// markup, whose tags need to be closed.
+pub fn colorize_minimal(
+    s: &mut String,
+    colors: &[ColorSet],
+    reserved_colors: &[ColorSet],
+    ranges: &[RangeWithId],
+    pattern_color_count: usize,
+) {
+    colorize_ranges(
+        s,
+        colors,
+        reserved_colors,
+        ranges,
+        pattern_color_count,
+        true,
+    );
+}
+
+fn colorize_ranges(
+    s: &mut String,
+    colors: &[ColorSet],
+    reserved_colors: &[ColorSet],
+    ranges: &[RangeWithId],
+    pattern_color_count: usize,
+    minimal: bool,
+) {
+    let mut colorized = String::with_capacity(s.len() + ranges.len() * 10);
+    let mut last_end_idx = 0;
+    let mut open_color: Option<&ColorSet> = None;
+    for r in ranges {
+        let color = if r.id >= pattern_color_count {
+            &reserved_colors[r.id - pattern_color_count]
+        } else {
+            &colors[color_index(r.id, pattern_color_count, colors.len())]
+        };
+        if let Some(open) = open_color.take() {
+            if r.start_idx == last_end_idx && open == color {
+                // keep the color on
+                colorized.push_str(&s[last_end_idx..r.end_idx]);
+                last_end_idx = r.end_idx;
+                open_color = Some(open);
+                continue;
+            }
+            if !(minimal && r.start_idx == last_end_idx && open.off == color.off) {
+                colorized.push_str(&open.off);
+            }
+        }
+        colorized.push_str(&s[last_end_idx..r.start_idx]);
+        colorized.push_str(&color.on);
+        colorized.push_str(&s[r.start_idx..r.end_idx]);
+        last_end_idx = r.end_idx;
+        open_color = Some(color);
+    }
+    if let Some(open) = open_color {
+        colorized.push_str(&open.off);
+    }
+    colorized.push_str(&s[last_end_idx..]);
+    *s = colorized;
+}
+
+/// trim_line returns the line without leading and trailing whitespace, together with the
+/// byte offset of the trimmed content within the original line.
+pub fn trim_line(line: &str) -> (usize, &str) {
+    let trimmed_start = line.trim_start();
+    (line.len() - trimmed_start.len(), trimmed_start.trim_end())
+}
+
+/// LineSelector decides which lines, and which parts of them, are matched against the patterns,
+/// like --ignore-lines, --only-lines, --markers, --trim and --after-anchor do.
+#[derive(Clone, Debug, Default)]
+pub struct LineSelector {
+    /// Lines matching this are not highlighted.
+    pub ignore_lines: Option<Regex>,
+    /// Only lines matching this are highlighted.
+    pub only_lines: Option<Regex>,
+    /// Whether marker lines turn highlighting on and off, see toggle_marker.
+    pub markers: bool,
+    /// Whether a marker line turned highlighting off.
+    pub off: bool,
+    /// Whether to match the lines without their leading and trailing whitespace.
+    pub trim: bool,
+    /// Only the part of the lines after the first match of this is matched.
+    pub anchor: Option<Regex>,
+}
+
+impl LineSelector {
+    /// toggle returns whether the line is a marker line, which is not printed, turning
+    /// highlighting of the following lines on or off.
+    pub fn toggle(&mut self, line: &str) -> bool {
+        let marker = self.markers.then(|| toggle_marker(line)).flatten();
+        if let Some(on) = marker {
+            self.off = !on;
+        }
+        marker.is_some()
+    }
+
+    /// highlights returns whether the line is to be highlighted.
+    pub fn highlights(&self, line: &str) -> bool {
+        !self.off
+            && !self
+                .ignore_lines
+                .as_ref()
+                .is_some_and(|re| re.is_match(line))
+            && self.only_lines.as_ref().is_none_or(|re| re.is_match(line))
+    }
+
+    /// text returns the part of a highlighted line to match against the patterns, together with
+    /// its byte offset within the line, or None if the line does not have the anchor.
+    pub fn text<'a>(&self, line: &'a str) -> Option<(usize, &'a str)> {
+        let (offset, text) = if self.trim {
+            trim_line(line)
+        } else {
+            (0, line)
+        };
+        match &self.anchor {
+            Some(anchor) => {
+                let (anchor_offset, text) = after_anchor(text, anchor)?;
+                Some((offset + anchor_offset, text))
+            }
+            None => Some((offset, text)),
+        }
+    }
+}
+
+/// toggle_marker returns whether the line is a `#colorexp:on` or `#colorexp:off` marker, which
+/// turns highlighting of the following lines on or off, or None if it is a regular line.
+pub fn toggle_marker(line: &str) -> Option<bool> {
+    match line.trim() {
+        "#colorexp:on" => Some(true),
+        "#colorexp:off" => Some(false),
+        _ => None,
+    }
+}
+
+// single or double quoted strings, which may contain escaped quotes
+static QUOTED: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#""((?:[^"\\]|\\.)*)"|'((?:[^'\\]|\\.)*)'"#).unwrap());
+
+/// match_in_quotes is like match_line_into, but only matches the regexps within the quoted
+/// strings of the line. The quotes themselves are not part of the strings.
+pub fn match_in_quotes(
+    line: &str,
+    regexps: &[Regex],
+    options: &MatchOptions,
+    ranges: &mut Vec<RangeWithId>,
+) {
+    ranges.clear();
+    let mut quoted_ranges = Vec::new();
+    for caps in QUOTED.captures_iter(line) {
+        let Some(quoted) = caps.get(1).or_else(|| caps.get(2)) else {
+            continue;
+        };
+        match_line_into(quoted.as_str(), regexps, options, &mut quoted_ranges);
+        inc_ranges(&mut quoted_ranges, quoted.start());
+        ranges.extend_from_slice(&quoted_ranges);
+    }
+}
+
+/// after_anchor returns the part of the line following the first match of the anchor, together
+/// with the byte offset of that part within the line, or None if the anchor does not match.
+pub fn after_anchor<'a>(line: &'a str, anchor: &Regex) -> Option<(usize, &'a str)> {
+    anchor.find(line).map(|m| (m.end(), &line[m.end()..]))
+}
+
+pub fn inc_ranges(ranges: &mut [RangeWithId], inc: usize) {
+    let ranges_len = ranges.len();
+    for i in 0..ranges_len {
+        let r = unsafe { ranges.get_unchecked_mut(i) };
+        r.start_idx += inc;
+        r.end_idx += inc;
+    }
+}
+
+/// compile_patterns compiles the given patterns in reverse order, so that the last given
+/// pattern that matches takes precedence.
+pub fn compile_patterns(
+    patterns: &[String],
+    fixed_strings: bool,
+    ignore_case: bool,
+) -> Result<Vec<Regex>> {
+    patterns
+        .iter()
+        .rev()
+        .map(|p| compile_pattern(p, fixed_strings, ignore_case))
+        .collect()
+}
+
+/// compile_pattern compiles a single pattern, taken literally if fixed_strings is set.
+pub fn compile_pattern(pattern: &str, fixed_strings: bool, ignore_case: bool) -> Result<Regex> {
+    let pat: std::borrow::Cow<'_, str> = if fixed_strings {
+        escape(pattern).into()
+    } else {
+        pattern.into()
+    };
+    RegexBuilder::new(&pat)
+        .case_insensitive(ignore_case)
+        .build()
+        .map_err(|source| ColorexpError::InvalidPattern {
+            pattern: pattern.to_string(),
+            source,
+        })
+}
+
+/// word_list_pattern returns a pattern matching any of the given words literally. Longer words
+/// take precedence over words they start with. An empty list results in a pattern that never
+/// matches.
+pub fn word_list_pattern(words: &[String]) -> String {
+    if words.is_empty() {
+        return r"\b\B".to_string();
+    }
+    let mut words = words.iter().map(|w| escape(w)).collect::<Vec<_>>();
+    words.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+    words.dedup();
+    words.join("|")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn r(start_idx: usize, end_idx: usize, id: usize) -> RangeWithId {
+        RangeWithId {
+            start_idx,
+            end_idx,
+            id,
+        }
+    }
+
+    #[rstest]
+    #[case::before_1(
+        vec![r(5, 8, 1)],
+        r(3, 5, 2),
+        vec![r(3, 5, 2), r(5, 8, 1)],
+    )]
+    #[case::before_2(
+        vec![r(5, 8, 1)],
+        r(3, 4, 2),
+        vec![r(3, 4, 2), r(5, 8, 1)],
+    )]
+    #[case::after_1(
+        vec![r(1, 3, 0)],
+        r(3, 5, 2),
+        vec![r(1, 3, 0), r(3, 5, 2)],
+    )]
+    #[case::after_2(
+        vec![r(1, 3, 0)],
+        r(4, 5, 2),
+        vec![r(1, 3, 0), r(4, 5, 2)],
+    )]
+    #[case::in_between_1(
+        vec![r(1, 3, 0), r(5, 8, 1)],
+        r(3, 5, 2),
+        vec![r(1, 3, 0), r(3, 5, 2), r(5, 8, 1)],
+    )]
+    #[case::in_between_2(
+        vec![r(1, 3, 0), r(5, 8, 1)],
+        r(3, 4, 2),
+        vec![r(1, 3, 0), r(3, 4, 2), r(5, 8, 1)],
+    )]
+    #[case::in_between_3(
+        vec![r(1, 3, 0), r(5, 8, 1)],
+        r(4, 5, 2),
+        vec![r(1, 3, 0), r(4, 5, 2), r(5, 8, 1)],
+    )]
+    #[case::partial_overlap(
+        vec![r(1, 3, 0), r(5, 8, 1)],
+        r(2, 6, 2),
+        vec![r(1, 3, 0), r(3, 5, 2), r(5, 8, 1)],
+    )]
+    #[case::full_overlap(
+        vec![r(1, 3, 0), r(5, 8, 1)],
+        r(6, 7, 2),
+        vec![r(1, 3, 0), r(5, 8, 1)],
+    )]
+    #[case::overlap_and_extend(
+        vec![r(1, 5, 0), r(10, 15, 1)],
+        r(3, 12, 2),
+        vec![r(1, 5, 0), r(5, 10, 2), r(10, 15, 1)],
+    )]
+    fn test_add_range(
+        #[case] existing: Vec<RangeWithId>,
+        #[case] new_range: RangeWithId,
+        #[case] expected: Vec<RangeWithId>,
+    ) {
+        let mut actual = existing.clone();
+        add_range(&mut actual, new_range);
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_match_line() {
+        let regexps = vec![
+            RegexBuilder::new("t")
+                .case_insensitive(false)
+                .build()
+                .unwrap(),
+        ];
+        let ranges = match_line("test", &regexps, &MatchOptions::default());
+        assert_eq!(
+            ranges,
+            vec![
+                RangeWithId {
+                    start_idx: 0,
+                    end_idx: 1,
+                    id: 0
+                },
+                RangeWithId {
+                    start_idx: 3,
+                    end_idx: 4,
+                    id: 0
+                },
+            ]
+        );
+    }
+
+    #[rstest]
+    #[case::overlaps("aa", "aaaa", vec![r(0, 2, 0), r(2, 3, 0), r(3, 4, 0)])]
+    #[case::one_char_apart("aa", "aaa", vec![r(0, 2, 0), r(2, 3, 0)])]
+    #[case::multibyte("ää", "äää", vec![r(0, 4, 0), r(4, 6, 0)])]
+    #[case::anchored("^a", "aa", vec![r(0, 1, 0)])]
+    #[case::empty_matches("x*", "ab", vec![r(0, 0, 0), r(1, 1, 0), r(2, 2, 0)])]
+    fn test_match_line_overlapping(
+        #[case] pattern: &str,
+        #[case] line: &str,
+        #[case] expected: Vec<RangeWithId>,
+    ) {
+        let regexps = compile_patterns(&[pattern.into()], false, false).unwrap();
+        let options = MatchOptions {
+            overlapping: true,
+            ..MatchOptions::default()
+        };
+        assert_eq!(match_line(line, &regexps, &options), expected);
+    }
+
+    #[rstest]
+    #[case::full_match(r"\w+", vec![r(2, 4, 0), r(5, 8, 0)])]
+    #[case::groups(r"(\w)(\w*)", vec![r(6, 8, 0)])]
+    fn test_match_line_min_length(#[case] pattern: &str, #[case] expected: Vec<RangeWithId>) {
+        let regexps = compile_patterns(&[pattern.into()], false, false).unwrap();
+        let options = MatchOptions {
+            min_length: 2,
+            ..MatchOptions::default()
+        };
+        assert_eq!(match_line("a bc def", &regexps, &options), expected);
+    }
+
+    #[test]
+    fn test_match_line_not_overlapping_by_default() {
+        let regexps = compile_patterns(&["aba".into()], false, false).unwrap();
+        let line = "ababa";
+        assert_eq!(
+            match_line(line, &regexps, &MatchOptions::default()),
+            vec![r(0, 3, 0)]
+        );
+        let options = MatchOptions {
+            overlapping: true,
+            ..MatchOptions::default()
+        };
+        assert_eq!(
+            match_line(line, &regexps, &options),
+            vec![r(0, 3, 0), r(3, 5, 0)]
+        );
+    }
+
+    #[rstest]
+    #[case::untrimmed("abc", (0, "abc"))]
+    #[case::leading("  abc", (2, "abc"))]
+    #[case::trailing("abc \t", (0, "abc"))]
+    #[case::both("\t abc  ", (2, "abc"))]
+    #[case::blank("   ", (3, ""))]
+    fn test_trim_line(#[case] line: &str, #[case] expected: (usize, &str)) {
+        assert_eq!(trim_line(line), expected);
+    }
+
+    #[rstest]
+    #[case::no_selection(None, None, vec![true, true, true])]
+    #[case::ignore_lines(Some("^#"), None, vec![true, false, true])]
+    #[case::only_lines(None, Some("id="), vec![true, false, false])]
+    #[case::both(Some("^#"), Some("a"), vec![true, false, true])]
+    fn test_line_selector_highlights(
+        #[case] ignore: Option<&str>,
+        #[case] only: Option<&str>,
+        #[case] expected: Vec<bool>,
+    ) {
+        let selector = LineSelector {
+            ignore_lines: ignore.map(|re| Regex::new(re).unwrap()),
+            only_lines: only.map(|re| Regex::new(re).unwrap()),
+            ..LineSelector::default()
+        };
+        let highlighted = ["id=a", "# id", "aid"]
+            .iter()
+            .map(|line| selector.highlights(line))
+            .collect::<Vec<_>>();
+        assert_eq!(highlighted, expected);
+    }
+
+    #[rstest]
+    #[case::double(r#"a "a b" a"#, vec![r(3, 4, 0)])]
+    #[case::single("a 'b a' a", vec![r(5, 6, 0)])]
+    #[case::escaped_quote(r#""\" a" a"#, vec![r(4, 5, 0)])]
+    #[case::several(r#""a" a "xa""#, vec![r(1, 2, 0), r(8, 9, 0)])]
+    #[case::unterminated(r#"a "a"#, vec![])]
+    #[case::no_quotes("a a", vec![])]
+    fn test_match_in_quotes(#[case] line: &str, #[case] expected: Vec<RangeWithId>) {
+        let regexps = vec![Regex::new("a").unwrap()];
+        let mut ranges = vec![r(0, 1, 5)];
+        match_in_quotes(line, &regexps, &MatchOptions::default(), &mut ranges);
+        assert_eq!(ranges, expected);
+    }
+
+    #[test]
+    fn test_match_in_quotes_anchored_pattern() {
+        // patterns see the quoted strings as if they were lines
+        let regexps = vec![Regex::new("^b$").unwrap()];
+        let mut ranges = Vec::new();
+        match_in_quotes(
+            r#"b "b" "ab""#,
+            &regexps,
+            &MatchOptions::default(),
+            &mut ranges,
+        );
+        assert_eq!(ranges, vec![r(3, 4, 0)]);
+    }
+
+    #[rstest]
+    #[case::duplicate("id=7 id=8 id=7", vec![r(3, 4, 9), r(13, 14, 9)])]
+    #[case::unique("id=1 id=2", vec![])]
+    #[case::three_times("id=1 id=1 id=1", vec![r(3, 4, 9), r(8, 9, 9), r(13, 14, 9)])]
+    fn test_keep_duplicates(#[case] line: &str, #[case] expected: Vec<RangeWithId>) {
+        let regexps = vec![Regex::new(r"id=(\d+)").unwrap()];
+        let mut ranges = match_line(line, &regexps, &MatchOptions::default());
+        keep_duplicates(line, &mut ranges, 9);
+        assert_eq!(ranges, expected);
+    }
+
+    #[rstest]
+    #[case::no_groups(&["a", "b"], false, &["a", "b"])]
+    #[case::groups_same_color(&["(a)(b)", "c"], false, &["(a)(b)", "c"])]
+    #[case::vary(&["(a)(b)", "c"], true, &["(a)(b)#1", "(a)(b)#2", "c"])]
+    fn test_color_keys(
+        #[case] patterns: &[&str],
+        #[case] vary_group_colors: bool,
+        #[case] expected: &[&str],
+    ) {
+        let patterns = patterns.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        let regexps = compile_patterns(&patterns, false, false).unwrap();
+        let keys = color_keys(&patterns, &regexps, vary_group_colors);
+        assert_eq!(keys, expected);
+        assert_eq!(keys.len(), pattern_color_count(&regexps, vary_group_colors));
+    }
+
+    #[rstest]
+    #[case::on("#colorexp:on", Some(true))]
+    #[case::off("  #colorexp:off ", Some(false))]
+    #[case::regular("#colorexp:offline", None)]
+    #[case::inline("x #colorexp:off", None)]
+    fn test_toggle_marker(#[case] line: &str, #[case] expected: Option<bool>) {
+        assert_eq!(toggle_marker(line), expected);
+    }
+
+    #[rstest]
+    #[case::markers(true, vec![("a1", true), ("a2", false), ("a3", false), ("a4", true)])]
+    #[case::no_markers(
+        false,
+        vec![
+            ("a1", true),
+            ("#colorexp:off", true),
+            ("a2", true),
+            ("#colorexp:off", true),
+            ("a3", true),
+            ("#colorexp:on", true),
+            ("a4", true),
+        ]
+    )]
+    fn test_toggle_highlighting(#[case] markers: bool, #[case] expected: Vec<(&str, bool)>) {
+        let input = [
+            "a1",
+            "#colorexp:off",
+            "a2",
+            "#colorexp:off",
+            "a3",
+            "#colorexp:on",
+            "a4",
+        ];
+        let mut selector = LineSelector {
+            markers,
+            ..LineSelector::default()
+        };
+        let mut output = Vec::new();
+        for line in input {
+            if !selector.toggle(line) {
+                output.push((line, selector.highlights(line)));
+            }
+        }
+        assert_eq!(output, expected);
+    }
+
+    #[rstest]
+    #[case::anchor("info: a: b", Some((5, " a: b")))]
+    #[case::anchor_at_end("info:", Some((5, "")))]
+    #[case::no_anchor("info - a", None)]
+    fn test_after_anchor(#[case] line: &str, #[case] expected: Option<(usize, &str)>) {
+        assert_eq!(after_anchor(line, &Regex::new(":").unwrap()), expected);
+    }
+
+    #[rstest]
+    #[case::anchor(false, "ab: ab", Some((4, "ab")))]
+    #[case::no_anchor(false, "ab ab", None)]
+    // the anchor is looked for in the trimmed line, and the offset counts the whitespace
+    #[case::trimmed(true, "  ab: ab  ", Some((6, "ab")))]
+    fn test_line_selector_text_after_anchor(
+        #[case] trim: bool,
+        #[case] line: &str,
+        #[case] expected: Option<(usize, &str)>,
+    ) {
+        let selector = LineSelector {
+            trim,
+            anchor: Some(Regex::new(": ").unwrap()),
+            ..LineSelector::default()
+        };
+        assert_eq!(selector.text(line), expected);
+    }
+
+    #[rstest]
+    #[case::capped("aaaaaaaa", 3, vec![r(0, 1, 0), r(1, 2, 0), r(2, 3, 0)])]
+    #[case::below_cap("aa", 3, vec![r(0, 1, 0), r(1, 2, 0)])]
+    #[case::zero("aa", 0, vec![])]
+    fn test_max_ranges(
+        #[case] line: &str,
+        #[case] max_ranges: usize,
+        #[case] expected: Vec<RangeWithId>,
+    ) {
+        let regexps = vec![Regex::new("a").unwrap()];
+        let options = MatchOptions {
+            max_ranges: Some(max_ranges),
+            ..MatchOptions::default()
+        };
+        assert_eq!(match_line(line, &regexps, &options), expected);
+    }
+
+    #[test]
+    fn test_max_ranges_patterns_and_groups() {
+        // the pattern taking precedence is matched first, and the cap applies to groups
+        let regexps = compile_patterns(&["b".into(), "(a)(a)".into()], false, false).unwrap();
+        let options = MatchOptions {
+            max_ranges: Some(3),
+            ..MatchOptions::default()
+        };
+        assert_eq!(
+            match_line("baaaab", &regexps, &options),
+            vec![r(1, 2, 0), r(2, 3, 0), r(3, 4, 0)]
+        );
+    }
+
+    #[rstest]
+    #[case::per_line(true, vec![vec![r(0, 1, 5), r(2, 3, 6)], vec![r(0, 1, 5), r(2, 3, 6), r(4, 5, 7)]])]
+    #[case::stream(false, vec![vec![r(0, 1, 5), r(2, 3, 6)], vec![r(0, 1, 7), r(2, 3, 5), r(4, 5, 6)]])]
+    fn test_color_cycle(#[case] per_line: bool, #[case] expected: Vec<Vec<RangeWithId>>) {
+        let regexps = compile_patterns(&["a".into(), "b".into()], false, false).unwrap();
+        let mut cycle = ColorCycle::new(5, 3, 2, per_line);
+        let lines = ["b a", "a b a"];
+        let recolored = lines
+            .iter()
+            .map(|line| {
+                let mut ranges = match_line(line, &regexps, &MatchOptions::default());
+                cycle.recolor(&mut ranges);
+                ranges
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(recolored, expected);
+    }
+
+    #[test]
+    fn test_color_cycle_keeps_reserved_ids() {
+        let mut cycle = ColorCycle::new(5, 3, 2, true);
+        let mut ranges = vec![r(0, 1, 4), r(1, 2, 1)];
+        cycle.recolor(&mut ranges);
+        assert_eq!(ranges, vec![r(0, 1, 4), r(1, 2, 5)]);
+    }
+
+    #[rstest]
+    #[case::alternating(&[r"\(", r"\)"], "( ( ) )", false, vec![r(0, 1, 1), r(2, 3, 11), r(4, 5, 0), r(6, 7, 10)])]
+    #[case::third_like_first(&["a"], "a a a", false, vec![r(0, 1, 0), r(2, 3, 10), r(4, 5, 0)])]
+    #[case::groups(&["(a)(b)"], "ab ab", true, vec![r(0, 1, 1), r(1, 2, 0), r(3, 4, 11), r(4, 5, 10)])]
+    fn test_alternate(
+        #[case] patterns: &[&str],
+        #[case] line: &str,
+        #[case] vary_group_colors: bool,
+        #[case] expected: Vec<RangeWithId>,
+    ) {
+        let patterns = patterns.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        let regexps = compile_patterns(&patterns, false, false).unwrap();
+        let options = MatchOptions {
+            vary_group_colors,
+            alternate_offset: Some(10),
+            ..MatchOptions::default()
+        };
+        assert_eq!(match_line(line, &regexps, &options), expected);
+    }
+
+    #[rstest]
+    #[case::second(2, "a1 a2 a3", vec![r(3, 5, 1)])]
+    #[case::first(1, "a1 a2 a3", vec![r(0, 2, 1)])]
+    #[case::too_few(4, "a1 a2 a3", vec![])]
+    // every pattern is counted on its own
+    #[case::per_pattern(2, "a1 b1 a2 b2", vec![r(6, 8, 1), r(9, 11, 0)])]
+    fn test_nth(#[case] nth: usize, #[case] line: &str, #[case] expected: Vec<RangeWithId>) {
+        let regexps = compile_patterns(&[r"a\d".into(), r"b\d".into()], false, false).unwrap();
+        let options = MatchOptions {
+            nth: Some(nth),
+            ..MatchOptions::default()
+        };
+        assert_eq!(match_line(line, &regexps, &options), expected);
+    }
+
+    #[test]
+    fn test_per_pattern_color() {
+        // the options used by --per-pattern-color: one solid color per pattern, groups or not
+        let patterns = vec![r"(\w+)=(\w+)".into(), r"(\d)(\d)".into()];
+        let regexps = compile_patterns(&patterns, false, false).unwrap();
+        let options = MatchOptions {
+            full_match_highlight: true,
+            ..MatchOptions::default()
+        };
+        assert_eq!(pattern_color_count(&regexps, options.vary_group_colors), 2);
+        assert_eq!(
+            match_line("12 key=value", &regexps, &options),
+            vec![r(0, 2, 0), r(3, 12, 1)]
+        );
+    }
+
+    fn longest(patterns: &[&str], overlapping: bool) -> (Vec<Regex>, MatchOptions) {
+        let patterns = patterns.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        let regexps = compile_patterns(&patterns, false, false).unwrap();
+        let options = MatchOptions {
+            overlapping,
+            longest_match: Some(LongestMatches::new(&regexps, false)),
+            ..MatchOptions::default()
+        };
+        (regexps, options)
+    }
+
+    #[rstest]
+    #[case::alternation(&["ab|abc"], "abcd", vec![r(0, 3, 0)])]
+    #[case::first_is_longest(&["abc|ab"], "abcd", vec![r(0, 3, 0)])]
+    #[case::repeated(&["ab|abc"], "abcab abc", vec![r(0, 3, 0), r(3, 5, 0), r(6, 9, 0)])]
+    #[case::lazy(&["a+?"], "aaa b", vec![r(0, 3, 0)])]
+    #[case::word_boundary(&[r"ab|abc\b"], "abc abcd", vec![r(0, 3, 0), r(4, 6, 0)])]
+    #[case::groups(&["(a)b|(a)(b)(c)"], "abc", vec![r(0, 1, 0), r(1, 2, 0), r(2, 3, 0)])]
+    #[case::per_pattern(&["b|bcd", "ab"], "abcd", vec![r(0, 2, 0), r(2, 4, 1)])]
+    fn test_match_line_longest(
+        #[case] patterns: &[&str],
+        #[case] line: &str,
+        #[case] expected: Vec<RangeWithId>,
+    ) {
+        let (regexps, options) = longest(patterns, false);
+        assert_eq!(match_line(line, &regexps, &options), expected);
+    }
+
+    #[test]
+    fn test_match_line_longest_overlapping() {
+        let (regexps, options) = longest(&["ab|abc", "bc"], true);
+        // within a pattern, the longest matches win; across patterns, precedence still decides
+        assert_eq!(
+            match_line("abcd", &regexps, &options),
+            vec![r(0, 1, 1), r(1, 3, 0)]
+        );
+        let (regexps, options) = longest(&["a|aa"], true);
+        assert_eq!(
+            match_line("aaa", &regexps, &options),
+            vec![r(0, 2, 0), r(2, 3, 0)]
+        );
+    }
+
+    #[test]
+    fn test_match_line_longest_ignore_case() {
+        let patterns = vec!["ab|abc".to_string()];
+        let regexps = compile_patterns(&patterns, false, true).unwrap();
+        let options = MatchOptions {
+            longest_match: Some(LongestMatches::new(&regexps, true)),
+            ..MatchOptions::default()
+        };
+        assert_eq!(match_line("ABCD", &regexps, &options), vec![r(0, 3, 0)]);
+    }
+
+    #[rstest]
+    #[case::vary_group_colors(true, false, vec![0..2, 2..3, 3..4])]
+    #[case::same_group_colors(false, false, vec![0..1, 1..2, 2..3])]
+    #[case::full_match_highlight(true, true, vec![0..1, 1..2, 2..3])]
+    fn test_regexp_ids(
+        #[case] vary_group_colors: bool,
+        #[case] full_match_highlight: bool,
+        #[case] expected: Vec<Range<usize>>,
+    ) {
+        let patterns = vec!["x".into(), "(y)".into(), "(a)(b)".into()];
+        let regexps = compile_patterns(&patterns, false, false).unwrap();
+        let options = MatchOptions {
+            vary_group_colors,
+            full_match_highlight,
+            ..MatchOptions::default()
+        };
+        assert_eq!(regexp_ids(&regexps, &options), expected);
+        // the ids are those of the ranges, the regexps being compiled in reverse order
+        let line = "ab y x";
+        for r in match_line(line, &regexps, &options) {
+            let regexp_idx = match &line[r.start_idx..r.end_idx] {
+                "x" => 2,
+                "y" => 1,
+                _ => 0,
+            };
+            assert!(expected[regexp_idx].contains(&r.id), "{r:?}");
+        }
+    }
+
+    #[rstest]
+    #[case::shared_color(0..1, 2, 0)]
+    #[case::first_group(0..3, 1, 2)]
+    #[case::last_group(0..3, 3, 0)]
+    #[case::full_match(0..3, 0, 2)]
+    #[case::offset(5..7, 2, 5)]
+    fn test_group_id(#[case] ids: Range<usize>, #[case] g_idx: usize, #[case] expected: usize) {
+        assert_eq!(group_id(&ids, g_idx), expected);
+    }
+
+    #[test]
+    fn test_match_line_into_reuses_ranges() {
+        let regexps = vec![Regex::new("a").unwrap()];
+        let options = MatchOptions::default();
+        let mut ranges = vec![r(0, 9, 7)];
+        match_line_into("xa", &regexps, &options, &mut ranges);
+        assert_eq!(ranges, vec![r(1, 2, 0)]);
+        let capacity = ranges.capacity();
+        match_line_into("b", &regexps, &options, &mut ranges);
+        assert_eq!(ranges, vec![]);
+        assert_eq!(ranges.capacity(), capacity);
+        match_line_into("aa", &regexps, &options, &mut ranges);
+        assert_eq!(ranges, match_line("aa", &regexps, &options));
+    }
+
+    #[rstest]
+    #[case::default(MatchOptions::default())]
+    #[case::vary_group_colors(MatchOptions { vary_group_colors: true, ..MatchOptions::default() })]
+    #[case::overlapping(MatchOptions { overlapping: true, ..MatchOptions::default() })]
+    #[case::max_ranges(MatchOptions { max_ranges: Some(3), ..MatchOptions::default() })]
+    #[case::alternate(MatchOptions { alternate_offset: Some(20), ..MatchOptions::default() })]
+    fn test_combined_patterns(#[case] options: MatchOptions) {
+        let patterns = ["abc", "bcd", r"(\d)(\d)", "x", "never", "a"].map(String::from);
+        let regexps = compile_patterns(&patterns, false, true).unwrap();
+        let combined = CombinedPatterns::new(&regexps, true).unwrap();
+        let mut ranges = Vec::new();
+        for line in ["abcd", "ABCD 12 x", "a x a 345", "none", "", "bcd abc x12"] {
+            combined.match_line_into(line, &regexps, &options, &mut ranges);
+            assert_eq!(ranges, match_line(line, &regexps, &options), "{line}");
+        }
+    }
+
+    #[test]
+    fn test_combined_patterns_single_regexp() {
+        let regexps = compile_patterns(&["a".into()], false, false).unwrap();
+        assert!(CombinedPatterns::new(&regexps, false).is_none());
+    }
+
+    #[rstest]
+    #[case::untrimmed(false, Some((0, "  ab  a")))]
+    #[case::trimmed(true, Some((2, "ab  a")))]
+    fn test_line_selector_text_trimmed(
+        #[case] trim: bool,
+        #[case] expected: Option<(usize, &str)>,
+    ) {
+        let selector = LineSelector {
+            trim,
+            ..LineSelector::default()
+        };
+        assert_eq!(selector.text("  ab  a"), expected);
+    }
+
+    #[rstest]
+    #[case::none(0, 0, vec![r(2, 3, 0), r(6, 7, 1)])]
+    #[case::before(1, 0, vec![r(1, 2, 0), r(2, 3, 0), r(5, 6, 1), r(6, 7, 1)])]
+    #[case::after(0, 1, vec![r(2, 3, 0), r(3, 4, 0), r(6, 7, 1), r(7, 8, 1)])]
+    #[case::clamped(5, 5, vec![r(0, 2, 0), r(2, 3, 0), r(3, 6, 0), r(6, 7, 1), r(7, 8, 0)])]
+    #[case::max(usize::MAX, usize::MAX, vec![r(0, 2, 0), r(2, 3, 0), r(3, 6, 0), r(6, 7, 1), r(7, 8, 0)])]
+    fn test_pad_ranges(
+        #[case] before: usize,
+        #[case] after: usize,
+        #[case] expected: Vec<RangeWithId>,
+    ) {
+        let mut ranges = vec![r(2, 3, 0), r(6, 7, 1)];
+        pad_ranges("abcdefgh", &mut ranges, before, after);
+        assert_eq!(ranges, expected);
+    }
+
+    #[test]
+    fn test_pad_ranges_char_boundaries() {
+        // 'ä' and 'ö' are two bytes long each
+        let line = "äxö";
+        let mut ranges = vec![r(2, 3, 0)];
+        pad_ranges(line, &mut ranges, 1, 1);
+        assert_eq!(ranges, vec![r(2, 3, 0)]);
+        pad_ranges(line, &mut ranges, 2, 2);
+        assert_eq!(ranges, vec![r(0, 2, 0), r(2, 3, 0), r(3, 5, 0)]);
+    }
+
+    #[rstest]
+    #[case::single_without_groups(&["a"], false)]
+    #[case::single_with_groups(&["(a)(b)"], true)]
+    #[case::single_with_non_capturing_group(&["(?:a)b"], false)]
+    #[case::multiple(&["(a)(b)", "c"], false)]
+    fn test_vary_group_colors_by_default(#[case] patterns: &[&str], #[case] expected: bool) {
+        let patterns = patterns.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        let regexps = compile_patterns(&patterns, false, false).unwrap();
+        assert_eq!(vary_group_colors_by_default(&regexps), expected);
+    }
+
+    #[rstest]
+    #[case::vary(true, vec![("year", 3), ("month", 2), ("level", 0)])]
+    #[case::same_color(false, vec![("year", 1), ("month", 1), ("level", 0)])]
+    fn test_named_group_ids(#[case] vary_group_colors: bool, #[case] expected: Vec<(&str, usize)>) {
+        let patterns = vec![
+            r"(?<year>\d{4})-(?<month>\d\d)-(\d\d)".into(),
+            r"(?<level>INFO)".into(),
+        ];
+        let regexps = compile_patterns(&patterns, false, false).unwrap();
+        let options = MatchOptions {
+            vary_group_colors,
+            ..MatchOptions::default()
+        };
+        assert_eq!(named_group_ids(&regexps, &options), expected);
+        // the ids are the ones match_line uses
+        let ranges = match_line("2024-01-02 INFO", &regexps, &options);
+        let year_id = ranges[0].id;
+        assert_eq!(year_id, expected[0].1);
+    }
+
+    #[test]
+    fn test_named_group_ids_full_match_highlight() {
+        let regexps = compile_patterns(&["(?<a>x)".into()], false, false).unwrap();
+        let options = MatchOptions {
+            full_match_highlight: true,
+            ..MatchOptions::default()
+        };
+        assert_eq!(named_group_ids(&regexps, &options), vec![]);
+    }
+
+    #[rstest]
+    #[case::same_color(false, vec![("a-b", 1), ("c", 0), ("d", 0)])]
+    #[case::vary_group_colors(true, vec![("a-b", 2), ("c", 1), ("d", 0)])]
+    fn test_match_line_full_match_regexps(
+        #[case] vary_group_colors: bool,
+        #[case] expected: Vec<(&str, usize)>,
+    ) {
+        // like --full-match-pattern 1, the first pattern is highlighted as a whole, while the
+        // second highlights its groups
+        let patterns = ["(a)-(b)".to_string(), "(c)-(d)".to_string()];
+        let regexps = compile_patterns(&patterns, false, false).unwrap();
+        let options = MatchOptions {
+            vary_group_colors,
+            full_match_regexps: vec!["(a)-(b)".to_string()],
+            ..MatchOptions::default()
+        };
+        let line = "a-b c-d";
+        let ranges = match_line(line, &regexps, &options);
+        assert_eq!(
+            ranges
+                .iter()
+                .map(|r| (&line[r.start_idx..r.end_idx], r.id))
+                .collect::<Vec<_>>(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_group_legend() {
+        let colors = [
+            ColorSet::new("<0>", "</0>"),
+            ColorSet::new("<1>", "</1>"),
+            ColorSet::new("<2>", "</2>"),
+        ];
+        let regexps =
+            compile_patterns(&["(?<key>\\w+)=(?<value>\\w+)".into()], false, false).unwrap();
+        let options = MatchOptions {
+            vary_group_colors: true,
+            ..MatchOptions::default()
+        };
+        let pattern_color_count = pattern_color_count(&regexps, true);
+        assert_eq!(
+            group_legend(&regexps, &options, &colors, pattern_color_count),
+            "<0>key</0> <1>value</1>"
+        );
+        let mut line = "a=b".to_string();
+        let ranges = match_line(&line, &regexps, &options);
+        colorize(&mut line, &colors, &[], &ranges, pattern_color_count);
+        assert_eq!(line, "<0>a</0>=<1>b</1>");
+    }
+
+    #[test]
+    fn test_vary_group_colors_without_groups() {
+        let regexps = compile_patterns(&["a".into()], false, false).unwrap();
+        let colors = [ColorSet::new("<0>", "</0>"), ColorSet::new("<1>", "</1>")];
+        let colorized = |vary_group_colors: bool| {
+            let mut line = "xaxa".to_string();
+            let options = MatchOptions {
+                vary_group_colors,
+                ..MatchOptions::default()
+            };
+            let ranges = match_line(&line, &regexps, &options);
+            let pattern_color_count = pattern_color_count(&regexps, vary_group_colors);
+            colorize(&mut line, &colors, &[], &ranges, pattern_color_count);
+            line
+        };
+        assert_eq!(colorized(true), "x<0>a</0>x<0>a</0>");
+        assert_eq!(colorized(true), colorized(false));
+    }
+
+    #[test]
+    fn test_colorize_reserved_colors() {
+        let colors = [ColorSet::new("<0>", "</0>"), ColorSet::new("<1>", "</1>")];
+        let mut reserved = ReservedColors::new(2);
+        assert_eq!(reserved.reserve(ColorSet::new("<r>", "</r>")), 2);
+        let mut line = "abc".to_string();
+        let ranges = vec![r(0, 1, 1), r(1, 2, 0), r(2, 3, 2)];
+        colorize(&mut line, &colors, &reserved.colors, &ranges, 2);
+        assert_eq!(line, "<0>a</0><1>b</1><r>c</r>");
+    }
+
+    #[rstest]
+    #[case::match_at_end(vec![r(2, 3, 0)], "ab<0>c</0>")]
+    #[case::merged_at_end(vec![r(1, 2, 0), r(2, 3, 2)], "a<0>bc</0>")]
+    #[case::reserved_at_end(vec![r(0, 1, 0), r(1, 3, 3)], "<0>a</0><r>bc</r>")]
+    #[case::whole_line(vec![r(0, 3, 1)], "<1>abc</1>")]
+    fn test_colorize_resets_at_end_of_line(
+        #[case] ranges: Vec<RangeWithId>,
+        #[case] expected: &str,
+    ) {
+        let colors = [ColorSet::new("<0>", "</0>"), ColorSet::new("<1>", "</1>")];
+        let reserved = [ColorSet::new("<r>", "</r>")];
+        let mut line = "abc".to_string();
+        colorize(&mut line, &colors, &reserved, &ranges, 3);
+        assert_eq!(line, expected);
+    }
+
+    #[rstest]
+    #[case::gaps(vec![r(0, 1, 0), r(2, 3, 1), r(4, 5, 0)], "<0>a</>b<1>c</>d<0>e</>", 6)]
+    #[case::adjacent(vec![r(0, 1, 0), r(1, 2, 1), r(2, 3, 0)], "<0>a<1>b<0>c</>de", 4)]
+    #[case::gaps_and_adjacent(vec![r(0, 1, 0), r(2, 3, 1), r(3, 4, 0)], "<0>a</>b<1>c<0>d</>e", 5)]
+    #[case::background(vec![r(0, 1, 0), r(1, 2, 2), r(2, 3, 1)], "<0>a</><b>b</b><1>c</>de", 6)]
+    fn test_colorize_minimal(
+        #[case] ranges: Vec<RangeWithId>,
+        #[case] expected: &str,
+        #[case] expected_escapes: usize,
+    ) {
+        // colors 0 and 1 turn off the same way, the background color b differently
+        let colors = [
+            ColorSet::new("<b>", "</b>"),
+            ColorSet::new("<1>", "</>"),
+            ColorSet::new("<0>", "</>"),
+        ];
+        let mut line = "abcde".to_string();
+        colorize_minimal(&mut line, &colors, &[], &ranges, 3);
+        assert_eq!(line, expected);
+        assert_eq!(line.matches('<').count(), expected_escapes);
+
+        let mut full = "abcde".to_string();
+        colorize(&mut full, &colors, &[], &ranges, 3);
+        assert!(full.matches('<').count() >= expected_escapes);
+    }
+
+    #[rstest]
+    #[case::gap(vec![r(0, 1, 0), r(2, 3, 0)], "<0>a</0>b<0>c</0>", 4)]
+    #[case::different_colors(vec![r(0, 1, 0), r(1, 2, 1)], "<0>a</0><1>b</1>c", 4)]
+    #[case::same_id(vec![r(0, 1, 0), r(1, 2, 0)], "<0>ab</0>c", 2)]
+    #[case::same_color(vec![r(0, 1, 0), r(1, 2, 2), r(2, 3, 0)], "<0>abc</0>", 2)]
+    #[case::empty_range(vec![r(0, 0, 0), r(0, 1, 1)], "<0></0><1>a</1>bc", 4)]
+    fn test_colorize_adjacent_ranges(
+        #[case] ranges: Vec<RangeWithId>,
+        #[case] expected: &str,
+        #[case] expected_escapes: usize,
+    ) {
+        // ids 0 and 2 both resolve to color 0
+        let colors = [ColorSet::new("<0>", "</0>"), ColorSet::new("<1>", "</1>")];
+        let mut line = "abc".to_string();
+        colorize(&mut line, &colors, &[], &ranges, 3);
+        assert_eq!(line, expected);
+        assert_eq!(line.matches('<').count(), expected_escapes);
+        // without skipping the resets, every range gets its own pair of escapes
+        assert!(expected_escapes <= ranges.len() * 2);
+    }
+
+    #[rstest]
+    #[case::first_pattern(1, 2, 6, 0)]
+    #[case::second_pattern(0, 2, 6, 1)]
+    #[case::wraps(0, 8, 6, 1)]
+    fn test_color_index(
+        #[case] id: usize,
+        #[case] pattern_color_count: usize,
+        #[case] color_count: usize,
+        #[case] expected: usize,
+    ) {
+        assert_eq!(color_index(id, pattern_color_count, color_count), expected);
+    }
+
+    #[test]
+    fn test_split_into_chars() {
+        let line = "xabäy";
+        let mut ranges = vec![r(1, 5, 4), r(5, 6, 7)];
+        split_into_chars(line, &mut ranges, |id, i| id * 10 + i);
+        assert_eq!(
+            ranges,
+            vec![r(1, 2, 40), r(2, 3, 41), r(3, 5, 42), r(5, 6, 70)]
+        );
+    }
+
+    #[test]
+    fn test_split_into_chars_colors() {
+        let regexps = compile_patterns(&["abc".into()], false, false).unwrap();
+        let colors = [
+            ColorSet::new("<0>", "</0>"),
+            ColorSet::new("<1>", "</1>"),
+            ColorSet::new("<2>", "</2>"),
+        ];
+        let mut line = "abc".to_string();
+        let mut ranges = match_line(&line, &regexps, &MatchOptions::default());
+        split_into_chars(&line, &mut ranges, |_, i| i % colors.len());
+        assert_eq!(ranges.len(), 3);
+        colorize(&mut line, &[], &colors, &ranges, 0);
+        assert_eq!(line, "<0>a</0><1>b</1><2>c</2>");
+    }
+
+    #[test]
+    fn test_pattern_color_count() {
+        let regexps = compile_patterns(&["(a)(b)(c)".into(), "d".into()], false, false).unwrap();
+        assert_eq!(pattern_color_count(&regexps, false), 2);
+        assert_eq!(pattern_color_count(&regexps, true), 4);
+    }
+
+    #[test]
+    fn test_count_matches() {
+        let regexps =
+            compile_patterns(&["a".into(), "b".into(), "c".into()], false, false).unwrap();
+        let mut counts = vec![0; 3];
+        count_matches("abab", &regexps, &mut counts);
+        count_matches("a", &regexps, &mut counts);
+        assert_eq!(counts, vec![3, 2, 0]);
+    }
+
+    #[rstest]
+    #[case::matches(MatchOptions::default(), vec![2, 2])]
+    #[case::nth(MatchOptions { nth: Some(2), ..MatchOptions::default() }, vec![1, 1])]
+    #[case::min_length(MatchOptions { min_length: 2, ..MatchOptions::default() }, vec![1, 0])]
+    #[case::alternate(
+        MatchOptions { alternate_offset: Some(5), ..MatchOptions::default() },
+        vec![2, 2]
+    )]
+    fn test_count_ranges(#[case] options: MatchOptions, #[case] expected: Vec<usize>) {
+        let regexps = compile_patterns(&["a+".into(), "b".into()], false, false).unwrap();
+        let mut ranges = match_line("aa b a b", &regexps, &options);
+        // ids beyond those of the regexps are not counted
+        ranges.push(r(10, 11, 9));
+        let mut counts = vec![0; 2];
+        count_ranges(
+            &ranges,
+            &regexp_ids(&regexps, &options),
+            options.alternate_offset,
+            &mut counts,
+        );
+        assert_eq!(counts, expected);
+    }
+
+    #[test]
+    fn test_compile_patterns() {
+        let regexps = compile_patterns(&["a".into(), "b+".into()], false, false).unwrap();
+        assert_eq!(
+            regexps.iter().map(Regex::as_str).collect::<Vec<_>>(),
+            vec!["b+", "a"]
+        );
+
+        let regexps = compile_patterns(&["a(".into()], true, false).unwrap();
+        assert_eq!(regexps[0].as_str(), r"a\(");
+    }
+
+    #[test]
+    fn test_compile_pattern_check() {
+        let results = ["a+", "b(", "c"]
+            .iter()
+            .map(|p| compile_pattern(p, false, false).map(|re| re.as_str().to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(results[0].as_ref().unwrap(), "a+");
+        assert!(
+            matches!(&results[1], Err(ColorexpError::InvalidPattern { pattern, source })
+                if pattern == "b(" && source.to_string().contains("unclosed group")),
+            "{:?}",
+            results[1]
+        );
+        assert!(results[2].is_ok());
+        assert!(compile_pattern("b(", true, false).is_ok());
+    }
+
+    #[test]
+    fn test_compile_patterns_invalid_pattern() {
+        let err = compile_patterns(&["a".into(), "b(".into()], false, false).unwrap_err();
+        assert!(
+            matches!(err, ColorexpError::InvalidPattern { ref pattern, .. } if pattern == "b("),
+            "{err:?}"
+        );
+    }
+
+    #[rstest]
+    #[case::words(&["foo", "a.b"], r"a\.b|foo")]
+    #[case::prefix(&["err", "error"], "error|err")]
+    #[case::duplicates(&["x", "x"], "x")]
+    #[case::empty(&[], r"\b\B")]
+    fn test_word_list_pattern(#[case] words: &[&str], #[case] expected: &str) {
+        let words = words.iter().map(|w| w.to_string()).collect::<Vec<_>>();
+        assert_eq!(word_list_pattern(&words), expected);
+    }
+
+    #[test]
+    fn test_match_word_list() {
+        let words = ["error", "err", "denied"].map(String::from);
+        let regexps = compile_patterns(&[word_list_pattern(&words)], false, false).unwrap();
+        let line = "error: err access denied";
+        let matched = match_line(line, &regexps, &MatchOptions::default())
+            .iter()
+            .map(|r| &line[r.start_idx..r.end_idx])
+            .collect::<Vec<_>>();
+        assert_eq!(matched, vec!["error", "err", "denied"]);
+
+        let regexps = compile_patterns(&[word_list_pattern(&[])], false, false).unwrap();
+        assert_eq!(match_line(line, &regexps, &MatchOptions::default()), vec![]);
+    }
+}
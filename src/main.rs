@@ -1,10 +1,65 @@
-use anyhow::Result;
-use clap::{ArgAction, ArgGroup, Parser};
-use regex::{Regex, RegexBuilder, escape};
-use std::cmp::{max, min};
-use std::io;
-use std::io::BufRead;
+use anyhow::{Context, Result, bail};
+use clap::{ArgAction, ArgGroup, Parser, ValueEnum};
+use colorexp::aliases::Aliases;
+use colorexp::align::align_first_matches;
+use colorexp::brackets::BracketPairs;
+use colorexp::classify::{Classes, parse_class};
+use colorexp::context::{ELLIPSIS, trim_context};
+use colorexp::control::{self, show_control};
+use colorexp::dashboard::Dashboard;
+use colorexp::decode::{self, Decoder};
+use colorexp::escapes::{self, mark_malformed, sanitize, visible_width};
+use colorexp::extract::{extract, has_named_groups};
+use colorexp::filter::{FilterCommand, ReplaceCommand};
+use colorexp::groups::limit_group_depth;
+use colorexp::heatmap::{self, Heatmap, heatmap_colors};
+use colorexp::hexdump::match_hexdump_line;
+use colorexp::histogram::Histogram;
+use colorexp::hyperlinks::add_hyperlinks;
+use colorexp::input::{
+    Line, compile_record_separator, fd_path, open_input, read_lines, read_patterns, read_records,
+    read_word_list, split_patterns,
+};
+#[cfg(unix)]
+use colorexp::interrupt::reset_on_interrupt;
+use colorexp::manifest::Manifest;
+use colorexp::markdown::render_markdown;
+use colorexp::normalize::{Normalization, Normalized, is_normalized};
+use colorexp::output::{ByteLimit, Pager, create_output, pager_command};
+use colorexp::palette::{
+    MIN_CONTRAST, Markup, TerminalBackground, color_set, contrast, distinct_specs, palette,
+    palette_specs, stable_palette,
+};
+use colorexp::pattern_groups::{GroupColors, PatternGroup};
+use colorexp::positions::{format_ndjson, format_positions};
+use colorexp::presets::{
+    self, append_count, match_key_values, match_non_ascii, match_numbers, tint_line,
+};
+use colorexp::priority::{Priorities, sort_by_priority, terminate_lines};
+use colorexp::profile::{PatternTimes, slow_line_report};
+use colorexp::regex_help::{regex_debug, regex_help};
+use colorexp::repeated::RepeatedLines;
+use colorexp::rewrite::{Rewriter, Template};
+use colorexp::sample::{Reservoir, Rng};
+use colorexp::stats::{DistinctMatches, Measure, Stats};
+use colorexp::svg::{SvgOptions, render_svg, svg_color};
+use colorexp::tags::match_in_tag;
+use colorexp::transcode;
+use colorexp::value_map::{ValueMap, parse_rule};
+use colorexp::watch::{CLEAR_SCREEN, DEBOUNCE, Watcher};
+use colorexp::{
+    ColorCycle, ColorSet, ColorexpError, CombinedPatterns, LineSelector, LongestMatches,
+    MatchOptions, RangeWithId, ReservedColors, add_range, color_index, color_keys, colorize,
+    colorize_minimal, compile_pattern, compile_patterns, count_ranges, group_legend, inc_ranges,
+    keep_duplicates, match_in_quotes, match_line_into, pad_ranges, pattern_color_count, regexp_ids,
+    split_into_chars, vary_group_colors_by_default, word_list_pattern,
+};
+use std::io::{self, IsTerminal, Write};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
 use std::process::exit;
+use std::time::{Duration, Instant};
+use terminal_size::{Height, Width};
 
 /// Command line multicolor regexp highlighter
 #[derive(Parser, Debug)]
@@ -15,21 +70,141 @@ use std::process::exit;
     disable_help_flag = true,
     group(
         ArgGroup::new("highlight")
-            .args(&["no_highlight", "only_highlight"])
+            .args(&["no_highlight", "no_highlight_deprecated", "only_highlight"])
             .multiple(false)
     ),
     group(
-        ArgGroup::new("vary_group_colors")
+        ArgGroup::new("vary_group_colors_on_off")
             .args(&["vary_group_colors_off", "vary_group_colors_on"])
             .multiple(false)
     ),
 )]
 struct Args {
-    // using custom help arg to be able to turn off -h, which is used by the no_highlight arg
+    // Short flags are kept for the most common options: -F, -f and -i for matching, -o for the
+    // output, and -n, -H, -g and -G for coloring. -h is reserved for the help, but still taken by
+    // the deprecated short form of --no-highlight, hence the custom help arg
     /// Show help
     #[arg(long = "help", action = ArgAction::Help)]
     help: Option<bool>,
 
+    /// Only highlight the part of each line after the first match of REGEX. Lines without a match of REGEX are not highlighted
+    #[arg(long, value_name = "REGEX")]
+    after_anchor: Option<String>,
+
+    /// Pad lines so that the first matches of all lines line up in a column. Reads all input before printing anything
+    #[arg(long, conflicts_with = "sample")]
+    align: bool,
+
+    /// Color matching pairs of brackets by their depth, to make the nesting visible. CHARS are pairs of an opening and a closing bracket, like "()" or "()[]{}". Brackets are paired up within a line, those without a partner are left alone
+    #[arg(long, value_name = "CHARS")]
+    balanced: Option<String>,
+
+    /// Highlight every second match of a pattern in a line in reverse video, to make it easy to pair up matches like opening and closing brackets
+    #[arg(long, conflicts_with = "markup")]
+    alternate: bool,
+
+    /// Whether the terminal has a dark or a light background [default: dark]. With "light", darker foreground and lighter background colors are used by default, to keep the highlighting readable. Given colors that are hard to read on the background are warned about
+    #[arg(long, value_enum, value_name = "BACKGROUND")]
+    background: Option<Background>,
+
+    /// Also match every pattern on its own against every line, and report on stderr how long each pattern took in total, most expensive first, to find the patterns worth simplifying. Slows down highlighting
+    #[arg(long)]
+    benchmark_patterns: bool,
+
+    /// Comma separated background colors to use instead of the default ones. A color is a name like "red", "bright-red" or "orange" (X11 and CSS names are mapped to the closest of the 256 colors), or a number between 0 and 255
+    #[arg(long, value_name = "COLORS", value_delimiter = ',')]
+    bg_colors: Vec<String>,
+
+    /// Only check whether PATTERNS compile, reporting every invalid pattern, without reading any input
+    #[arg(long)]
+    check: bool,
+
+    /// Color whole lines by class. RULE is PATTERN=COLOR, like "FATAL=red", giving the lines matched by the regular expression PATTERN the foreground color COLOR, in which PATTERNS are still highlighted. Can be given multiple times, the first matching rule winning
+    #[arg(long, value_name = "RULE", conflicts_with = "tint_by_file")]
+    classify: Vec<String>,
+
+    /// How colors are assigned: "pattern" gives every pattern (or group) its own color. "line" gives the matches of a line the colors in turn, so that the first match of every line gets the first color. "stream" does the same, but continues with the next color on the next line
+    #[arg(
+        long,
+        value_enum,
+        value_name = "SCOPE",
+        default_value_t = ColorScope::Pattern
+    )]
+    color_scope: ColorScope,
+
+    /// Seed for picking colors with --stable-colors, to try out different colors for the same patterns
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 0,
+        requires = "stable_colors"
+    )]
+    color_seed: u64,
+
+    /// Make escape sequences in the output visible by writing ESC as `\e` (and `\` as `\\`)
+    #[arg(long)]
+    color_escape: bool,
+
+    /// Print the number of distinct texts matched by every pattern to stderr when done, e.g. to find out how many different IP addresses occur
+    #[arg(long)]
+    count_distinct: bool,
+
+    /// Append the number of matches to every line with matches, dimmed, like " [3]"
+    #[arg(long)]
+    count_inline: bool,
+
+    /// Show a live count of the highlighted matches (or capturing groups) per pattern on the last line of the terminal
+    #[arg(long)]
+    dashboard: bool,
+
+    /// Decompress the input as gzip, even if it is read from stdin, or from a file not ending in .gz
+    #[arg(long)]
+    decompress: bool,
+
+    /// The encoding of the input, which is decoded to UTF-8 before matching: "latin1" (ISO-8859-1), "utf-16le" or "utf-16be". The output is UTF-8
+    #[arg(long, value_enum, value_name = "NAME")]
+    encoding: Option<InputEncoding>,
+
+    /// Use "," as the decimal separator and "." to group digits for --numbers
+    #[arg(long, requires = "numbers")]
+    decimal_comma: bool,
+
+    /// Also highlight the tokens encoded as ENCODING whose decoded text is matched by PATTERNS, e.g. base64 encoded credentials. Tokens must be at least 8 characters long, and tokens not encoding valid UTF-8 text are not decoded
+    #[arg(long, value_enum, value_name = "ENCODING", conflicts_with_all = ["heatmap", "hexdump", "in_quotes", "in_tag"])]
+    decode: Option<Encoding>,
+
+    /// Decode escape sequences in input produced by --color-escape, without highlighting anything
+    #[arg(long, conflicts_with = "color_escape")]
+    decode_escapes: bool,
+
+    /// Define an alias for a sub-pattern, to be reused in the patterns. SPEC is NAME=PATTERN, like "time=\d\d:\d\d", which patterns reference as "(?&time)". The references are expanded into non-capturing groups. An alias may reference the aliases defined before it. Can be given multiple times
+    #[arg(long, value_name = "SPEC", conflicts_with = "fixed_strings")]
+    define: Vec<String>,
+
+    /// Make the text between the matches faint, so that the matches stand out. Same as --gap-style=dim
+    #[arg(long, conflicts_with_all = ["gap_style", "tint_by_file"])]
+    dim_unmatched: bool,
+
+    /// Instead of the lines, print the named capturing groups of the first match of every pattern in them, separated by tabs, each in the color of its group. Lines without a match are skipped, see --extract-unmatched
+    #[arg(long, conflicts_with_all = ["rewrite", "unsafe_replace"])]
+    extract: bool,
+
+    /// Print lines without a match unchanged with --extract, instead of skipping them
+    #[arg(long, requires = "extract")]
+    extract_unmatched: bool,
+
+    /// Comma separated foreground colors to use instead of the default ones. A color is a name like "red", "bright-red" or "orange" (X11 and CSS names are mapped to the closest of the 256 colors), or a number between 0 and 255
+    #[arg(long, value_name = "COLORS", value_delimiter = ',')]
+    fg_colors: Vec<String>,
+
+    /// Only highlight matches for which the shell command CMD succeeds when given the matched text on stdin, e.g. a script validating tokens. This runs CMD once for every distinct matched text, which is slow
+    #[arg(long, value_name = "CMD")]
+    filter_command: Option<String>,
+
+    /// Match ignoring differences of WHAT between PATTERNS and the input. "accents" makes e.g. "cafe" match "café", printing the input as it is
+    #[arg(long, value_enum, value_name = "WHAT", conflicts_with = "normalize")]
+    fold: Option<Fold>,
+
     /// Interpret PATTERNS as fixed strings, not regular expressions
     #[arg(short = 'F', long)]
     fixed_strings: bool,
@@ -38,216 +213,449 @@ struct Args {
     #[arg(short, long)]
     full_match_highlight: bool,
 
+    /// Highlight the entire match of the Nth pattern, counting from 1, even if it contains capturing groups, while other patterns highlight their groups as usual. Its groups are kept for the options reading them, like --rewrite and --extract. Can be given multiple times
+    #[arg(long, value_name = "N")]
+    full_match_pattern: Vec<NonZeroUsize>,
+
+    /// Style the text between the matches, keeping the matches highlighted as usual, e.g. "dim" to make the matches stand out more
+    #[arg(
+        long,
+        value_enum,
+        value_name = "STYLE",
+        conflicts_with = "tint_by_file"
+    )]
+    gap_style: Option<GapStyle>,
+
+    /// Separate the parts of a line kept by --trim-context by STR instead of "…", e.g. " ... ", or "" to just join them. The parts left out at the start and end of the line are still replaced by "…"
+    #[arg(long, value_name = "STR", requires = "trim_context")]
+    group_separator: Option<String>,
+
+    /// Color the separator of --group-separator in COLOR, like the colors of --fg-colors
+    #[arg(long, value_name = "COLOR", requires = "trim_context")]
+    group_separator_color: Option<String>,
+
+    /// Highlight a group of patterns in one color. SPEC is "NAME=COLOR:PATTERN:PATTERN...", or "NAME:PATTERN..." to use the next color, with colons within patterns written as "\:". The patterns of a group act like one pattern named NAME, given after PATTERNS. Can be given multiple times
+    #[arg(long, value_name = "SPEC")]
+    group: Vec<String>,
+
+    /// Only highlight capturing groups nested at most N levels deep, the outermost groups being at level 1
+    #[arg(long, value_name = "N")]
+    group_depth: Option<usize>,
+
+    /// Print a line with the names of all named capturing groups in their colors before the output, and a line for every --group, with its name in its color, followed by its patterns
+    #[arg(long)]
+    group_legend: bool,
+
+    /// Only highlight matches (or capturing groups) whose text occurs more than once in the line, all in the same color
+    #[arg(long)]
+    highlight_duplicates: bool,
+
+    /// Give lines that are exact duplicates of any earlier line of the input a gray background, to spot repeated entries scattered through a log. Only a hash of every distinct line is kept, see --max-remembered-lines
+    #[arg(long, conflicts_with_all = ["classify", "dim_unmatched", "gap_style", "tint_by_file"])]
+    highlight_repeated_lines: bool,
+
+    /// Instead of highlighting matches, color the background of capturing group GROUP (a number or a name) of every match on a cold to hot gradient by its numeric value. Requires a terminal supporting 24-bit colors
+    #[arg(long, value_name = "GROUP")]
+    heatmap: Option<String>,
+
+    /// The value colored hottest by --heatmap
+    #[arg(long, value_name = "N", default_value_t = 100.0, requires = "heatmap")]
+    heatmap_max: f64,
+
+    /// The value colored coldest by --heatmap
+    #[arg(long, value_name = "N", default_value_t = 0.0, requires = "heatmap")]
+    heatmap_min: f64,
+
+    /// Treat the input as "xxd" output. PATTERNS are matched against the hex digits of the bytes of a line, without separators and in lowercase, and the matching bytes are highlighted in both the hex and the text column
+    #[arg(long, conflicts_with_all = ["heatmap", "in_quotes", "in_tag"])]
+    hexdump: bool,
+
+    /// Instead of printing lines, print how often every matched text occurs, most frequent first, with bars colored like the matches. Reads all input before printing anything
+    #[arg(long, conflicts_with_all = ["align", "sample"])]
+    histogram: bool,
+
+    /// Highlight malformed and incomplete escape sequences in the input, writing their ESC as "\e" so that they show. Complete escape sequences are left intact
+    #[arg(long)]
+    highlight_ansi_errors: bool,
+
+    /// Color every character of a match differently, cycling through the colors
+    #[arg(long)]
+    highlight_char: bool,
+
+    /// Highlight the longest match of a pattern where it matches, e.g. "abc" rather than "ab" for "ab|abc", which otherwise highlights the first alternative that matches. Slower, as every match is searched for twice
+    #[arg(long)]
+    highlight_longest_match_only: bool,
+
+    /// Make highlighted URLs clickable in terminals supporting OSC 8 hyperlinks. Other terminals just highlight them
+    #[arg(long, conflicts_with = "markup")]
+    hyperlinks: bool,
+
     /// Perform case-insensitive matching
     #[arg(short, long)]
     ignore_case: bool,
 
     /// Do not color by changing the background color
-    #[arg(short = 'h', long)]
+    #[arg(short = 'n', long, visible_alias = "fg-only")]
     no_highlight: bool,
 
+    /// Deprecated short form of --no-highlight. -h will show the help in a future version
+    #[arg(short = 'h', hide = true)]
+    no_highlight_deprecated: bool,
+
     /// Only color by changing the background color
-    #[arg(short = 'H', long)]
+    #[arg(short = 'H', long, visible_alias = "bg-only")]
     only_highlight: bool,
 
+    /// Only match PATTERNS within single or double quoted strings, which may contain escaped quotes
+    #[arg(long)]
+    in_quotes: bool,
+
+    /// Only match PATTERNS within the text of the XML or HTML tags named TAG, like "msg" for "<msg>...</msg>", not within their attributes or the tags nested in them. Tags are found on every line on its own, so a tag not closed on its line extends to the end of the line
+    #[arg(long, value_name = "TAG", conflicts_with_all = ["heatmap", "in_quotes"])]
+    in_tag: Option<String>,
+
+    /// Print lines matching REGEX without highlighting anything in them
+    #[arg(long, value_name = "REGEX")]
+    ignore_lines: Option<String>,
+
+    /// Read input from FILE instead of stdin ("-" for stdin). Can be given multiple times. Files ending in .gz are decompressed
+    #[arg(long, value_name = "FILE")]
+    input: Vec<PathBuf>,
+
+    /// Highlight the keys and values of key=value and "key: value" pairs. Values may be quoted
+    #[arg(long)]
+    kv: bool,
+
+    /// Also list the distinct texts matched by every pattern, sorted, with --count-distinct
+    #[arg(long, requires = "count_distinct")]
+    list_distinct: bool,
+
+    /// Also write a summary of the matches in all inputs to PATH, as NDJSON: an object with the file, line number and matches of every line with matches, like {"file":"a.log","line":3,"matches":[{"start":4,"end":9,"pattern":"error","text":"error"}]}, with byte offsets, and an object with the numbers of lines and matches at the end of every file, like {"file":"a.log","lines":10,"matched_lines":1,"matches":1}
+    #[arg(long, value_name = "PATH")]
+    manifest: Option<PathBuf>,
+
+    /// Color capturing group --map-group of every match by its text. RULE is VALUE=COLOR, like "5\d\d=red", giving the group COLOR if the regular expression VALUE matches all of its text. Can be given multiple times, the first matching rule winning. Groups not matched by any rule are highlighted as usual
+    #[arg(long, value_name = "RULE", conflicts_with_all = ["heatmap", "hexdump", "in_quotes", "in_tag"])]
+    map: Vec<String>,
+
+    /// The capturing group (a number or a name) colored by --map, 0 being the entire match
+    #[arg(long, value_name = "GROUP", default_value = "0")]
+    map_group: String,
+
+    /// Highlight with markup tags like "[[red]]text[[/]]" instead of escape sequences
+    #[arg(long, visible_alias = "replace-with-color-codes")]
+    markup: bool,
+
+    /// Closing delimiter of markup tags
+    #[arg(long, value_name = "STR", default_value = "]]", requires = "markup")]
+    markup_close: String,
+
+    /// Opening delimiter of markup tags
+    #[arg(long, value_name = "STR", default_value = "[[", requires = "markup")]
+    markup_open: String,
+
+    /// Turn highlighting off for the lines after a "#colorexp:off" line, and back on after a "#colorexp:on" line. The marker lines are not printed
+    #[arg(long)]
+    markers: bool,
+
+    /// Instead of printing the highlighted lines, print how many bytes the escape sequences highlighting them add, compared to the size of the lines, to estimate the overhead of the highlighting
+    #[arg(long, conflicts_with_all = ["align", "histogram", "sample"])]
+    measure: bool,
+
+    /// Stop after the line with which the output reaches N bytes, escape sequences included, so that piping a huge input to the terminal does not flood it
+    #[arg(long, value_name = "N")]
+    max_output_bytes: Option<usize>,
+
+    /// Stop highlighting a line once N matches (or capturing groups) are highlighted in it. Matches of patterns given later are highlighted first
+    #[arg(long, value_name = "N")]
+    max_per_line: Option<usize>,
+
+    /// Remember at most N distinct lines for --highlight-repeated-lines, to bound its memory. Later lines are not remembered, but repeats of the remembered lines are still highlighted
+    #[arg(long, value_name = "N", requires = "highlight_repeated_lines")]
+    max_remembered_lines: Option<usize>,
+
+    /// Only highlight the Nth match of every pattern in a line, counting from 1, e.g. to highlight a field at a given position. Lines with fewer matches of a pattern get no highlighting for it
+    #[arg(long, value_name = "N", conflicts_with = "alternate")]
+    nth: Option<NonZeroUsize>,
+
+    /// Do not turn off colors between adjacent matches whose colors replace each other, to save escape sequences
+    #[arg(long, conflicts_with = "markup")]
+    minimal_escapes: bool,
+
+    /// Leave out colors of the palette that are closer than D to an earlier one, by the CIE76 distance, on which 2.3 is just noticeable and black and white are 100 apart, e.g. 40. If that leaves fewer colors than patterns, colors of the 256 color palette that are far enough apart are added, with a warning if there are not enough of them
+    #[arg(long, value_name = "D")]
+    min_color_distance: Option<f64>,
+
+    /// Do not highlight matches (or capturing groups) shorter than N bytes
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    min_length: usize,
+
+    /// Highlight runs of non-ASCII characters, e.g. to find encoding issues
+    #[arg(long, visible_alias = "highlight-non-ascii")]
+    non_ascii: bool,
+
+    /// Highlight numbers, including ones with digits grouped like 1,234.56
+    #[arg(long)]
+    numbers: bool,
+
+    /// Convert lines to Unicode normalization FORM before matching, so that e.g. a composed "é" in a pattern matches a decomposed one in the input. Highlights cover whole characters including their combining marks
+    #[arg(long, value_enum, value_name = "FORM")]
+    normalize: Option<NormalizationForm>,
+
+    /// Only highlight lines matching REGEX, printing other lines without highlighting. With --only-matching-lines, other lines are not printed
+    #[arg(long, value_name = "REGEX")]
+    only_lines: Option<String>,
+
     /// Only print lines with matches (suppress lines without matches)
     #[arg(short, long)]
     only_matching_lines: bool,
 
-    /// Patterns
-    #[arg(required = true, num_args = 1..)]
+    /// What to print: "lines" prints the highlighted lines, "positions" prints a "line:start:end:pattern" line for every match instead, with byte offsets, for editor plugins doing their own highlighting, starting with "file:" if there are several inputs. "ndjson" prints a JSON object for every line instead, with its number, its text and its matches, and its file if there are several inputs, flushed right away for tools processing the matches as they stream in. "svg" prints an SVG image of the highlighted lines in a terminal, to put into documents, and "markdown" prints the lines in a Markdown code block, to be pasted into issues or chats, without colors but with the tags of --markup. Both read all input before printing anything
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        default_value_t = OutputFormat::Lines
+    )]
+    output: OutputFormat,
+
+    /// Write output to FILE instead of stdout, creating or truncating it. Turns off --dashboard
+    #[arg(long, value_name = "FILE")]
+    output_file: Option<PathBuf>,
+
+    /// Also highlight matches that overlap with other matches of the same pattern. This is slower, as the search restarts after the start of every match
+    #[arg(long)]
+    overlapping: bool,
+
+    /// Also color up to N bytes after each match
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    pad_after: usize,
+
+    /// Also color up to N bytes before each match
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    pad_before: usize,
+
+    /// Show the output in the pager given by the PAGER environment variable, or in "less", if the output is a terminal. Colors are passed through by setting LESS to "FRX" if it is not set. Without a pager, the output is written to the terminal. Turns off --dashboard
+    #[arg(long, conflicts_with = "output_file")]
+    pager: bool,
+
+    /// Patterns. More patterns can be given in the COLOREXP_PATTERNS environment variable, separated by newlines or colons, with colons within patterns written as "\:"
+    #[arg(required_unless_present_any = ["balanced", "classify", "decode_escapes", "env_patterns", "group", "highlight_ansi_errors", "kv", "non_ascii", "numbers", "patterns_fd", "regex_help", "show_control", "word_list"], num_args = 1..)]
     patterns: Vec<String>,
 
-    /// Turn off changing of colors for every capturing group. Defaults to on if exactly one pattern is given
+    /// Patterns from the environment, see PATTERNS
+    #[arg(long, env = "COLOREXP_PATTERNS", hide = true)]
+    env_patterns: Option<String>,
+
+    /// Also read patterns from file descriptor N, one per line, after the ones given as arguments. Lets scripts pipe in patterns while the input comes from stdin
+    #[arg(long, value_name = "N")]
+    patterns_fd: Option<u32>,
+
+    /// Highlight the entire match of every pattern in one color, even if it contains capturing groups, which are still used by options like --heatmap. Same as --full-match-highlight --vary-group-colors=off
+    #[arg(long, conflicts_with = "vary_group_colors_on")]
+    per_pattern_color: bool,
+
+    /// Report lines whose matching takes longer than --profile-threshold on stderr, to find inputs that make patterns slow. The time --filter-command takes is not counted
+    #[arg(long)]
+    profile: bool,
+
+    /// Matching time in milliseconds above which --profile reports a line
+    #[arg(long, value_name = "MS", default_value_t = 1.0, requires = "profile")]
+    profile_threshold: f64,
+
+    /// Split the input into records separated by matches of REGEX instead of into lines, so that patterns can match across lines. In REGEX, ^ and $ match at the start and end of every line. Reads each input entirely before printing anything
+    #[arg(long, value_name = "REGEX")]
+    record_separator: Option<String>,
+
+    /// Print the structure of every pattern as parsed by the regex crate to stderr, e.g. to find out why a pattern matches more than expected
+    #[arg(long)]
+    regex_debug: bool,
+
+    /// Show a short reference of the supported regular expression syntax
+    #[arg(long)]
+    regex_help: bool,
+
+    /// Replace the matches of a pattern by TEMPLATE, in which every capturing group keeps its color, e.g. "$3/$2/$1" to reformat the dates matched by "(\d{4})-(\d\d)-(\d\d)". Groups are written as "$1" or "$name", or as "${1}" or "${name}" when followed by letters or digits, and "$$" is a "$". The Nth --rewrite is used for the Nth pattern, patterns without one are highlighted as usual
+    #[arg(long, value_name = "TEMPLATE", conflicts_with = "unsafe_replace")]
+    rewrite: Vec<String>,
+
+    /// Only print a random sample of N of the lines with matches
+    #[arg(long, value_name = "N")]
+    sample: Option<usize>,
+
+    /// Make escape sequences in the input visible, writing their ESC as "\e", before highlighting, so that untrusted input cannot control the terminal, e.g. clear the screen or change its title. The other C1 control characters are written like "\x9b"
+    #[arg(long, conflicts_with = "highlight_ansi_errors")]
+    sanitize: bool,
+
+    /// Seed for the random number generator used by --sample, to get reproducible samples
+    #[arg(long, value_name = "N", requires = "sample")]
+    seed: Option<u64>,
+
+    /// Make control characters other than tab and ESC visible, in caret notation like "^A", or as Unicode control pictures like "␁" with --show-control=pictures. Patterns still match the original characters
+    #[arg(
+        long,
+        value_enum,
+        value_name = "NOTATION",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "caret",
+        visible_alias = "highlight-control-chars"
+    )]
+    show_control: Option<ControlNotation>,
+
+    /// Print the lines with matches first, those matching patterns given later, which take precedence, before the others, followed by the lines without matches, keeping the order of the input otherwise. Reads all input before printing anything
+    #[arg(long, conflicts_with_all = ["align", "histogram", "measure", "sample"])]
+    sort_by_pattern_priority: bool,
+
+    /// Pick the color of each pattern by hashing it, so that a pattern gets the same color no matter which other patterns are given
+    #[arg(long)]
+    stable_colors: bool,
+
+    /// Also end lines at carriage returns, printing them right away, so that lines updated in place, like progress bars, get highlighted
+    #[arg(long)]
+    split_cr: bool,
+
+    /// Print a summary of the number of lines and highlighted matches (or capturing groups) to stderr when done
+    #[arg(long)]
+    stats: bool,
+
+    /// The background color of the image printed by --output=svg
+    #[arg(long, value_name = "COLOR", default_value = "black")]
+    svg_background: String,
+
+    /// The font size of the image printed by --output=svg, in pixels
+    #[arg(long, value_name = "N", default_value_t = 14.0)]
+    svg_font_size: f64,
+
+    /// Give the lines of every input a background color of their own, so that the inputs can be told apart
+    #[arg(long, conflicts_with = "align")]
+    tint_by_file: bool,
+
+    /// Match PATTERNS against the line without its leading and trailing whitespace
+    #[arg(long)]
+    trim: bool,
+
+    /// Shorten lines wider than the terminal (or 80 columns if the output is not a terminal) to their matches and up to N characters around each, replacing the rest by "…"
+    #[arg(long, value_name = "N")]
+    trim_context: Option<usize>,
+
+    /// Replace every match (or capturing group) by the output of the shell command CMD, which gets the matched text on stdin. Matches for which CMD fails are kept. CMD runs once for every distinct matched text, which is slow. As the matched text comes from the input, CMD must be safe to run on any input, so only use trusted commands that do not execute or evaluate their input
+    #[arg(long, value_name = "CMD")]
+    unsafe_replace: Option<String>,
+
+    /// Change colors for every capturing group. "auto" turns it on if exactly one pattern is given and it has capturing groups
+    #[arg(
+        long,
+        value_enum,
+        value_name = "WHEN",
+        env = "COLOREXP_VARY_GROUP_COLORS",
+        default_value_t = VaryGroupColors::Auto
+    )]
+    vary_group_colors: VaryGroupColors,
+
+    /// Highlight the words in FILE, one per line, literally. Can be given multiple times, each list getting its own color
+    #[arg(long, value_name = "FILE")]
+    word_list: Vec<PathBuf>,
+
+    /// Turn off changing of colors for every capturing group. Same as --vary-group-colors=off
     #[arg(short = 'g', long)]
     vary_group_colors_off: bool,
 
-    /// Turn on changing of colors for every capturing group. Defaults to on if exactly one pattern is given
+    /// Turn on changing of colors for every capturing group. Same as --vary-group-colors=on
     #[arg(short = 'G', long)]
     vary_group_colors_on: bool,
+
+    /// Read input from FILE, and print it again, after clearing the screen, whenever FILE changes, until interrupted. Unlike "tail -f", this rereads all of FILE, and suits files that are rewritten, like status reports
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["input", "output_file", "pager"])]
+    watch: Option<PathBuf>,
 }
 
-static FOREGROUND_COLORS: &[&str] = &[
-    //"\x1b[30m", // Black
-    "\x1b[31m", // Red
-    "\x1b[32m", // Green
-    "\x1b[33m", // Yellow
-    "\x1b[34m", // Blue
-    "\x1b[35m", // Magenta
-    "\x1b[36m", // Cyan
-                //"\x1b[37m", // White
-];
-
-static BACKGROUND_COLORS: &[&str] = &[
-    //"\x1b[40m", // Black
-    "\x1b[41m", // Red
-    "\x1b[44m", // Blue
-    "\x1b[45m", // Magenta
-    "\x1b[42m", // Green
-    "\x1b[43m", // Yellow
-    "\x1b[46m", // Cyan
-                //"\x1b[47m", // White
-];
-
-const RESET_FOREGROUND: &str = "\x1b[0m";
-const RESET_BACKGROUND: &str = "\x1b[49m";
-
-#[derive(Copy, Clone, Eq, PartialEq, Debug)]
-struct RangeWithId {
-    start_idx: usize,
-    end_idx: usize,
-    id: usize,
+const HISTOGRAM_BAR_WIDTH: usize = 40;
+const DEFAULT_TERMINAL_WIDTH: usize = 80;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
+enum Background {
+    Dark,
+    Light,
 }
 
-/// add_range adds a new range to the ordered list of non-overlapping ranges.
-/// It ensures that the list stays ordered and any existing ranges are subtracted
-/// from the new range, potentially splitting it into multiple pieces.
-fn add_range(ranges: &mut Vec<RangeWithId>, mut new_range: RangeWithId) {
-    let mut inserted = false;
-
-    let mut i = 0;
-    while i < ranges.len() {
-        let existing_range = *unsafe { ranges.get_unchecked(i) };
-
-        if new_range.end_idx <= existing_range.start_idx {
-            // The new range is entirely before the existing range.
-            if !inserted {
-                ranges.insert(i, new_range);
-                i += 1;
-                inserted = true;
-            }
-        } else if new_range.start_idx >= existing_range.end_idx {
-            // The new range is entirely after the existing range.
-        } else {
-            // There is an overlap; we may need to split the new range.
-            if !inserted && new_range.start_idx < existing_range.start_idx {
-                // Add the non-overlapping piece before the existing range.
-                ranges.insert(
-                    i,
-                    RangeWithId {
-                        start_idx: new_range.start_idx,
-                        end_idx: existing_range.start_idx,
-                        id: new_range.id,
-                    },
-                );
-                i += 1;
-            }
-            if new_range.end_idx > existing_range.end_idx {
-                // Update the new range to start from the end of the existing range.
-                new_range.start_idx = existing_range.end_idx;
-            } else {
-                // The new range is fully covered by the existing range; nothing left to add.
-                inserted = true;
-                new_range.start_idx = new_range.end_idx;
-            }
-        }
-        i += 1;
-    }
+#[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
+enum ColorScope {
+    Line,
+    Pattern,
+    Stream,
+}
 
-    // If the new range was not inserted because it is after all existing ranges,
-    // or if it still has a remaining piece after processing overlaps, add it now.
-    if !inserted {
-        ranges.push(new_range);
-    }
+#[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
+enum ControlNotation {
+    Caret,
+    Pictures,
 }
 
-fn match_line(
-    line: &str,
-    regexps: &Vec<Regex>,
-    vary_group_colors: bool,
-    full_match_highlight: bool,
-) -> Vec<RangeWithId> {
-    let mut ranges = Vec::default();
-    let mut color_idx = 0;
-    for re in regexps {
-        let num_groups = re.captures_len() - 1; // subtract implicit group
-        let first_group_to_colorize = if full_match_highlight {
-            0
-        } else {
-            min(1, num_groups)
-        };
-        let groups_to_colorize = if full_match_highlight {
-            1
-        } else {
-            num_groups + 1 - first_group_to_colorize
-        };
-        for match_ in re.captures_iter(line) {
-            // if there is no capturing group, the full match will be colorized (group 0)
-            // if there are capturing groups, all groups but group 0 (the full match) will be colorized, unless
-            // full_match_highlight == true
-            for i in 0..groups_to_colorize {
-                let mut cur_color_idx = color_idx;
-                if vary_group_colors {
-                    cur_color_idx += groups_to_colorize - 1 - i;
-                }
-                let g_idx = i + first_group_to_colorize;
-                if let Some(g) = match_.get(g_idx) {
-                    add_range(
-                        &mut ranges,
-                        RangeWithId {
-                            start_idx: g.start(),
-                            end_idx: g.end(),
-                            id: cur_color_idx,
-                        },
-                    );
-                }
-            }
-        }
-        if vary_group_colors {
-            color_idx += groups_to_colorize;
-        } else {
-            color_idx += 1;
-        }
-    }
-    ranges
+#[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
+enum Encoding {
+    Base64,
+    Hex,
 }
 
-struct ColorSet {
-    on: &'static str,
-    off: &'static str,
+#[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
+enum InputEncoding {
+    Latin1,
+    #[value(name = "utf-16le")]
+    Utf16Le,
+    #[value(name = "utf-16be")]
+    Utf16Be,
 }
 
-impl ColorSet {
-    pub fn new(on: &'static str, off: &'static str) -> Self {
-        Self { on, off }
-    }
+#[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
+enum Fold {
+    Accents,
 }
 
-fn colorize(
-    s: &mut String,
-    colors: &[ColorSet],
-    ranges: &mut [RangeWithId],
-    pattern_color_count: usize,
-) {
-    let ranges_len = ranges.len();
-    for i in 0..ranges_len {
-        let r = unsafe { ranges.get_unchecked(i) };
-        let mut color_idx = (pattern_color_count - r.id) as i32 - 1; // The quick ...
-        while color_idx < 0 {
-            color_idx += colors.len() as i32;
-        }
-        let color_idx = color_idx as usize;
-        let color = &colors[color_idx % colors.len()];
-        s.insert_str(r.start_idx, color.on);
-        inc_ranges(ranges, color.on.len());
-        s.insert_str(unsafe { ranges.get_unchecked(i) }.end_idx, color.off);
-        inc_ranges(ranges, color.off.len());
-    }
+#[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
+enum GapStyle {
+    Dim,
 }
 
-fn inc_ranges(ranges: &mut [RangeWithId], inc: usize) {
-    let ranges_len = ranges.len();
-    for i in 0..ranges_len {
-        let r = unsafe { ranges.get_unchecked_mut(i) };
-        r.start_idx += inc;
-        r.end_idx += inc;
-    }
+#[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
+enum NormalizationForm {
+    Nfc,
+    Nfd,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
+enum OutputFormat {
+    Lines,
+    Positions,
+    Svg,
+    Markdown,
+    Ndjson,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
+enum VaryGroupColors {
+    Auto,
+    On,
+    Off,
 }
 
 fn main() {
     let args = Args::parse();
 
-    if let Err(err) = run(&args) {
+    let result = match &args.watch {
+        Some(path) => watch(&args, path),
+        None => run(&args),
+    };
+    if let Err(err) = result {
+        // quitting the pager before all output is written is not an error
+        if args.pager
+            && err
+                .downcast_ref::<io::Error>()
+                .is_some_and(|err| err.kind() == io::ErrorKind::BrokenPipe)
+        {
+            exit(0);
+        }
+        eprintln!("Error: {err}");
         let mut source = err.source();
         while let Some(cause) = source {
             eprintln!("  Caused by: {cause}");
@@ -257,73 +665,1181 @@ fn main() {
     }
 }
 
-fn run(args: &Args) -> Result<()> {
-    let vary_group_colors = {
-        if args.vary_group_colors_on {
-            true
-        } else if args.vary_group_colors_off {
-            false
-        } else {
-            args.patterns.len() == 1
+/// only_patterns describes how few patterns there are, for errors about arguments referring to more
+/// patterns than given.
+fn only_patterns(len: usize) -> String {
+    match len {
+        0 => "there are no patterns".to_string(),
+        1 => "there is only 1 pattern".to_string(),
+        len => format!("there are only {len} patterns"),
+    }
+}
+
+/// watch prints the highlighted file whenever it changes, clearing the screen first. Errors
+/// reading the file, e.g. while it is replaced, are printed instead of its lines.
+fn watch(args: &Args, path: &Path) -> Result<()> {
+    let mut watcher = Watcher::new(path);
+    loop {
+        print!("{CLEAR_SCREEN}");
+        io::stdout().flush()?;
+        if let Err(err) = run(args) {
+            if !matches!(
+                err.downcast_ref::<ColorexpError>(),
+                Some(ColorexpError::Io { .. })
+            ) {
+                return Err(err);
+            }
+            eprintln!("Error: {err:#}");
         }
+        watcher.wait(DEBOUNCE);
+    }
+}
+
+fn run(args: &Args) -> Result<()> {
+    if args.no_highlight_deprecated {
+        eprintln!(
+            "Warning: -h is deprecated, use -n or --no-highlight instead. -h will show the help in a future version"
+        );
+    }
+    let inputs = if let Some(path) = &args.watch {
+        vec![path.clone()]
+    } else if args.input.is_empty() {
+        vec![PathBuf::from("-")]
+    } else {
+        args.input.clone()
     };
 
-    let regexps = args
-        .patterns
+    let encoding = args.encoding.map(|encoding| match encoding {
+        InputEncoding::Latin1 => transcode::InputEncoding::Latin1,
+        InputEncoding::Utf16Le => transcode::InputEncoding::Utf16Le,
+        InputEncoding::Utf16Be => transcode::InputEncoding::Utf16Be,
+    });
+    let positions = args.output == OutputFormat::Positions;
+    let ndjson = args.output == OutputFormat::Ndjson;
+    if (positions || ndjson)
+        && (args.align
+            || args.histogram
+            || args.measure
+            || args.sample.is_some()
+            || args.sort_by_pattern_priority)
+    {
+        return Err(ColorexpError::InvalidArgumentCombination(format!(
+            "--output={} cannot be used with --align, --histogram, --measure, --sample or --sort-by-pattern-priority",
+            if ndjson { "ndjson" } else { "positions" }
+        ))
+        .into());
+    }
+
+    let svg = args.output == OutputFormat::Svg;
+    if svg && (args.markup || args.color_escape) {
+        return Err(ColorexpError::InvalidArgumentCombination(
+            "--output=svg cannot be used with --markup or --color-escape".to_string(),
+        )
+        .into());
+    }
+    let markdown = args.output == OutputFormat::Markdown;
+    if markdown && args.color_escape {
+        return Err(ColorexpError::InvalidArgumentCombination(
+            "--output=markdown cannot be used with --color-escape".to_string(),
+        )
+        .into());
+    }
+
+    // like the dashboard, the pager is only used if the output is a terminal
+    let pager = if args.pager && io::stdout().is_terminal() {
+        pager_command(std::env::var("PAGER").ok().as_deref())
+            .and_then(|command| Pager::spawn(&command).ok())
+    } else {
+        None
+    };
+    let paging = pager.is_some();
+    // interrupting colorexp must not leave the terminal colored, which it can only be if escape
+    // sequences are written to it
+    #[cfg(unix)]
+    if args.output == OutputFormat::Lines
+        && !args.markup
+        && !args.color_escape
+        && !paging
+        && args.output_file.is_none()
+        && io::stdout().is_terminal()
+    {
+        reset_on_interrupt();
+    }
+    let mut out: Box<dyn Write> = match pager {
+        Some(pager) => Box::new(pager),
+        None => create_output(args.output_file.as_deref())?,
+    };
+    let output_name = args
+        .output_file
+        .as_ref()
+        .map_or_else(|| "stdout".to_string(), |path| path.display().to_string());
+    let write_error = || format!("cannot write to {output_name}");
+
+    if args.decode_escapes {
+        for line in read_lines(&inputs, args.decompress, encoding, args.split_cr) {
+            let line = line?;
+            write!(out, "{}{}", escapes::decode(&line.text), line.terminator)
+                .with_context(write_error)?;
+        }
+        return out.flush().with_context(write_error);
+    }
+
+    let mut given_patterns = args.patterns.clone();
+    if let Some(fd) = args.patterns_fd {
+        let path = fd_path(fd);
+        given_patterns.extend(read_patterns(open_input(&path, false)?, &path)?);
+    }
+    if let Some(env_patterns) = &args.env_patterns {
+        given_patterns.extend(split_patterns(env_patterns));
+    }
+
+    let mut aliases = Aliases::default();
+    for spec in &args.define {
+        aliases.define(spec)?;
+    }
+
+    if args.check {
+        let mut invalid = 0;
+        for pattern in &given_patterns {
+            match compile_pattern(
+                &aliases.expand(pattern)?,
+                args.fixed_strings,
+                args.ignore_case,
+            ) {
+                Ok(_) => writeln!(out, "ok: {pattern}"),
+                Err(ColorexpError::InvalidPattern { source, .. }) => {
+                    invalid += 1;
+                    writeln!(out, "invalid: {pattern}\n{source}")
+                }
+                Err(err) => return Err(err.into()),
+            }
+            .with_context(write_error)?;
+        }
+        out.flush().with_context(write_error)?;
+        if invalid > 0 {
+            bail!("{invalid} of {} patterns are invalid", given_patterns.len());
+        }
+        return Ok(());
+    }
+
+    if let Some(n) = args
+        .full_match_pattern
         .iter()
-        // reverse order, so that the last given regex that matches takes precedence
-        .rev()
-        .map(|p| {
-            let pat: std::borrow::Cow<'_, str> = if args.fixed_strings {
-                escape(p).into()
-            } else {
-                p.as_str().into()
-            };
-            RegexBuilder::new(&pat)
-                .case_insensitive(args.ignore_case)
-                .build()
+        .find(|n| n.get() > given_patterns.len())
+    {
+        return Err(ColorexpError::InvalidArgumentCombination(format!(
+            "--full-match-pattern {n} given, but {}",
+            only_patterns(given_patterns.len())
+        ))
+        .into());
+    }
+    let patterns = given_patterns
+        .iter()
+        .map(|p| Ok(aliases.expand(p)?))
+        .collect::<Result<Vec<_>>>()?;
+    let patterns = match args.group_depth {
+        Some(max_depth) if !args.fixed_strings => patterns
+            .iter()
+            .map(|p| limit_group_depth(p, max_depth))
+            .collect(),
+        _ => patterns,
+    };
+    // folding accents is done by normalizing, which takes care of mapping the ranges back
+    let normalization = match (args.fold, args.normalize) {
+        (Some(Fold::Accents), _) => Some(Normalization::FoldAccents),
+        (None, Some(NormalizationForm::Nfc)) => Some(Normalization::Nfc),
+        (None, Some(NormalizationForm::Nfd)) => Some(Normalization::Nfd),
+        (None, None) => None,
+    };
+    // patterns are normalized like the input, so that they are written in the same form
+    let patterns = match normalization {
+        Some(normalization) => patterns
+            .iter()
+            .map(|p| Normalized::new(p, normalization).text)
+            .collect(),
+        None => patterns,
+    };
+    let mut regexps = compile_patterns(&patterns, args.fixed_strings, args.ignore_case)?;
+    // groups act like patterns given after the others, but before word lists
+    let groups = args
+        .group
+        .iter()
+        .map(|spec| PatternGroup::parse(spec))
+        .collect::<Vec<_>>();
+    let group_patterns = groups
+        .iter()
+        .map(|group| {
+            let pattern = aliases.expand(&group.pattern(args.fixed_strings))?;
+            Ok(match normalization {
+                Some(normalization) => Normalized::new(&pattern, normalization).text,
+                None => pattern,
+            })
         })
-        .collect::<Result<Vec<_>, _>>()?;
+        .collect::<Result<Vec<_>>>()?;
+    regexps.splice(
+        0..0,
+        compile_patterns(&group_patterns, false, args.ignore_case)?,
+    );
+    // word lists act like patterns given after all the others
+    let word_list_patterns = args
+        .word_list
+        .iter()
+        .map(|path| Ok(word_list_pattern(&read_word_list(path)?)))
+        .collect::<Result<Vec<_>>>()?;
+    let word_list_regexps = compile_patterns(&word_list_patterns, false, args.ignore_case)?;
+    regexps.splice(0..0, word_list_regexps);
+    if args.regex_debug {
+        for re in regexps.iter().rev() {
+            eprint!("{}", regex_debug(re, args.ignore_case));
+        }
+    }
+    let rewriter = if args.rewrite.is_empty() {
+        None
+    } else {
+        if args.rewrite.len() > patterns.len() {
+            return Err(ColorexpError::InvalidArgumentCombination(format!(
+                "{} --rewrite templates given, but {}",
+                args.rewrite.len(),
+                only_patterns(patterns.len())
+            ))
+            .into());
+        }
+        // the regexps of the patterns come last, in reverse order
+        let mut templates = vec![None; regexps.len() - patterns.len()];
+        templates.extend(
+            (0..patterns.len())
+                .rev()
+                .map(|i| args.rewrite.get(i).map(|t| Template::parse(t))),
+        );
+        // the regexps are compiled by now, with the groups left by --group-depth and the like
+        for (idx, template) in args.rewrite.iter().enumerate() {
+            let re = &regexps[regexps.len() - 1 - idx];
+            if let Some(group) = Template::parse(template).unknown_group(re) {
+                return Err(ColorexpError::BadRewriteTemplate {
+                    template: template.clone(),
+                    reason: format!("the pattern has no group {group}"),
+                }
+                .into());
+            }
+        }
+        Some(Rewriter { templates })
+    };
+    if args.extract && !has_named_groups(&regexps) {
+        return Err(ColorexpError::InvalidArgumentCombination(
+            "--extract requires a pattern with a named capturing group, like (?<name>...)"
+                .to_string(),
+        )
+        .into());
+    }
+    let pattern_names = given_patterns
+        .into_iter()
+        .chain(groups.iter().map(|group| group.name.clone()))
+        .chain(args.word_list.iter().map(|path| path.display().to_string()))
+        .collect::<Vec<_>>();
+    let compile_regex = |pattern: &Option<String>, ignore_case| {
+        pattern
+            .as_deref()
+            .map(|p| compile_pattern(p, false, ignore_case))
+            .transpose()
+    };
+    let record_separator = args
+        .record_separator
+        .as_deref()
+        .map(compile_record_separator)
+        .transpose()?;
+    let mut selector = LineSelector {
+        ignore_lines: compile_regex(&args.ignore_lines, args.ignore_case)?,
+        only_lines: compile_regex(&args.only_lines, args.ignore_case)?,
+        markers: args.markers,
+        off: false,
+        trim: args.trim,
+        anchor: compile_regex(&args.after_anchor, args.ignore_case)?,
+    };
+
+    // --per-pattern-color, -g and -G take precedence over --vary-group-colors, which may be set via
+    // the environment
+    let vary_group_colors = if args.per_pattern_color {
+        false
+    } else if args.vary_group_colors_on {
+        true
+    } else if args.vary_group_colors_off {
+        false
+    } else {
+        match args.vary_group_colors {
+            VaryGroupColors::Auto => vary_group_colors_by_default(&regexps),
+            VaryGroupColors::On => true,
+            VaryGroupColors::Off => false,
+        }
+    };
 
-    let colors = {
-        let mut colors = Vec::new();
-        if !args.only_highlight {
-            for c in FOREGROUND_COLORS {
-                colors.push(ColorSet::new(c, RESET_FOREGROUND));
+    let markup = args.markup.then(|| Markup {
+        open: args.markup_open.clone(),
+        close: args.markup_close.clone(),
+    });
+    let terminal_background = match args.background {
+        Some(Background::Light) => TerminalBackground::Light,
+        Some(Background::Dark) | None => TerminalBackground::Dark,
+    };
+    if args.background.is_some() {
+        let specs = args.fg_colors.iter().map(|spec| (spec, false));
+        for (spec, background) in specs.chain(args.bg_colors.iter().map(|spec| (spec, true))) {
+            let contrast = contrast(spec, background, terminal_background)?;
+            if contrast < MIN_CONTRAST {
+                eprintln!(
+                    "Warning: color `{}` has a contrast ratio of only {contrast:.1}:1 on the background, {MIN_CONTRAST}:1 is needed to be readable",
+                    spec.trim()
+                );
             }
         }
-        if !args.no_highlight {
-            for c in BACKGROUND_COLORS {
-                colors.push(ColorSet::new(c, RESET_BACKGROUND));
+    }
+    let mut colors = match args.min_color_distance {
+        Some(min_distance) => {
+            let specs = palette_specs(
+                &args.fg_colors,
+                &args.bg_colors,
+                !args.only_highlight,
+                !(args.no_highlight || args.no_highlight_deprecated),
+                terminal_background,
+            );
+            let count = pattern_color_count(&regexps, vary_group_colors);
+            let specs = distinct_specs(&specs, count, min_distance, terminal_background)?;
+            if specs.len() < count {
+                eprintln!(
+                    "Warning: only {} colors are at least {min_distance} apart, {count} are needed to give every pattern its own color",
+                    specs.len()
+                );
             }
+            specs
+                .iter()
+                .map(|(spec, background)| Ok(color_set(spec, *background, markup.as_ref())?))
+                .collect::<Result<Vec<_>>>()?
         }
-        colors
+        None => palette(
+            &args.fg_colors,
+            &args.bg_colors,
+            !args.only_highlight,
+            !(args.no_highlight || args.no_highlight_deprecated),
+            markup.as_ref(),
+            terminal_background,
+        )?,
+    };
+
+    if args.regex_help {
+        write!(out, "{}", regex_help(colors.first())).with_context(write_error)?;
+        return out.flush().with_context(write_error);
+    }
+
+    // with --output=svg or markdown, the output is collected, to be rendered at the end
+    let svg_options = if svg {
+        Some(SvgOptions {
+            font_size: args.svg_font_size,
+            background: svg_color(&args.svg_background)?,
+            ..SvgOptions::default()
+        })
+    } else {
+        None
+    };
+    let mut target = out;
+    let mut collected = Vec::new();
+    let out: Box<dyn Write + '_> = if svg || markdown {
+        Box::new(&mut collected)
+    } else {
+        Box::new(&mut target)
     };
+    // every output goes through the limit, whatever its format
+    let mut out = ByteLimit::new(out, args.max_output_bytes);
 
-    let mut pattern_color_count = regexps.len();
-    if vary_group_colors {
-        for re in &regexps {
-            pattern_color_count += max(0, re.captures_len() as i32 - 2) as usize;
+    let pattern_color_count = pattern_color_count(&regexps, vary_group_colors);
+    let keys = color_keys(&pattern_names, &regexps, vary_group_colors);
+    if args.stable_colors && !colors.is_empty() {
+        colors = stable_palette(&colors, &keys, args.color_seed);
+    }
+    let mut reserved_colors = ReservedColors::new(pattern_color_count);
+    // the colors of the patterns in reverse video, in the order of the ids of the patterns
+    let alternate_offset = (args.alternate && !colors.is_empty()).then(|| {
+        (0..pattern_color_count)
+            .map(|id| {
+                let color = &colors[color_index(id, pattern_color_count, colors.len())];
+                reserved_colors.reserve(ColorSet::new(
+                    format!("{}\x1b[7m", color.on),
+                    format!("\x1b[27m{}", color.off),
+                ))
+            })
+            .min()
+            .unwrap_or_default()
+    });
+    let kv_ids = if args.kv {
+        Some((
+            reserved_colors.reserve(color_set(presets::KEY_COLOR, false, markup.as_ref())?),
+            reserved_colors.reserve(color_set(presets::VALUE_COLOR, false, markup.as_ref())?),
+        ))
+    } else {
+        None
+    };
+    let heatmap = args.heatmap.as_ref().map(|group| {
+        let ids = heatmap_colors(markup.as_ref())
+            .into_iter()
+            .map(|color| reserved_colors.reserve(color))
+            .collect::<Vec<_>>();
+        Heatmap {
+            group: group.clone(),
+            min: args.heatmap_min,
+            max: args.heatmap_max,
+            first_id: ids[0],
+        }
+    });
+    let classes = if args.classify.is_empty() {
+        None
+    } else {
+        let rules = args
+            .classify
+            .iter()
+            .map(|rule| {
+                let (re, color) = parse_class(rule)?;
+                Ok((
+                    re,
+                    reserved_colors.reserve(color_set(color, false, markup.as_ref())?),
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Some(Classes { rules })
+    };
+    let value_map = if args.map.is_empty() {
+        None
+    } else {
+        let rules = args
+            .map
+            .iter()
+            .map(|rule| {
+                let (re, color) = parse_rule(rule)?;
+                Ok((
+                    re,
+                    reserved_colors.reserve(color_set(color, false, markup.as_ref())?),
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Some(ValueMap {
+            group: args.map_group.clone(),
+            rules,
+        })
+    };
+    let duplicate_id = if args.highlight_duplicates {
+        Some(reserved_colors.reserve(color_set(presets::DUPLICATE_COLOR, false, markup.as_ref())?))
+    } else {
+        None
+    };
+    let number_id = if args.numbers {
+        Some(reserved_colors.reserve(color_set(presets::NUMBER_COLOR, false, markup.as_ref())?))
+    } else {
+        None
+    };
+    let brackets = match &args.balanced {
+        Some(spec) => {
+            let pairs = BracketPairs::parse(spec)?;
+            let ids = presets::BRACKET_COLORS
+                .iter()
+                .map(|spec| Ok(reserved_colors.reserve(color_set(spec, false, markup.as_ref())?)))
+                .collect::<Result<Vec<_>>>()?;
+            Some((pairs, ids[0]))
         }
+        None => None,
+    };
+    let tint_first_id = if args.tint_by_file {
+        let ids = presets::TINT_COLORS
+            .iter()
+            .map(|spec| Ok(reserved_colors.reserve(color_set(spec, true, markup.as_ref())?)))
+            .collect::<Result<Vec<_>>>()?;
+        ids.first().copied()
+    } else {
+        None
+    };
+    let gap_style = if args.dim_unmatched {
+        Some(GapStyle::Dim)
+    } else {
+        args.gap_style
+    };
+    let gap_id = gap_style.map(|style| {
+        let style = match style {
+            GapStyle::Dim => presets::GapStyle::Dim,
+        };
+        reserved_colors.reserve(style.color_set(markup.as_ref()))
+    });
+    let count_id = args
+        .count_inline
+        .then(|| reserved_colors.reserve(presets::GapStyle::Dim.color_set(markup.as_ref())));
+    let repeated_line_id = if args.highlight_repeated_lines {
+        Some(reserved_colors.reserve(color_set(
+            presets::REPEATED_LINE_COLOR,
+            true,
+            markup.as_ref(),
+        )?))
+    } else {
+        None
+    };
+    let separator_id = match &args.group_separator_color {
+        Some(spec) => Some(reserved_colors.reserve(color_set(spec, false, markup.as_ref())?)),
+        None => None,
+    };
+    let mut repeated_lines = args
+        .highlight_repeated_lines
+        .then(|| RepeatedLines::new(args.max_remembered_lines));
+    let escape_error_id = if args.highlight_ansi_errors {
+        Some(reserved_colors.reserve(color_set(
+            presets::ESCAPE_ERROR_COLOR,
+            true,
+            markup.as_ref(),
+        )?))
+    } else {
+        None
+    };
+    let show_control_as = match args.show_control {
+        Some(notation) => Some((
+            reserved_colors.reserve(color_set(presets::CONTROL_COLOR, true, markup.as_ref())?),
+            match notation {
+                ControlNotation::Caret => control::ControlNotation::Caret,
+                ControlNotation::Pictures => control::ControlNotation::Pictures,
+            },
+        )),
+        None => None,
+    };
+    let non_ascii_id = if args.non_ascii {
+        Some(reserved_colors.reserve(color_set(presets::NON_ASCII_COLOR, true, markup.as_ref())?))
+    } else {
+        None
+    };
+    // a copy of the palette, so that the characters of a match, or the matches of a line, can
+    // cycle through all the colors
+    let palette_first_id =
+        (args.highlight_char || args.color_scope != ColorScope::Pattern).then(|| {
+            colors
+                .iter()
+                .map(|c| reserved_colors.reserve(c.clone()))
+                .min()
+                .unwrap_or_default()
+        });
+    let char_first_id = palette_first_id.filter(|_| args.highlight_char);
+    let mut color_cycle = palette_first_id
+        .filter(|_| args.color_scope != ColorScope::Pattern)
+        .map(|first_id| {
+            ColorCycle::new(
+                first_id,
+                colors.len(),
+                pattern_color_count,
+                args.color_scope == ColorScope::Line,
+            )
+        });
+    let match_options = MatchOptions {
+        vary_group_colors,
+        full_match_highlight: args.full_match_highlight || args.per_pattern_color,
+        // the regexps of the patterns come last, in reverse order
+        full_match_regexps: args
+            .full_match_pattern
+            .iter()
+            .map(|n| regexps[regexps.len() - n.get()].as_str().to_string())
+            .collect(),
+        overlapping: args.overlapping,
+        min_length: args.min_length,
+        max_ranges: args.max_per_line,
+        alternate_offset,
+        nth: args.nth.map(NonZeroUsize::get),
+        longest_match: args
+            .highlight_longest_match_only
+            .then(|| LongestMatches::new(&regexps, args.ignore_case)),
+    };
+    let ids = regexp_ids(&regexps, &match_options);
+    // the ids of the colors of the patterns of the groups, which come after the word lists, in
+    // reverse order
+    let group_ids = ids
+        .iter()
+        .skip(args.word_list.len())
+        .take(groups.len())
+        .rev()
+        .cloned()
+        .collect::<Vec<_>>();
+    let combined = CombinedPatterns::new(&regexps, args.ignore_case);
+    let decoder = args.decode.map(|encoding| {
+        let encoding = match encoding {
+            Encoding::Base64 => decode::Encoding::Base64,
+            Encoding::Hex => decode::Encoding::Hex,
+        };
+        Decoder::new(encoding, ids.iter().map(|ids| ids.start).collect())
+    });
+    let group_colors = GroupColors(
+        groups
+            .iter()
+            .zip(&group_ids)
+            .filter_map(|(group, ids)| group.color.as_ref().map(|color| (ids, color)))
+            .map(|(ids, color)| {
+                let id = reserved_colors.reserve(color_set(color, false, markup.as_ref())?);
+                Ok((ids.clone(), id))
+            })
+            .collect::<Result<Vec<_>>>()?,
+    );
+    // names for --output=positions and ndjson
+    let range_name = |id: usize| -> &str {
+        match id {
+            _ if id < pattern_color_count => &keys[pattern_color_count - 1 - id],
+            _ if let Some(offset) = alternate_offset
+                && (offset..offset + pattern_color_count).contains(&id) =>
+            {
+                &keys[pattern_color_count - 1 - (id - offset)]
+            }
+            _ if kv_ids.is_some_and(|(key_id, _)| id == key_id) => "key",
+            _ if kv_ids.is_some_and(|(_, value_id)| id == value_id) => "value",
+            _ if heatmap.as_ref().is_some_and(|heatmap| {
+                (heatmap.first_id..heatmap.first_id + heatmap::STEPS).contains(&id)
+            }) =>
+            {
+                "heatmap"
+            }
+            _ if classes
+                .as_ref()
+                .is_some_and(|classes| classes.rules.iter().any(|(_, rule_id)| *rule_id == id)) =>
+            {
+                "class"
+            }
+            _ if let Some(rule) = value_map.as_ref().and_then(|value_map| {
+                value_map
+                    .rules
+                    .iter()
+                    .position(|(_, rule_id)| *rule_id == id)
+            }) =>
+            {
+                &args.map[rule]
+            }
+            _ if duplicate_id == Some(id) => "duplicate",
+            _ if number_id == Some(id) => "number",
+            _ if brackets.as_ref().is_some_and(|(_, first_id)| {
+                (*first_id..first_id + presets::BRACKET_COLORS.len()).contains(&id)
+            }) =>
+            {
+                "bracket"
+            }
+            _ if tint_first_id.is_some_and(|first_id| {
+                (first_id..first_id + presets::TINT_COLORS.len()).contains(&id)
+            }) =>
+            {
+                "tint"
+            }
+            _ if gap_id == Some(id) => "gap",
+            _ if count_id == Some(id) => "count",
+            _ if repeated_line_id == Some(id) => "repeated-line",
+            _ if separator_id == Some(id) => "group-separator",
+            _ if escape_error_id == Some(id) => "escape-error",
+            _ if show_control_as
+                .as_ref()
+                .is_some_and(|(control_id, _)| *control_id == id) =>
+            {
+                "control"
+            }
+            _ if non_ascii_id == Some(id) => "non-ascii",
+            _ if palette_first_id
+                .is_some_and(|first_id| (first_id..first_id + colors.len()).contains(&id)) =>
+            {
+                "palette"
+            }
+            _ if let Some((group, _)) = groups.iter().zip(&group_ids).find(|(_, ids)| {
+                group_colors
+                    .0
+                    .iter()
+                    .any(|(color_ids, color_id)| color_ids == *ids && *color_id == id)
+            }) =>
+            {
+                &group.name
+            }
+            // every id reserved above has a name
+            _ => "unknown",
+        }
+    };
+
+    // the dashboard is silently turned off if the output is not a terminal
+    let mut dashboard = if args.dashboard
+        && args.output_file.is_none()
+        && !paging
+        && !svg
+        && !markdown
+        && io::stdout().is_terminal()
+    {
+        terminal_size::terminal_size()
+            .and_then(|(Width(cols), Height(rows))| Dashboard::new(rows, cols))
+    } else {
+        None
+    };
+    let mut match_counts = vec![0; pattern_names.len()];
+    let mut stats = args.stats.then(|| (Stats::default(), Instant::now()));
+    let mut measure = args.measure.then(Measure::default);
+    let mut distinct_matches = args
+        .count_distinct
+        .then(|| DistinctMatches::new(pattern_names.len()));
+    let mut pattern_times = args
+        .benchmark_patterns
+        .then(|| PatternTimes::new(pattern_names.len()));
+    if let Some(dashboard) = &mut dashboard {
+        dashboard.start(&mut io::stdout())?;
     }
 
-    let stdin = io::stdin();
-    for line in stdin.lock().lines() {
-        let mut line = line?;
-        let mut ranges = match_line(
-            &line,
-            &regexps,
-            vary_group_colors,
-            args.full_match_highlight,
-        );
-        if ranges.is_empty() {
-            if args.only_matching_lines {
-                continue;
+    let mut sample = args.sample.map(|n| {
+        let rng = args.seed.map_or_else(Rng::from_time, Rng::new);
+        Reservoir::new(n, rng)
+    });
+
+    let render = |mut line: String, ranges: &[RangeWithId]| {
+        let mut linked_ranges;
+        let ranges = if args.hyperlinks {
+            linked_ranges = ranges.to_vec();
+            // only the matches of the patterns can be URLs, not e.g. the ranges of --dim-unmatched
+            add_hyperlinks(&mut line, &mut linked_ranges, |id| {
+                id < pattern_color_count
+                    || alternate_offset
+                        .is_some_and(|offset| (offset..offset + pattern_color_count).contains(&id))
+            });
+            &linked_ranges
+        } else {
+            ranges
+        };
+        if !ranges.is_empty() {
+            let colorize = if args.minimal_escapes {
+                colorize_minimal
+            } else {
+                colorize
+            };
+            colorize(
+                &mut line,
+                &colors,
+                &reserved_colors.colors,
+                ranges,
+                pattern_color_count,
+            );
+        }
+        if args.color_escape {
+            line = escapes::encode(&line);
+        }
+        line
+    };
+    let mut aligned = Vec::new();
+    let priorities = args
+        .sort_by_pattern_priority
+        .then(|| Priorities::new(&regexps, vary_group_colors, alternate_offset));
+    let mut sorted = Vec::new();
+    let mut histogram = args.histogram.then(Histogram::default);
+    let mut aligned_terminators = Vec::new();
+    let mut filter = args.filter_command.as_deref().map(FilterCommand::new);
+    let mut replace = args.unsafe_replace.as_deref().map(ReplaceCommand::new);
+    let trim_context_width = args.trim_context.map(|_| {
+        terminal_size::terminal_size()
+            .map_or(DEFAULT_TERMINAL_WIDTH, |(Width(cols), _)| cols as usize)
+    });
+    let profile_threshold = Duration::from_secs_f64(args.profile_threshold.max(0.0) / 1000.0);
+
+    let lines: Box<dyn Iterator<Item = _>> = match &record_separator {
+        Some(separator) => Box::new(read_records(&inputs, args.decompress, encoding, separator)),
+        None => Box::new(read_lines(
+            &inputs,
+            args.decompress,
+            encoding,
+            args.split_cr,
+        )),
+    };
+    if args.group_legend {
+        let legend = group_legend(&regexps, &match_options, &colors, pattern_color_count);
+        if !legend.is_empty() {
+            writeln!(out, "{}", render(legend, &[])).with_context(write_error)?;
+        }
+        for (group, ids) in groups.iter().zip(&group_ids) {
+            let (legend, mut ranges) = group.legend(ids.start);
+            group_colors.recolor(&mut ranges);
+            writeln!(out, "{}", render(legend, &ranges)).with_context(write_error)?;
+        }
+    }
+
+    let mut manifest = match &args.manifest {
+        Some(path) => Some(Manifest::new(
+            create_output(Some(path))?,
+            inputs
+                .iter()
+                .map(|input| input.display().to_string())
+                .collect(),
+        )),
+        None => None,
+    };
+    let manifest_error = || {
+        let path = args.manifest.as_ref().unwrap();
+        format!("cannot write to {}", path.display())
+    };
+
+    // --output=positions and ndjson name the file of a line only if there are several
+    let file_names = (inputs.len() > 1).then(|| {
+        inputs
+            .iter()
+            .map(|input| input.display().to_string())
+            .collect::<Vec<_>>()
+    });
+    // the number of the line within its input, for --output=positions and ndjson
+    let mut line_number = 0;
+    let mut line_input = None;
+
+    // reused from line to line, to avoid an allocation per line
+    let mut ranges = Vec::new();
+    for (line_idx, line) in lines.enumerate() {
+        let Line {
+            text: line,
+            terminator,
+            input,
+        } = line?;
+        let line = if args.sanitize { sanitize(&line) } else { line };
+        if line_input != Some(input) {
+            line_input = Some(input);
+            line_number = 0;
+        }
+        line_number += 1;
+        let file_name = file_names.as_ref().map(|names| names[input].as_str());
+        if let Some(manifest) = &mut manifest {
+            manifest.next_line(input).with_context(manifest_error)?;
+        }
+        let repeated = repeated_lines
+            .as_mut()
+            .is_some_and(|repeated_lines| repeated_lines.repeated(&line));
+        let (line, escape_error_ranges) = match escape_error_id
+            .and_then(|escape_error_id| mark_malformed(&line, escape_error_id))
+        {
+            Some((marked, escape_error_ranges)) => (marked, escape_error_ranges),
+            None => (line, Vec::new()),
+        };
+        if selector.toggle(&line) {
+            continue;
+        }
+        let highlight_line = selector.highlights(&line);
+        let class_id = classes
+            .as_ref()
+            .filter(|_| highlight_line)
+            .and_then(|classes| classes.class(&line));
+        // lines without the anchor are not highlighted at all
+        let (offset, text) = match highlight_line.then(|| selector.text(&line)).flatten() {
+            Some((offset, text)) => (offset, Some(text)),
+            None => (0, None),
+        };
+        let start = args.profile.then(Instant::now);
+        // patterns are matched against the normalized text, and the ranges mapped back
+        let normalized = match (text, normalization) {
+            (Some(text), Some(normalization)) if !is_normalized(text, normalization) => {
+                Some(Normalized::new(text, normalization))
+            }
+            _ => None,
+        };
+        let matched_text = normalized.as_ref().map(|n| n.text.as_str()).or(text);
+        match matched_text {
+            Some(text) if let Some(heatmap) = &heatmap => {
+                ranges.clear();
+                heatmap.match_line(text, &regexps, &mut ranges);
+            }
+            Some(text) if args.hexdump => {
+                match_hexdump_line(text, &regexps, &match_options, &mut ranges)
+            }
+            Some(text) if args.in_quotes => {
+                match_in_quotes(text, &regexps, &match_options, &mut ranges)
+            }
+            Some(text) if let Some(tag) = &args.in_tag => {
+                match_in_tag(text, tag, &regexps, &match_options, &mut ranges)
+            }
+            Some(text) => {
+                match &combined {
+                    Some(combined) => {
+                        combined.match_line_into(text, &regexps, &match_options, &mut ranges)
+                    }
+                    None => match_line_into(text, &regexps, &match_options, &mut ranges),
+                }
+                if let Some(value_map) = &value_map {
+                    value_map.recolor(text, &regexps, &mut ranges);
+                }
+                if let Some(decoder) = &decoder {
+                    decoder.match_line(text, &regexps, &mut ranges);
+                }
+            }
+            None => ranges.clear(),
+        }
+        if let Some(normalized) = &normalized {
+            normalized.map_ranges(&mut ranges);
+        }
+        // before --filter-command runs, which is not part of matching
+        if let Some(start) = start
+            && let Some(report) = slow_line_report(line_idx + 1, start.elapsed(), profile_threshold)
+        {
+            eprintln!("{report}");
+        }
+        if let Some(filter) = &mut filter
+            && let Some(text) = text
+        {
+            filter.retain_accepted(text, &mut ranges)?;
+        }
+        if let Some(duplicate_id) = duplicate_id
+            && let Some(text) = text
+        {
+            keep_duplicates(text, &mut ranges, duplicate_id);
+        }
+        // not counted by --profile
+        if let Some(pattern_times) = &mut pattern_times
+            && let Some(text) = matched_text
+        {
+            pattern_times.time_line(text, &regexps, &match_options);
+        }
+        if let Some((key_id, value_id)) = kv_ids
+            && let Some(text) = text
+        {
+            match_key_values(text, &mut ranges, key_id, value_id);
+        }
+        if let Some(number_id) = number_id
+            && let Some(text) = text
+        {
+            match_numbers(text, &mut ranges, number_id, args.decimal_comma);
+        }
+        if let Some((pairs, first_id)) = &brackets
+            && let Some(text) = text
+        {
+            pairs.match_line(text, &mut ranges, *first_id, presets::BRACKET_COLORS.len());
+        }
+        // map the offsets in the trimmed text back to the original line
+        inc_ranges(&mut ranges, offset);
+        // malformed escape sequences take precedence over matches
+        if !escape_error_ranges.is_empty() {
+            for r in std::mem::replace(&mut ranges, escape_error_ranges) {
+                add_range(&mut ranges, r);
+            }
+        }
+        let update_dashboard = dashboard.is_some() && !ranges.is_empty();
+        if let Some((stats, _)) = &mut stats {
+            stats.add_line(!ranges.is_empty());
+        }
+        // what is highlighted is counted, after everything changing it, like --nth
+        if dashboard.is_some() || stats.is_some() {
+            count_ranges(&ranges, &ids, alternate_offset, &mut match_counts);
+        }
+        if let Some(distinct_matches) = &mut distinct_matches
+            && let Some(text) = text
+        {
+            distinct_matches.add_line(text, &regexps);
+        }
+        if let Some(manifest) = &mut manifest {
+            manifest
+                .add_matches(&line, &ranges, range_name)
+                .with_context(manifest_error)?;
+        }
+        if positions {
+            let positions = format_positions(file_name, line_number, &ranges, range_name);
+            write!(out, "{positions}").with_context(write_error)?;
+            if out.reached() {
+                break;
+            }
+            continue;
+        }
+        if ndjson {
+            let record = format_ndjson(file_name, line_number, &line, &ranges, range_name);
+            write!(out, "{record}").with_context(write_error)?;
+            out.flush().with_context(write_error)?;
+            if out.reached() {
+                break;
+            }
+            continue;
+        }
+        let mut line = match &mut replace {
+            Some(replace) => {
+                let mut line = line;
+                replace.replace_ranges(&mut line, &mut ranges, pattern_color_count)?;
+                line
+            }
+            None => line,
+        };
+        if let Some(rewriter) = &rewriter
+            && !ranges.is_empty()
+        {
+            rewriter.rewrite(&mut line, &regexps, &match_options, &mut ranges);
+        }
+        if args.extract {
+            match extract(&line, &regexps, &match_options).filter(|_| highlight_line) {
+                Some((fields, field_ranges)) => {
+                    line = fields;
+                    ranges = field_ranges;
+                }
+                None if args.extract_unmatched => ranges.clear(),
+                None => continue,
             }
+        }
+        if let Some(histogram) = &mut histogram {
+            for r in &ranges {
+                histogram.add(&line[r.start_idx..r.end_idx], r.id);
+            }
+            continue;
+        }
+        // before ranges get split or added
+        let match_count = ranges.len();
+        pad_ranges(&line, &mut ranges, args.pad_before, args.pad_after);
+        // before the ranges get recolored
+        let priority = priorities.as_ref().and_then(|p| p.line_priority(&ranges));
+        group_colors.recolor(&mut ranges);
+        if let Some(non_ascii_id) = non_ascii_id
+            && highlight_line
+        {
+            match_non_ascii(&line, &mut ranges, non_ascii_id);
+        }
+        if let Some(color_cycle) = &mut color_cycle {
+            color_cycle.recolor(&mut ranges);
+        }
+        if let Some(char_first_id) = char_first_id {
+            split_into_chars(&line, &mut ranges, |id, i| {
+                let color_idx = if id < pattern_color_count {
+                    color_index(id, pattern_color_count, colors.len())
+                } else if (char_first_id..char_first_id + colors.len()).contains(&id) {
+                    // already recolored by the color cycle
+                    id - char_first_id
+                } else {
+                    return id;
+                };
+                char_first_id + (color_idx + i) % colors.len()
+            });
+        }
+        let mut line = match trim_context_width {
+            Some(width) if !ranges.is_empty() && visible_width(&line) > width => {
+                let (trimmed, trimmed_ranges) = trim_context(
+                    &line,
+                    &ranges,
+                    args.trim_context.unwrap_or_default(),
+                    args.group_separator.as_deref().unwrap_or(ELLIPSIS),
+                    separator_id,
+                );
+                ranges = trimmed_ranges;
+                trimmed
+            }
+            _ => line,
+        };
+        if ranges.is_empty() && args.only_matching_lines {
+            continue;
+        }
+        let matched = !ranges.is_empty();
+        if let Some((control_id, notation)) = show_control_as {
+            show_control(&mut line, &mut ranges, control_id, notation);
+        }
+        if let Some(count_id) = count_id
+            && matched
+        {
+            append_count(&mut line, &mut ranges, match_count, count_id);
+        }
+        if let Some(class_id) = class_id {
+            tint_line(&line, &mut ranges, class_id);
+        }
+        if let Some(tint_first_id) = tint_first_id {
+            tint_line(
+                &line,
+                &mut ranges,
+                tint_first_id + input % presets::TINT_COLORS.len(),
+            );
+        }
+        if let Some(gap_id) = gap_id {
+            tint_line(&line, &mut ranges, gap_id);
+        }
+        if let Some(repeated_line_id) = repeated_line_id.filter(|_| repeated && highlight_line) {
+            tint_line(&line, &mut ranges, repeated_line_id);
+        }
+        if args.align {
+            aligned.push((line, ranges.clone()));
+            aligned_terminators.push(terminator);
         } else {
-            colorize(&mut line, &colors, &mut ranges, pattern_color_count);
+            let len = line.len() + terminator.len();
+            let line = render(line, &ranges);
+            match &mut sample {
+                Some(sample) if matched => sample.offer((line, terminator)),
+                Some(_) => {}
+                None if let Some(measure) = &mut measure => {
+                    measure.add_line(len, line.len() + terminator.len());
+                }
+                None if priorities.is_some() => sorted.push((priority, (line, terminator))),
+                None => {
+                    write!(out, "{line}{terminator}").with_context(write_error)?;
+                    // unlike newlines, carriage returns do not flush stdout
+                    if terminator == "\r" {
+                        out.flush().with_context(write_error)?;
+                    }
+                    if out.reached() {
+                        break;
+                    }
+                }
+            }
+        }
+        if let Some(dashboard) = &dashboard
+            && update_dashboard
+        {
+            dashboard.update(&mut io::stdout(), &pattern_names, &match_counts)?;
+        }
+    }
+    if args.align {
+        align_first_matches(&mut aligned);
+        for ((line, ranges), terminator) in aligned.into_iter().zip(aligned_terminators) {
+            let line = render(line, &ranges);
+            write!(out, "{line}{terminator}").with_context(write_error)?;
+            if out.reached() {
+                break;
+            }
+        }
+    }
+    if let Some(histogram) = histogram {
+        for (line, ranges) in histogram.format(HISTOGRAM_BAR_WIDTH) {
+            writeln!(out, "{}", render(line, &ranges)).with_context(write_error)?;
+            if out.reached() {
+                break;
+            }
         }
-        println!("{line}");
+    }
+    if priorities.is_some() {
+        terminate_lines(&mut sorted);
+        sort_by_priority(&mut sorted);
+        for (_, (line, terminator)) in sorted {
+            write!(out, "{line}{terminator}").with_context(write_error)?;
+            if out.reached() {
+                break;
+            }
+        }
+    }
+    if let Some(sample) = sample {
+        for (line, terminator) in sample.into_items() {
+            write!(out, "{line}{terminator}").with_context(write_error)?;
+            if out.reached() {
+                break;
+            }
+        }
+    }
+    if let Some(measure) = measure {
+        writeln!(out, "{}", measure.report()).with_context(write_error)?;
+    }
+    if let Some(manifest) = &mut manifest {
+        manifest.finish().with_context(manifest_error)?;
+    }
+    out.flush().with_context(write_error)?;
+    drop(out);
+    if let Some(svg_options) = &svg_options {
+        let svg_text = String::from_utf8_lossy(&collected);
+        write!(target, "{}", render_svg(&svg_text, svg_options)).with_context(write_error)?;
+        target.flush().with_context(write_error)?;
+    }
+    if markdown {
+        let text = String::from_utf8_lossy(&collected);
+        write!(target, "{}", render_markdown(&text)).with_context(write_error)?;
+        target.flush().with_context(write_error)?;
+    }
+    if let Some(dashboard) = &mut dashboard {
+        dashboard.finish(&mut io::stdout(), &pattern_names, &match_counts)?;
+    }
+    if let Some(distinct_matches) = distinct_matches {
+        eprintln!(
+            "{}",
+            distinct_matches.report(&pattern_names, args.list_distinct)
+        );
+    }
+    if let Some(pattern_times) = pattern_times {
+        eprintln!("{}", pattern_times.report(&pattern_names));
+    }
+    if let Some((stats, start)) = stats {
+        eprintln!(
+            "{}",
+            stats.summary(&pattern_names, &match_counts, start.elapsed())
+        );
     }
     Ok(())
 }
@@ -331,100 +1847,118 @@ fn run(args: &Args) -> Result<()> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use clap::error::ErrorKind;
     use rstest::rstest;
+    use std::fs;
+    use std::sync::atomic::{AtomicUsize, Ordering};
 
-    fn r(start_idx: usize, end_idx: usize, id: usize) -> RangeWithId {
-        RangeWithId {
-            start_idx,
-            end_idx,
-            id,
-        }
+    #[rstest]
+    #[case::short(&["-n"], true, false)]
+    #[case::long(&["--no-highlight"], true, false)]
+    #[case::alias(&["--fg-only"], true, false)]
+    #[case::deprecated_short(&["-h"], false, true)]
+    #[case::neither(&[], false, false)]
+    fn test_no_highlight_flags(
+        #[case] flags: &[&str],
+        #[case] no_highlight: bool,
+        #[case] no_highlight_deprecated: bool,
+    ) {
+        let args = ["colorexp"].iter().chain(flags).chain(&["x"]);
+        let args = Args::try_parse_from(args).unwrap();
+        assert_eq!(args.no_highlight, no_highlight);
+        assert_eq!(args.no_highlight_deprecated, no_highlight_deprecated);
     }
 
     #[rstest]
-    #[case::before_1(
-        vec![r(5, 8, 1)],
-        r(3, 5, 2),
-        vec![r(3, 5, 2), r(5, 8, 1)],
-    )]
-    #[case::before_2(
-        vec![r(5, 8, 1)],
-        r(3, 4, 2),
-        vec![r(3, 4, 2), r(5, 8, 1)],
-    )]
-    #[case::after_1(
-        vec![r(1, 3, 0)],
-        r(3, 5, 2),
-        vec![r(1, 3, 0), r(3, 5, 2)],
-    )]
-    #[case::after_2(
-        vec![r(1, 3, 0)],
-        r(4, 5, 2),
-        vec![r(1, 3, 0), r(4, 5, 2)],
-    )]
-    #[case::in_between_1(
-        vec![r(1, 3, 0), r(5, 8, 1)],
-        r(3, 5, 2),
-        vec![r(1, 3, 0), r(3, 5, 2), r(5, 8, 1)],
-    )]
-    #[case::in_between_2(
-        vec![r(1, 3, 0), r(5, 8, 1)],
-        r(3, 4, 2),
-        vec![r(1, 3, 0), r(3, 4, 2), r(5, 8, 1)],
-    )]
-    #[case::in_between_3(
-        vec![r(1, 3, 0), r(5, 8, 1)],
-        r(4, 5, 2),
-        vec![r(1, 3, 0), r(4, 5, 2), r(5, 8, 1)],
-    )]
-    #[case::partial_overlap(
-        vec![r(1, 3, 0), r(5, 8, 1)],
-        r(2, 6, 2),
-        vec![r(1, 3, 0), r(3, 5, 2), r(5, 8, 1)],
-    )]
-    #[case::full_overlap(
-        vec![r(1, 3, 0), r(5, 8, 1)],
-        r(6, 7, 2),
-        vec![r(1, 3, 0), r(5, 8, 1)],
-    )]
-    #[case::overlap_and_extend(
-        vec![r(1, 5, 0), r(10, 15, 1)],
-        r(3, 12, 2),
-        vec![r(1, 5, 0), r(5, 10, 2), r(10, 15, 1)],
-    )]
-    fn test_add_range(
-        #[case] existing: Vec<RangeWithId>,
-        #[case] new_range: RangeWithId,
-        #[case] expected: Vec<RangeWithId>,
-    ) {
-        let mut actual = existing.clone();
-        add_range(&mut actual, new_range);
-        assert_eq!(actual, expected);
+    #[case::new(&["-n", "-H"])]
+    #[case::deprecated(&["-h", "-H"])]
+    fn test_no_highlight_conflicts_with_only_highlight(#[case] flags: &[&str]) {
+        let args = ["colorexp"].iter().chain(flags).chain(&["x"]);
+        let err = Args::try_parse_from(args).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ArgumentConflict);
     }
 
     #[test]
-    fn test_match_line() {
-        let regexps = vec![
-            RegexBuilder::new("t")
-                .case_insensitive(false)
-                .build()
-                .unwrap(),
-        ];
-        let ranges = match_line("test", &regexps, false, false);
-        assert_eq!(
-            ranges,
-            vec![
-                RangeWithId {
-                    start_idx: 0,
-                    end_idx: 1,
-                    id: 0
-                },
-                RangeWithId {
-                    start_idx: 3,
-                    end_idx: 4,
-                    id: 0
-                },
-            ]
+    fn test_dim_unmatched_conflicts_with_gap_style() {
+        let args = ["colorexp", "--dim-unmatched", "--gap-style", "dim", "x"];
+        let err = Args::try_parse_from(args).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ArgumentConflict);
+    }
+
+    /// highlight runs colorexp with the flags on the input, and returns what it writes. The input and
+    /// output go through files, as stdin and stdout are those of the tests.
+    fn highlight(input: &str, flags: &[&str]) -> String {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let count = COUNT.fetch_add(1, Ordering::Relaxed);
+        let dir = std::env::temp_dir();
+        let base = format!("colorexp-test-{}-{count}", std::process::id());
+        let (input_path, output_path) = (
+            dir.join(format!("{base}.in")),
+            dir.join(format!("{base}.out")),
         );
+        fs::write(&input_path, input).unwrap();
+        let args = [
+            "colorexp".as_ref(),
+            "--input".as_ref(),
+            input_path.as_os_str(),
+        ]
+        .into_iter()
+        .chain(["--output-file".as_ref(), output_path.as_os_str()])
+        .chain(flags.iter().map(|flag| flag.as_ref()));
+        let result = run(&Args::try_parse_from(args).unwrap());
+        let output = fs::read_to_string(&output_path).unwrap_or_default();
+        let _ = fs::remove_file(&input_path);
+        let _ = fs::remove_file(&output_path);
+        result.unwrap();
+        output
+    }
+
+    #[test]
+    fn test_hyperlinks_dim_unmatched() {
+        let output = highlight(
+            "x https://a.com/b y\n",
+            &["--hyperlinks", "--dim-unmatched", r"https://\S+"],
+        );
+        assert!(
+            output.contains("\x1b]8;;https://a.com/b\x1b\\"),
+            "{output:?}"
+        );
+    }
+
+    #[rstest]
+    #[case::kv(&["--kv"], "1:0:1:key\n1:2:3:value\n")]
+    #[case::numbers(&["--numbers"], "1:2:3:number\n")]
+    #[case::group_color(&["--group", "errs=red:a"], "1:0:1:errs\n")]
+    fn test_positions_range_names(#[case] flags: &[&str], #[case] expected: &str) {
+        let flags = ["--output", "positions"]
+            .iter()
+            .chain(flags)
+            .copied()
+            .collect::<Vec<_>>();
+        assert_eq!(highlight("a=1\n", &flags), expected);
+    }
+
+    #[rstest]
+    #[case::lines(&[], "\x1b[31ma\x1b[0m1")]
+    #[case::positions(&["--output", "positions"], "1:0:1:a")]
+    #[case::ndjson(&["--output", "ndjson"], r#"{"line":1,"text":"a1""#)]
+    #[case::histogram(&["--histogram"], "a 2 ")]
+    fn test_max_output_bytes(#[case] flags: &[&str], #[case] first_line: &str) {
+        let flags = ["-n", "--max-output-bytes", "1", "a", "b"]
+            .iter()
+            .chain(flags)
+            .copied()
+            .collect::<Vec<_>>();
+        let output = highlight("a1\na2\nb3\n", &flags);
+        // output stops after the line reaching the limit
+        assert_eq!(output.lines().count(), 1, "{output:?}");
+        assert!(output.starts_with(first_line), "{output:?}");
+    }
+
+    #[test]
+    fn test_help() {
+        let err = Args::try_parse_from(["colorexp", "--help"]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::DisplayHelp);
+        assert!(!err.to_string().contains("  -h"));
     }
 }
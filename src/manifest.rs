@@ -0,0 +1,183 @@
+use crate::RangeWithId;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+/// json_string returns s as a JSON string, in quotes.
+pub(crate) fn json_string(s: &str) -> String {
+    let mut json = String::with_capacity(s.len() + 2);
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => write!(json, "\\u{:04x}", c as u32).unwrap(),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// json_matches returns the ranges of the line as a JSON array of objects like
+/// `{"start":4,"end":9,"pattern":"error","text":"error"}`, with byte offsets. The patterns of the
+/// ranges are looked up by their ids.
+pub(crate) fn json_matches<'a>(
+    line: &str,
+    ranges: &[RangeWithId],
+    name: impl Fn(usize) -> &'a str,
+) -> String {
+    let matches = ranges
+        .iter()
+        .map(|r| {
+            format!(
+                "{{\"start\":{},\"end\":{},\"pattern\":{},\"text\":{}}}",
+                r.start_idx,
+                r.end_idx,
+                json_string(name(r.id)),
+                json_string(&line[r.start_idx..r.end_idx])
+            )
+        })
+        .collect::<Vec<_>>();
+    format!("[{}]", matches.join(","))
+}
+
+/// Manifest writes a summary of the matches in all inputs for --manifest, as NDJSON: a JSON object
+/// for every line with matches, like
+/// `{"file":"a.log","line":3,"matches":[{"start":4,"end":9,"pattern":"error","text":"error"}]}`,
+/// with byte offsets and 1-based line numbers, followed by an object like
+/// `{"file":"a.log","lines":10,"matched_lines":1,"matches":1}` once all lines of an input are
+/// read. Inputs without lines get no records. Every record is written as soon as it is complete,
+/// so that the manifest takes no memory.
+pub struct Manifest<W: Write> {
+    out: W,
+    /// The names of the inputs.
+    files: Vec<String>,
+    /// The current input, if any line has been read.
+    input: Option<usize>,
+    lines: usize,
+    matched_lines: usize,
+    matches: usize,
+}
+
+impl<W: Write> Manifest<W> {
+    pub fn new(out: W, files: Vec<String>) -> Self {
+        Self {
+            out,
+            files,
+            input: None,
+            lines: 0,
+            matched_lines: 0,
+            matches: 0,
+        }
+    }
+
+    /// next_line must be called for every line read, including those that are not highlighted, so
+    /// that the lines are numbered within their inputs.
+    pub fn next_line(&mut self, input: usize) -> io::Result<()> {
+        if self.input != Some(input) {
+            self.finish_file()?;
+            self.input = Some(input);
+        }
+        self.lines += 1;
+        Ok(())
+    }
+
+    /// add_matches writes the record of the current line, if it has ranges. The patterns of the
+    /// ranges are looked up by their ids.
+    pub fn add_matches<'a>(
+        &mut self,
+        line: &str,
+        ranges: &[RangeWithId],
+        name: impl Fn(usize) -> &'a str,
+    ) -> io::Result<()> {
+        let Some(input) = self.input.filter(|_| !ranges.is_empty()) else {
+            return Ok(());
+        };
+        self.matched_lines += 1;
+        self.matches += ranges.len();
+        writeln!(
+            self.out,
+            "{{\"file\":{},\"line\":{},\"matches\":{}}}",
+            json_string(&self.files[input]),
+            self.lines,
+            json_matches(line, ranges, name)
+        )
+    }
+
+    /// finish_file writes the summary of the current input, if any.
+    fn finish_file(&mut self) -> io::Result<()> {
+        if let Some(input) = self.input.take() {
+            writeln!(
+                self.out,
+                "{{\"file\":{},\"lines\":{},\"matched_lines\":{},\"matches\":{}}}",
+                json_string(&self.files[input]),
+                self.lines,
+                self.matched_lines,
+                self.matches
+            )?;
+        }
+        self.lines = 0;
+        self.matched_lines = 0;
+        self.matches = 0;
+        Ok(())
+    }
+
+    /// finish writes the summary of the last input, and flushes the manifest.
+    pub fn finish(&mut self) -> io::Result<()> {
+        self.finish_file()?;
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MatchOptions, compile_patterns, match_line};
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::plain("abc", r#""abc""#)]
+    #[case::quotes(r#"a "b" \c"#, r#""a \"b\" \\c""#)]
+    #[case::controls("a\tb\x01\n", r#""a\tb\u0001\n""#)]
+    #[case::unicode("café", r#""café""#)]
+    fn test_json_string(#[case] s: &str, #[case] expected: &str) {
+        assert_eq!(json_string(s), expected);
+    }
+
+    #[test]
+    fn test_manifest_two_files() {
+        let patterns = vec!["error".to_string(), r"\d+".to_string()];
+        let regexps = compile_patterns(&patterns, false, false).unwrap();
+        let name = |id: usize| patterns[patterns.len() - 1 - id].as_str();
+        let inputs = [(0, "ok"), (0, "error 42"), (1, "fine"), (1, "no \"error\"")];
+        let mut manifest = Manifest::new(Vec::new(), vec!["a.log".into(), "b.log".into()]);
+        for (input, line) in inputs {
+            manifest.next_line(input).unwrap();
+            let ranges = match_line(line, &regexps, &MatchOptions::default());
+            manifest.add_matches(line, &ranges, name).unwrap();
+        }
+        manifest.finish().unwrap();
+        assert_eq!(
+            String::from_utf8(manifest.out)
+                .unwrap()
+                .lines()
+                .collect::<Vec<_>>(),
+            vec![
+                r#"{"file":"a.log","line":2,"matches":[{"start":0,"end":5,"pattern":"error","text":"error"},{"start":6,"end":8,"pattern":"\\d+","text":"42"}]}"#,
+                r#"{"file":"a.log","lines":2,"matched_lines":1,"matches":2}"#,
+                r#"{"file":"b.log","line":2,"matches":[{"start":4,"end":9,"pattern":"error","text":"error"}]}"#,
+                r#"{"file":"b.log","lines":2,"matched_lines":1,"matches":1}"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_manifest_empty_input() {
+        let mut manifest = Manifest::new(Vec::new(), vec!["a.log".into()]);
+        manifest.finish().unwrap();
+        assert!(manifest.out.is_empty());
+    }
+}
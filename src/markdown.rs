@@ -0,0 +1,36 @@
+use crate::escapes::strip_sequences;
+
+/// render_markdown puts text, like the output of colorexp, into a fenced code block, to be pasted
+/// into Markdown documents. As Markdown cannot show colors, escape sequences are dropped, while
+/// markup tags are kept, annotating the matches. The fence is longer than any run of backticks in
+/// the text, so that the text cannot end the block.
+pub fn render_markdown(text: &str) -> String {
+    let text = strip_sequences(text);
+    let longest_run = text.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    let fence = "`".repeat(longest_run.max(2) + 1);
+    let newline = if text.is_empty() || text.ends_with('\n') {
+        ""
+    } else {
+        "\n"
+    };
+    format!("{fence}\n{text}{newline}{fence}\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::plain("a\nb\n", "```\na\nb\n```\n")]
+    #[case::missing_newline("a", "```\na\n```\n")]
+    #[case::empty("", "```\n```\n")]
+    #[case::colors_dropped("an \x1b[31merror\x1b[0m\n", "```\nan error\n```\n")]
+    #[case::markup_kept("an [[red]]error[[/]]\n", "```\nan [[red]]error[[/]]\n```\n")]
+    #[case::short_backtick_runs("`a` ``b``\n", "```\n`a` ``b``\n```\n")]
+    #[case::fence_in_text("```\n", "````\n```\n````\n")]
+    #[case::long_backtick_run("a `````b\n", "``````\na `````b\n``````\n")]
+    fn test_render_markdown(#[case] text: &str, #[case] expected: &str) {
+        assert_eq!(render_markdown(text), expected);
+    }
+}
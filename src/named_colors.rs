@@ -0,0 +1,436 @@
+/// NAMED_COLORS are the X11 color names, plus the CSS names missing from X11, with their RGB
+/// values, sorted by name. Where X11 and CSS disagree, like for `gray` or `purple`, the X11 value
+/// is used.
+static NAMED_COLORS: &[(&str, (u8, u8, u8))] = &[
+    ("aliceblue", (240, 248, 255)),
+    ("antiquewhite", (250, 235, 215)),
+    ("aqua", (0, 255, 255)),
+    ("aquamarine", (127, 255, 212)),
+    ("azure", (240, 255, 255)),
+    ("beige", (245, 245, 220)),
+    ("bisque", (255, 228, 196)),
+    ("black", (0, 0, 0)),
+    ("blanchedalmond", (255, 235, 205)),
+    ("blue", (0, 0, 255)),
+    ("blueviolet", (138, 43, 226)),
+    ("brown", (165, 42, 42)),
+    ("burlywood", (222, 184, 135)),
+    ("cadetblue", (95, 158, 160)),
+    ("chartreuse", (127, 255, 0)),
+    ("chocolate", (210, 105, 30)),
+    ("coral", (255, 127, 80)),
+    ("cornflowerblue", (100, 149, 237)),
+    ("cornsilk", (255, 248, 220)),
+    ("cyan", (0, 255, 255)),
+    ("darkblue", (0, 0, 139)),
+    ("darkcyan", (0, 139, 139)),
+    ("darkgoldenrod", (184, 134, 11)),
+    ("darkgray", (169, 169, 169)),
+    ("darkgreen", (0, 100, 0)),
+    ("darkgrey", (169, 169, 169)),
+    ("darkkhaki", (189, 183, 107)),
+    ("darkmagenta", (139, 0, 139)),
+    ("darkolivegreen", (85, 107, 47)),
+    ("darkorange", (255, 140, 0)),
+    ("darkorchid", (153, 50, 204)),
+    ("darkred", (139, 0, 0)),
+    ("darksalmon", (233, 150, 122)),
+    ("darkseagreen", (143, 188, 143)),
+    ("darkslateblue", (72, 61, 139)),
+    ("darkslategray", (47, 79, 79)),
+    ("darkslategrey", (47, 79, 79)),
+    ("darkturquoise", (0, 206, 209)),
+    ("darkviolet", (148, 0, 211)),
+    ("deeppink", (255, 20, 147)),
+    ("deepskyblue", (0, 191, 255)),
+    ("dimgray", (105, 105, 105)),
+    ("dimgrey", (105, 105, 105)),
+    ("dodgerblue", (30, 144, 255)),
+    ("firebrick", (178, 34, 34)),
+    ("floralwhite", (255, 250, 240)),
+    ("forestgreen", (34, 139, 34)),
+    ("fuchsia", (255, 0, 255)),
+    ("gainsboro", (220, 220, 220)),
+    ("ghostwhite", (248, 248, 255)),
+    ("gold", (255, 215, 0)),
+    ("goldenrod", (218, 165, 32)),
+    ("gray", (190, 190, 190)),
+    ("green", (0, 255, 0)),
+    ("greenyellow", (173, 255, 47)),
+    ("grey", (190, 190, 190)),
+    ("honeydew", (240, 255, 240)),
+    ("hotpink", (255, 105, 180)),
+    ("indianred", (205, 92, 92)),
+    ("ivory", (255, 255, 240)),
+    ("khaki", (240, 230, 140)),
+    ("lavender", (230, 230, 250)),
+    ("lavenderblush", (255, 240, 245)),
+    ("lawngreen", (124, 252, 0)),
+    ("lemonchiffon", (255, 250, 205)),
+    ("lightblue", (173, 216, 230)),
+    ("lightcoral", (240, 128, 128)),
+    ("lightcyan", (224, 255, 255)),
+    ("lightgoldenrod", (238, 221, 130)),
+    ("lightgoldenrodyellow", (250, 250, 210)),
+    ("lightgray", (211, 211, 211)),
+    ("lightgreen", (144, 238, 144)),
+    ("lightgrey", (211, 211, 211)),
+    ("lightpink", (255, 182, 193)),
+    ("lightsalmon", (255, 160, 122)),
+    ("lightseagreen", (32, 178, 170)),
+    ("lightskyblue", (135, 206, 250)),
+    ("lightslateblue", (132, 112, 255)),
+    ("lightslategray", (119, 136, 153)),
+    ("lightslategrey", (119, 136, 153)),
+    ("lightsteelblue", (176, 196, 222)),
+    ("lightyellow", (255, 255, 224)),
+    ("lime", (0, 255, 0)),
+    ("limegreen", (50, 205, 50)),
+    ("linen", (250, 240, 230)),
+    ("magenta", (255, 0, 255)),
+    ("maroon", (176, 48, 96)),
+    ("mediumaquamarine", (102, 205, 170)),
+    ("mediumblue", (0, 0, 205)),
+    ("mediumorchid", (186, 85, 211)),
+    ("mediumpurple", (147, 112, 219)),
+    ("mediumseagreen", (60, 179, 113)),
+    ("mediumslateblue", (123, 104, 238)),
+    ("mediumspringgreen", (0, 250, 154)),
+    ("mediumturquoise", (72, 209, 204)),
+    ("mediumvioletred", (199, 21, 133)),
+    ("midnightblue", (25, 25, 112)),
+    ("mintcream", (245, 255, 250)),
+    ("mistyrose", (255, 228, 225)),
+    ("moccasin", (255, 228, 181)),
+    ("navajowhite", (255, 222, 173)),
+    ("navy", (0, 0, 128)),
+    ("navyblue", (0, 0, 128)),
+    ("oldlace", (253, 245, 230)),
+    ("olive", (128, 128, 0)),
+    ("olivedrab", (107, 142, 35)),
+    ("orange", (255, 165, 0)),
+    ("orangered", (255, 69, 0)),
+    ("orchid", (218, 112, 214)),
+    ("palegoldenrod", (238, 232, 170)),
+    ("palegreen", (152, 251, 152)),
+    ("paleturquoise", (175, 238, 238)),
+    ("palevioletred", (219, 112, 147)),
+    ("papayawhip", (255, 239, 213)),
+    ("peachpuff", (255, 218, 185)),
+    ("peru", (205, 133, 63)),
+    ("pink", (255, 192, 203)),
+    ("plum", (221, 160, 221)),
+    ("powderblue", (176, 224, 230)),
+    ("purple", (160, 32, 240)),
+    ("rebeccapurple", (102, 51, 153)),
+    ("red", (255, 0, 0)),
+    ("rosybrown", (188, 143, 143)),
+    ("royalblue", (65, 105, 225)),
+    ("saddlebrown", (139, 69, 19)),
+    ("salmon", (250, 128, 114)),
+    ("sandybrown", (244, 164, 96)),
+    ("seagreen", (46, 139, 87)),
+    ("seashell", (255, 245, 238)),
+    ("sienna", (160, 82, 45)),
+    ("silver", (192, 192, 192)),
+    ("skyblue", (135, 206, 235)),
+    ("slateblue", (106, 90, 205)),
+    ("slategray", (112, 128, 144)),
+    ("slategrey", (112, 128, 144)),
+    ("snow", (255, 250, 250)),
+    ("springgreen", (0, 255, 127)),
+    ("steelblue", (70, 130, 180)),
+    ("tan", (210, 180, 140)),
+    ("teal", (0, 128, 128)),
+    ("thistle", (216, 191, 216)),
+    ("tomato", (255, 99, 71)),
+    ("turquoise", (64, 224, 208)),
+    ("violet", (238, 130, 238)),
+    ("violetred", (208, 32, 144)),
+    ("wheat", (245, 222, 179)),
+    ("white", (255, 255, 255)),
+    ("whitesmoke", (245, 245, 245)),
+    ("yellow", (255, 255, 0)),
+    ("yellowgreen", (154, 205, 50)),
+];
+
+/// named_color returns the RGB value of the X11 or CSS color name, ignoring case as well as `-`,
+/// `_` and spaces, so that `DarkCyan` and `dark-cyan` are the same as `darkcyan`.
+pub fn named_color(name: &str) -> Option<(u8, u8, u8)> {
+    let name = normalize_name(name);
+    NAMED_COLORS
+        .binary_search_by(|(n, _)| (*n).cmp(name.as_str()))
+        .ok()
+        .map(|i| NAMED_COLORS[i].1)
+}
+
+/// similar_name returns the color name closest to the given one, if there is one that is only a
+/// couple of typos away, for suggesting it in error messages.
+pub fn similar_name(name: &str) -> Option<&'static str> {
+    let name = normalize_name(name);
+    NAMED_COLORS
+        .iter()
+        .map(|(n, _)| (edit_distance(n, &name), *n))
+        .filter(|(distance, _)| *distance <= 2)
+        .min()
+        .map(|(_, n)| n)
+}
+
+fn normalize_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| !matches!(c, '-' | '_' | ' '))
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+// the number of characters to insert, delete or replace to turn a into b
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut prev = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            cur[j + 1] = (prev[j] + usize::from(ca != *cb))
+                .min(prev[j + 1] + 1)
+                .min(cur[j] + 1);
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+// the levels of the red, green and blue components of the 6x6x6 color cube at indices 16 to 231
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// nearest_256 returns the index of the color of the 256 color palette closest to the RGB value.
+/// Only the color cube and the grayscale ramp are considered, as the 16 basic colors differ
+/// between terminals.
+pub fn nearest_256((r, g, b): (u8, u8, u8)) -> u8 {
+    let nearest_level = |c: u8| {
+        (0..CUBE_LEVELS.len())
+            .min_by_key(|&i| CUBE_LEVELS[i].abs_diff(c))
+            .unwrap_or_default()
+    };
+    let (ri, gi, bi) = (nearest_level(r), nearest_level(g), nearest_level(b));
+    let cube = (
+        16 + 36 * ri + 6 * gi + bi,
+        (CUBE_LEVELS[ri], CUBE_LEVELS[gi], CUBE_LEVELS[bi]),
+    );
+    // the grayscale ramp has 24 levels, 8, 18, ..., 238
+    let gray_level = ((u32::from(r) + u32::from(g) + u32::from(b)) / 3).saturating_sub(3) / 10;
+    let gray_level = gray_level.min(23) as u8;
+    let gray_value = 8 + 10 * gray_level;
+    let gray = (
+        232 + gray_level as usize,
+        (gray_value, gray_value, gray_value),
+    );
+    let distance = |(cr, cg, cb): (u8, u8, u8)| {
+        let d = |a: u8, b: u8| u32::from(a.abs_diff(b)).pow(2);
+        d(r, cr) + d(g, cg) + d(b, cb)
+    };
+    let (idx, _) = if distance(gray.1) < distance(cube.1) {
+        gray
+    } else {
+        cube
+    };
+    idx as u8
+}
+
+// the 16 basic colors as xterm shows them by default
+const BASIC_COLORS: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+/// rgb_256 returns the RGB value of the color of the 256 color palette at idx, taking the 16 basic
+/// colors to look like they do in xterm by default.
+pub fn rgb_256(idx: u8) -> (u8, u8, u8) {
+    match idx {
+        0..16 => BASIC_COLORS[idx as usize],
+        16..232 => {
+            let cube = idx - 16;
+            (
+                CUBE_LEVELS[(cube / 36) as usize],
+                CUBE_LEVELS[(cube / 6 % 6) as usize],
+                CUBE_LEVELS[(cube % 6) as usize],
+            )
+        }
+        232.. => {
+            let gray = 8 + 10 * (idx - 232);
+            (gray, gray, gray)
+        }
+    }
+}
+
+/// hex formats the RGB value like `#ff8000`.
+pub fn hex((r, g, b): (u8, u8, u8)) -> String {
+    format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+/// relative_luminance returns the relative luminance of the RGB value, between 0 for black and 1
+/// for white, as defined by WCAG.
+fn relative_luminance((r, g, b): (u8, u8, u8)) -> f64 {
+    let linear = |c: u8| {
+        let c = f64::from(c) / 255.0;
+        if c <= 0.03928 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    0.2126 * linear(r) + 0.7152 * linear(g) + 0.0722 * linear(b)
+}
+
+/// contrast_ratio returns the WCAG contrast ratio of two RGB values, between 1 for equal colors
+/// and 21 for black and white.
+pub fn contrast_ratio(a: (u8, u8, u8), b: (u8, u8, u8)) -> f64 {
+    let (la, lb) = (relative_luminance(a), relative_luminance(b));
+    (la.max(lb) + 0.05) / (la.min(lb) + 0.05)
+}
+
+/// lab returns the CIELAB coordinates of the sRGB value, for a D65 white point.
+fn lab((r, g, b): (u8, u8, u8)) -> (f64, f64, f64) {
+    let linear = |c: u8| {
+        let c = f64::from(c) / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    let (r, g, b) = (linear(r), linear(g), linear(b));
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+    let f = |t: f64| {
+        if t > 216.0 / 24389.0 {
+            t.cbrt()
+        } else {
+            (24389.0 / 27.0 * t + 16.0) / 116.0
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    (116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz))
+}
+
+/// color_distance returns the CIE76 distance of two RGB values, the euclidean distance of their
+/// CIELAB coordinates, where about 2.3 is the smallest difference that can be seen, and black and
+/// white are 100 apart.
+pub fn color_distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> f64 {
+    let ((l1, a1, b1), (l2, a2, b2)) = (lab(a), lab(b));
+    ((l1 - l2).powi(2) + (a1 - a2).powi(2) + (b1 - b2).powi(2)).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[test]
+    fn test_named_colors_sorted() {
+        assert!(NAMED_COLORS.windows(2).all(|w| w[0].0 < w[1].0));
+    }
+
+    #[rstest]
+    #[case::lowercase("orange", Some((255, 165, 0)))]
+    #[case::camel_case("DarkCyan", Some((0, 139, 139)))]
+    #[case::hyphenated("dark-cyan", Some((0, 139, 139)))]
+    #[case::css_only("teal", Some((0, 128, 128)))]
+    #[case::unknown("blurple", None)]
+    fn test_named_color(#[case] name: &str, #[case] expected: Option<(u8, u8, u8)>) {
+        assert_eq!(named_color(name), expected);
+    }
+
+    #[rstest]
+    #[case::black((0, 0, 0), 16)]
+    #[case::white((255, 255, 255), 231)]
+    #[case::red((255, 0, 0), 196)]
+    #[case::orange((255, 165, 0), 214)]
+    #[case::darkcyan((0, 139, 139), 30)]
+    #[case::gray((128, 128, 128), 244)]
+    #[case::near_black_gray((10, 10, 10), 232)]
+    fn test_nearest_256(#[case] rgb: (u8, u8, u8), #[case] expected: u8) {
+        assert_eq!(nearest_256(rgb), expected);
+    }
+
+    #[rstest]
+    #[case::basic(1, (205, 0, 0))]
+    #[case::bright(15, (255, 255, 255))]
+    #[case::cube_start(16, (0, 0, 0))]
+    #[case::cube(214, (255, 175, 0))]
+    #[case::cube_end(231, (255, 255, 255))]
+    #[case::gray_start(232, (8, 8, 8))]
+    #[case::gray_end(255, (238, 238, 238))]
+    fn test_rgb_256(#[case] idx: u8, #[case] expected: (u8, u8, u8)) {
+        assert_eq!(rgb_256(idx), expected);
+    }
+
+    #[test]
+    fn test_rgb_256_nearest_256_round_trip() {
+        for idx in 16..=255 {
+            assert_eq!(nearest_256(rgb_256(idx)), idx, "{idx}");
+        }
+    }
+
+    #[rstest]
+    #[case::black((0, 0, 0), "#000000")]
+    #[case::orange((255, 128, 0), "#ff8000")]
+    fn test_hex(#[case] rgb: (u8, u8, u8), #[case] expected: &str) {
+        assert_eq!(hex(rgb), expected);
+    }
+
+    #[rstest]
+    #[case::black_white((0, 0, 0), (255, 255, 255), 21.0)]
+    #[case::white_black((255, 255, 255), (0, 0, 0), 21.0)]
+    #[case::equal((205, 0, 0), (205, 0, 0), 1.0)]
+    #[case::gray((118, 118, 118), (255, 255, 255), 4.54)]
+    fn test_contrast_ratio(
+        #[case] a: (u8, u8, u8),
+        #[case] b: (u8, u8, u8),
+        #[case] expected: f64,
+    ) {
+        assert!(
+            (contrast_ratio(a, b) - expected).abs() < 0.01,
+            "{}",
+            contrast_ratio(a, b)
+        );
+    }
+
+    #[rstest]
+    #[case::black_white((0, 0, 0), (255, 255, 255), 100.0)]
+    #[case::same((10, 20, 30), (10, 20, 30), 0.0)]
+    #[case::red_black((255, 0, 0), (0, 0, 0), 117.34)]
+    fn test_color_distance(
+        #[case] a: (u8, u8, u8),
+        #[case] b: (u8, u8, u8),
+        #[case] expected: f64,
+    ) {
+        assert!(
+            (color_distance(a, b) - expected).abs() < 0.05,
+            "{}",
+            color_distance(a, b)
+        );
+    }
+
+    #[rstest]
+    #[case::typo("ornage", Some("orange"))]
+    #[case::missing_letter("darkcyn", Some("darkcyan"))]
+    #[case::far_off("xyzzyplugh", None)]
+    fn test_similar_name(#[case] name: &str, #[case] expected: Option<&str>) {
+        assert_eq!(similar_name(name), expected);
+    }
+}
@@ -0,0 +1,188 @@
+use crate::{RangeWithId, add_range};
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::canonical_combining_class;
+use unicode_normalization::{IsNormalized, is_nfc_quick, is_nfd_quick};
+
+/// Normalization is the Unicode normalization form lines are converted to before matching.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Normalization {
+    Nfc,
+    Nfd,
+    /// NFD without the combining marks, so that e.g. `café` becomes `cafe`.
+    FoldAccents,
+}
+
+/// Normalized is a line converted to a normalization form, remembering where each part of the
+/// normalized text came from in the line.
+///
+/// The line is normalized in segments made of a character that does not combine with preceding
+/// ones, followed by the characters that do. As normalization may change the length of a segment,
+/// offsets within a segment cannot be mapped back exactly, so ranges are widened to the segments
+/// they touch. Compositions across segments, like of Hangul jamo, are not performed. This also
+/// takes care of folding accents, which makes the normalized text shorter than the line whenever
+/// there is one: as a combining mark always belongs to the segment of its base character, a range
+/// ending after a folded base character ends after its accents in the line.
+pub struct Normalized {
+    pub text: String,
+    // the start offsets of the segments, in the normalized text and in the line
+    segments: Vec<(usize, usize)>,
+    line_len: usize,
+}
+
+impl Normalized {
+    pub fn new(line: &str, normalization: Normalization) -> Self {
+        let mut text = String::with_capacity(line.len());
+        let mut segments = vec![(0, 0)];
+        let mut segment_start = 0;
+        for (idx, c) in line.char_indices().skip(1) {
+            if canonical_combining_class(c) == 0 {
+                push_segment(&mut text, &line[segment_start..idx], normalization);
+                segments.push((text.len(), idx));
+                segment_start = idx;
+            }
+        }
+        push_segment(&mut text, &line[segment_start..], normalization);
+        Self {
+            text,
+            segments,
+            line_len: line.len(),
+        }
+    }
+
+    /// map_ranges maps ranges in the normalized text to ranges in the line.
+    pub fn map_ranges(&self, ranges: &mut Vec<RangeWithId>) {
+        let mapped = ranges
+            .drain(..)
+            .map(|r| RangeWithId {
+                start_idx: self.line_offset(r.start_idx, false),
+                end_idx: self.line_offset(r.end_idx, true),
+                id: r.id,
+            })
+            .collect::<Vec<_>>();
+        for r in mapped {
+            add_range(ranges, r);
+        }
+    }
+
+    // line_offset returns the offset of the start of the segment the offset in the normalized text
+    // falls into, or of the end of that segment if end is set and the offset is not at its start
+    fn line_offset(&self, offset: usize, end: bool) -> usize {
+        let idx = self.segments.partition_point(|&(start, _)| start <= offset) - 1;
+        let (start, line_start) = self.segments[idx];
+        if !end || start == offset {
+            return line_start;
+        }
+        self.segments
+            .get(idx + 1)
+            .map_or(self.line_len, |&(_, line_start)| line_start)
+    }
+}
+
+/// is_normalized returns whether the line is known to be in the normalization form already, in
+/// which case there is no need to normalize it.
+pub fn is_normalized(line: &str, normalization: Normalization) -> bool {
+    let quick = match normalization {
+        Normalization::Nfc => is_nfc_quick(line.chars()),
+        Normalization::Nfd => is_nfd_quick(line.chars()),
+        // ASCII has no accents
+        Normalization::FoldAccents => return line.is_ascii(),
+    };
+    quick == IsNormalized::Yes
+}
+
+fn push_segment(text: &mut String, segment: &str, normalization: Normalization) {
+    match normalization {
+        Normalization::Nfc => text.extend(segment.nfc()),
+        Normalization::Nfd => text.extend(segment.nfd()),
+        Normalization::FoldAccents => {
+            text.extend(segment.nfd().filter(|&c| canonical_combining_class(c) == 0))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MatchOptions, compile_patterns, match_line};
+    use rstest::rstest;
+
+    fn normalized_matches(pattern: &str, line: &str, normalization: Normalization) -> Vec<String> {
+        let regexps = compile_patterns(&[pattern.into()], false, false).unwrap();
+        let normalized = Normalized::new(line, normalization);
+        let mut ranges = match_line(&normalized.text, &regexps, &MatchOptions::default());
+        normalized.map_ranges(&mut ranges);
+        ranges
+            .iter()
+            .map(|r| line[r.start_idx..r.end_idx].to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_composed_pattern_decomposed_input() {
+        let line = "cafe\u{301} and café";
+        let regexps = compile_patterns(&["café".into()], false, false).unwrap();
+        assert_eq!(
+            match_line(line, &regexps, &MatchOptions::default()).len(),
+            1
+        );
+        assert_eq!(
+            normalized_matches("café", line, Normalization::Nfc),
+            vec!["cafe\u{301}", "café"]
+        );
+    }
+
+    #[test]
+    fn test_decomposed_pattern_composed_input() {
+        assert_eq!(
+            normalized_matches("e\u{301}", "é e\u{301}", Normalization::Nfd),
+            vec!["é", "e\u{301}"]
+        );
+    }
+
+    #[test]
+    fn test_partial_segment_widened() {
+        // the pattern only matches the base character of the decomposed é, which is then
+        // highlighted as a whole
+        assert_eq!(normalized_matches("e", "xé", Normalization::Nfd), vec!["é"]);
+    }
+
+    #[rstest]
+    #[case::composed("café au lait", vec!["café"])]
+    #[case::decomposed("cafe\u{301}", vec!["cafe\u{301}"])]
+    #[case::unaccented("cafe", vec!["cafe"])]
+    #[case::several_marks("cafe\u{301}\u{323}s", vec!["cafe\u{301}\u{323}"])]
+    #[case::multibyte_before("ÄÖÜ café", vec!["café"])]
+    fn test_fold_accents(#[case] line: &str, #[case] expected: Vec<&str>) {
+        assert_eq!(
+            normalized_matches("cafe", line, Normalization::FoldAccents),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_fold_accents_pattern() {
+        // patterns are folded like the input
+        let pattern = Normalized::new("café", Normalization::FoldAccents).text;
+        assert_eq!(pattern, "cafe");
+        assert_eq!(
+            normalized_matches(&pattern, "un café", Normalization::FoldAccents),
+            vec!["café"]
+        );
+    }
+
+    #[test]
+    fn test_fold_accents_is_normalized() {
+        assert!(is_normalized("cafe", Normalization::FoldAccents));
+        // NFD, but with an accent to fold
+        assert!(!is_normalized("cafe\u{301}", Normalization::FoldAccents));
+    }
+
+    #[test]
+    fn test_normalized_ascii() {
+        let normalized = Normalized::new("abc", Normalization::Nfc);
+        assert_eq!(normalized.text, "abc");
+        assert!(is_normalized("abc", Normalization::Nfc));
+        assert!(!is_normalized("e\u{301}", Normalization::Nfc));
+        assert_eq!(Normalized::new("", Normalization::Nfc).text, "");
+    }
+}
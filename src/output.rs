@@ -0,0 +1,233 @@
+use crate::{ColorexpError, Result};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+
+/// DEFAULT_PAGER is the pager used by --pager if PAGER is not set.
+pub const DEFAULT_PAGER: &str = "less";
+/// DEFAULT_LESS is the value of the LESS environment variable passed to the pager if it is not
+/// set, making less pass colors through (R), quit if the output fits on one screen (F) and leave
+/// it on the screen (X), like git does.
+pub const DEFAULT_LESS: &str = "FRX";
+
+/// create_output creates (or truncates) the file at the given path for writing, or returns stdout
+/// if no path is given.
+pub fn create_output(path: Option<&Path>) -> Result<Box<dyn Write>> {
+    let Some(path) = path else {
+        return Ok(Box::new(io::stdout().lock()));
+    };
+    let file = File::create(path).map_err(|source| ColorexpError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    Ok(Box::new(BufWriter::new(file)))
+}
+
+/// ByteLimit writes to the inner writer, counting the bytes of the output, to stop printing lines
+/// once there are enough of them. All output goes through it, whatever its format.
+#[derive(Debug)]
+pub struct ByteLimit<W> {
+    inner: W,
+    pub max: Option<usize>,
+    pub written: usize,
+}
+
+impl<W: Write> ByteLimit<W> {
+    /// new returns a writer to inner with the limit, if any.
+    pub fn new(inner: W, max: Option<usize>) -> Self {
+        Self {
+            inner,
+            max,
+            written: 0,
+        }
+    }
+
+    /// reached returns whether the limit is reached. As it is checked after every line, the output
+    /// may exceed the limit by the part of the last line written beyond it.
+    pub fn reached(&self) -> bool {
+        self.max.is_some_and(|max| self.written >= max)
+    }
+}
+
+impl<W: Write> Write for ByteLimit<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.written += written;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// pager_command returns the program and arguments of the pager given by pager, the value of the
+/// PAGER environment variable, split at whitespace, or DEFAULT_PAGER if it is not set. None is
+/// returned if pager is empty or "cat", which turns paging off.
+pub fn pager_command(pager: Option<&str>) -> Option<Vec<String>> {
+    let command = pager
+        .unwrap_or(DEFAULT_PAGER)
+        .split_whitespace()
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    match command.first() {
+        Some(program) if program != "cat" => Some(command),
+        _ => None,
+    }
+}
+
+/// Pager is a pager running as a child process, writing to it writes to its stdin. Dropping it
+/// closes its stdin and waits for it to exit, that is for the user to quit it.
+pub struct Pager {
+    child: Child,
+    stdin: Option<BufWriter<ChildStdin>>,
+}
+
+impl Pager {
+    /// spawn starts the pager, which gets DEFAULT_LESS as LESS if that is not set.
+    pub fn spawn(command: &[String]) -> io::Result<Self> {
+        let (program, args) = command
+            .split_first()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty pager command"))?;
+        let mut cmd = Command::new(program);
+        cmd.args(args).stdin(Stdio::piped());
+        if std::env::var_os("LESS").is_none() {
+            cmd.env("LESS", DEFAULT_LESS);
+        }
+        let mut child = cmd.spawn()?;
+        let stdin = child.stdin.take().map(BufWriter::new);
+        Ok(Self { child, stdin })
+    }
+
+    fn stdin(&mut self) -> &mut BufWriter<ChildStdin> {
+        // only taken when dropped
+        self.stdin.as_mut().unwrap()
+    }
+}
+
+impl Write for Pager {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stdin().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stdin().flush()
+    }
+}
+
+impl Drop for Pager {
+    fn drop(&mut self) {
+        // the pager may have been quit before reading everything
+        if let Some(mut stdin) = self.stdin.take() {
+            let _ = stdin.flush();
+        }
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[test]
+    fn test_create_output_file() {
+        let path = std::env::temp_dir().join(format!("colorexp-{}-out.log", std::process::id()));
+        std::fs::write(&path, "previous content that is longer\n").unwrap();
+        let mut out = create_output(Some(&path)).unwrap();
+        write!(out, "a \x1b[31mb\x1b[0m\nc").unwrap();
+        out.flush().unwrap();
+        drop(out);
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(contents, "a \x1b[31mb\x1b[0m\nc");
+    }
+
+    #[test]
+    fn test_create_output_missing_dir() {
+        let path = std::env::temp_dir()
+            .join("colorexp-missing-dir")
+            .join("out.log");
+        let err = create_output(Some(&path)).err().unwrap();
+        assert!(
+            matches!(err, ColorexpError::Io { path: ref p, .. } if *p == path),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn test_byte_limit() {
+        let mut limit = ByteLimit::new(Vec::new(), Some(10));
+        write!(limit, "abcd").unwrap();
+        assert!(!limit.reached());
+        write!(limit, "efghi").unwrap();
+        assert!(!limit.reached());
+        // the line crossing the limit is still written in full
+        write!(limit, "jklmno").unwrap();
+        assert!(limit.reached());
+        assert_eq!(limit.written, 15);
+    }
+
+    #[test]
+    fn test_byte_limit_unlimited() {
+        let mut limit = ByteLimit::new(Vec::new(), None);
+        write!(limit, "{}", "x".repeat(1000)).unwrap();
+        assert!(!limit.reached());
+    }
+
+    #[test]
+    fn test_byte_limit_truncates_lines() {
+        let lines = [
+            "first line\n",
+            "second line\n",
+            "third line\n",
+            "fourth line\n",
+        ];
+        let mut out = ByteLimit::new(Vec::new(), Some(20));
+        for line in lines {
+            write!(out, "{line}").unwrap();
+            if out.reached() {
+                break;
+            }
+        }
+        assert_eq!(out.inner, b"first line\nsecond line\n");
+    }
+
+    #[rstest]
+    #[case::unset(None, Some(vec!["less"]))]
+    #[case::program(Some("most"), Some(vec!["most"]))]
+    #[case::with_args(Some(" less  -R -S "), Some(vec!["less", "-R", "-S"]))]
+    #[case::empty(Some(""), None)]
+    #[case::blank(Some("  "), None)]
+    #[case::cat(Some("cat"), None)]
+    fn test_pager_command(#[case] pager: Option<&str>, #[case] expected: Option<Vec<&str>>) {
+        assert_eq!(
+            pager_command(pager),
+            expected.map(|command| command.into_iter().map(str::to_string).collect())
+        );
+    }
+
+    #[test]
+    fn test_pager() {
+        let path = std::env::temp_dir().join(format!("colorexp-{}-pager.log", std::process::id()));
+        let command = vec![
+            "sh".to_string(),
+            "-c".to_string(),
+            format!("cat > {}", path.display()),
+        ];
+        let mut pager = Pager::spawn(&command).unwrap();
+        write!(pager, "a \x1b[31mb\x1b[0m\nc").unwrap();
+        drop(pager);
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(contents, "a \x1b[31mb\x1b[0m\nc");
+    }
+
+    #[test]
+    fn test_pager_not_found() {
+        let command = vec!["colorexp-no-such-pager".to_string()];
+        let err = Pager::spawn(&command).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+}
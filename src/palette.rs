@@ -0,0 +1,542 @@
+use crate::named_colors::{
+    color_distance, contrast_ratio, named_color, nearest_256, rgb_256, similar_name,
+};
+use crate::sample::Rng;
+use crate::{ColorSet, ColorexpError, RESET_BACKGROUND, RESET_FOREGROUND, Result};
+
+static COLOR_NAMES: &[&str] = &[
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+// the names of FOREGROUND_COLORS and BACKGROUND_COLORS
+static FOREGROUND_NAMES: &[&str] = &["red", "green", "yellow", "blue", "magenta", "cyan"];
+static BACKGROUND_NAMES: &[&str] = &["red", "blue", "magenta", "green", "yellow", "cyan"];
+// the default colors on light backgrounds: darker foreground and lighter background variants of
+// the ones above, with brown standing in for yellow, which is unreadable on white
+static LIGHT_FOREGROUND_NAMES: &[&str] = &[
+    "darkred",
+    "darkgreen",
+    "saddlebrown",
+    "mediumblue",
+    "darkmagenta",
+    "seagreen",
+];
+static LIGHT_BACKGROUND_NAMES: &[&str] = &[
+    "lightcoral",
+    "lightskyblue",
+    "plum",
+    "lightgreen",
+    "khaki",
+    "paleturquoise",
+];
+
+/// MIN_CONTRAST is the contrast ratio WCAG requires for normal text, see contrast.
+pub const MIN_CONTRAST: f64 = 4.5;
+
+/// TerminalBackground tells whether the terminal has a dark or a light background, to pick default
+/// colors that are readable on it.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum TerminalBackground {
+    #[default]
+    Dark,
+    Light,
+}
+
+impl TerminalBackground {
+    // the background of the terminal and the color of the text on it, taken to be black and white
+    // (or the other way round), as xterm shows them
+    fn colors(self) -> ((u8, u8, u8), (u8, u8, u8)) {
+        match self {
+            TerminalBackground::Dark => (rgb_256(0), rgb_256(7)),
+            TerminalBackground::Light => (rgb_256(15), rgb_256(0)),
+        }
+    }
+}
+
+/// parse_color returns the escape sequence that sets the foreground (or background) to the given
+/// color, which is either one of the 8 basic color names, optionally prefixed with `bright-`, an
+/// X11 or CSS color name like `orange`, which is mapped to the closest color of the 256 color
+/// palette, or an index into that palette.
+pub fn parse_color(spec: &str, background: bool) -> Result<String> {
+    let spec = spec.trim();
+    let indexed = |idx: u8| format!("\x1b[{};5;{idx}m", if background { 48 } else { 38 });
+    if let Ok(idx) = spec.parse::<u8>() {
+        return Ok(indexed(idx));
+    }
+    let lower = spec.to_ascii_lowercase();
+    let (name, base) = match lower.strip_prefix("bright-") {
+        Some(name) => (name, if background { 100 } else { 90 }),
+        None => (lower.as_str(), if background { 40 } else { 30 }),
+    };
+    if let Some(offset) = COLOR_NAMES.iter().position(|n| *n == name) {
+        return Ok(format!("\x1b[{}m", base + offset));
+    }
+    if let Some(rgb) = named_color(spec) {
+        return Ok(indexed(nearest_256(rgb)));
+    }
+    let reason = match similar_name(spec) {
+        Some(similar) => format!("unknown color name, did you mean `{similar}`?"),
+        None => "expected a color name or a number between 0 and 255".to_string(),
+    };
+    Err(ColorexpError::BadColorSpec {
+        spec: spec.to_string(),
+        reason,
+    })
+}
+
+/// color_rgb returns the RGB value of the color, as xterm shows it by default.
+pub fn color_rgb(spec: &str) -> Result<(u8, u8, u8)> {
+    let sequence = parse_color(spec, false)?;
+    // the sequence sets the foreground like `\x1b[31m`, `\x1b[91m` or `\x1b[38;5;208m`
+    let params = &sequence[2..sequence.len() - 1];
+    let idx = match params.strip_prefix("38;5;") {
+        Some(idx) => idx.parse().unwrap_or_default(),
+        None => match params.parse::<u8>().unwrap_or_default() {
+            code @ 90.. => code - 90 + 8,
+            code => code - 30,
+        },
+    };
+    Ok(rgb_256(idx))
+}
+
+/// contrast returns the WCAG contrast ratio of a foreground color with the terminal background,
+/// or of a background color with the text on it. Text is readable from MIN_CONTRAST on.
+pub fn contrast(spec: &str, background: bool, terminal: TerminalBackground) -> Result<f64> {
+    let (terminal_bg, terminal_fg) = terminal.colors();
+    let rgb = color_rgb(spec)?;
+    Ok(contrast_ratio(
+        rgb,
+        if background { terminal_fg } else { terminal_bg },
+    ))
+}
+
+/// Markup configures highlighting with textual tags like `[[red]]text[[/]]` instead of escape
+/// sequences, so that the highlighting can be stored and rendered by other tools.
+#[derive(Clone, Debug)]
+pub struct Markup {
+    pub open: String,
+    pub close: String,
+}
+
+/// color_set returns the color set for the given color, made of escape sequences, or of markup
+/// tags if markup is given. Background colors are tagged with a `bg-` prefix.
+pub fn color_set(spec: &str, background: bool, markup: Option<&Markup>) -> Result<ColorSet> {
+    let on = parse_color(spec, background)?;
+    Ok(match markup {
+        Some(Markup { open, close }) => {
+            let prefix = if background { "bg-" } else { "" };
+            ColorSet::new(
+                format!("{open}{prefix}{}{close}", spec.trim()),
+                format!("{open}/{close}"),
+            )
+        }
+        None if background => ColorSet::new(on, RESET_BACKGROUND),
+        None => ColorSet::new(on, RESET_FOREGROUND),
+    })
+}
+
+/// palette returns the colors used for highlighting: the foreground colors, followed by the
+/// background colors. Empty specs select the default colors for the terminal background.
+pub fn palette(
+    fg_specs: &[String],
+    bg_specs: &[String],
+    foreground: bool,
+    background: bool,
+    markup: Option<&Markup>,
+    terminal: TerminalBackground,
+) -> Result<Vec<ColorSet>> {
+    palette_specs(fg_specs, bg_specs, foreground, background, terminal)
+        .iter()
+        .map(|(spec, background)| color_set(spec, *background, markup))
+        .collect()
+}
+
+/// palette_specs returns the specs of the colors of palette, each with whether it is a background
+/// color.
+pub fn palette_specs(
+    fg_specs: &[String],
+    bg_specs: &[String],
+    foreground: bool,
+    background: bool,
+    terminal: TerminalBackground,
+) -> Vec<(String, bool)> {
+    let (fg_names, bg_names) = match terminal {
+        TerminalBackground::Dark => (FOREGROUND_NAMES, BACKGROUND_NAMES),
+        TerminalBackground::Light => (LIGHT_FOREGROUND_NAMES, LIGHT_BACKGROUND_NAMES),
+    };
+    let mut specs = Vec::new();
+    if foreground {
+        specs.extend(specs_or_defaults(fg_specs, fg_names, false));
+    }
+    if background {
+        specs.extend(specs_or_defaults(bg_specs, bg_names, true));
+    }
+    specs
+}
+
+fn specs_or_defaults(specs: &[String], defaults: &[&str], background: bool) -> Vec<(String, bool)> {
+    if specs.is_empty() {
+        defaults
+            .iter()
+            .map(|spec| (spec.to_string(), background))
+            .collect()
+    } else {
+        specs
+            .iter()
+            .map(|spec| (spec.clone(), background))
+            .collect()
+    }
+}
+
+/// distinct_specs returns the color specs, as returned by palette_specs, without those closer
+/// than min_distance to an earlier one of the same kind, by color_distance, for
+/// --min-color-distance. Foreground colors are only compared to foreground colors, and background
+/// colors to background colors. If fewer than count colors are left, colors of the 256 color
+/// palette of the first kind of the specs are added, that are far enough from them and from the
+/// terminal background, and readable, until there are count colors or none are left. The caller
+/// can tell from the number of colors returned whether that has succeeded.
+pub fn distinct_specs(
+    specs: &[(String, bool)],
+    count: usize,
+    min_distance: f64,
+    terminal: TerminalBackground,
+) -> Result<Vec<(String, bool)>> {
+    let mut distinct = Vec::new();
+    // the RGB values of the distinct colors, with whether they are background colors
+    let mut rgbs = Vec::new();
+    let is_distinct = |rgbs: &[((u8, u8, u8), bool)], rgb, background| {
+        rgbs.iter()
+            .filter(|(_, bg)| *bg == background)
+            .all(|(other, _)| color_distance(*other, rgb) >= min_distance)
+    };
+    for (spec, background) in specs {
+        let rgb = color_rgb(spec)?;
+        if is_distinct(&rgbs, rgb, *background) {
+            rgbs.push((rgb, *background));
+            distinct.push((spec.clone(), *background));
+        }
+    }
+    if let Some(&(_, background)) = specs.first() {
+        // skip the 16 basic colors, which terminals often change
+        for idx in 16..=255u8 {
+            if distinct.len() >= count {
+                break;
+            }
+            let spec = idx.to_string();
+            let rgb = rgb_256(idx);
+            if is_distinct(&rgbs, rgb, background)
+                && color_distance(rgb, terminal.colors().0) >= min_distance
+                && contrast(&spec, background, terminal)? >= MIN_CONTRAST
+            {
+                rgbs.push((rgb, background));
+                distinct.push((spec, background));
+            }
+        }
+    }
+    Ok(distinct)
+}
+
+/// stable_palette returns one color per key, picked from the colors by hashing the key, so that a
+/// pattern keeps its color no matter which other patterns are given. Different seeds result in
+/// different picks. The keys must be in the order of the colors used for patterns, see
+/// color_keys.
+pub fn stable_palette(colors: &[ColorSet], keys: &[String], seed: u64) -> Vec<ColorSet> {
+    keys.iter()
+        .map(|key| colors[(stable_hash(key, seed) % colors.len() as u64) as usize].clone())
+        .collect()
+}
+
+// FNV-1a, which unlike the std hashers is guaranteed not to change between releases, mixed with
+// the seed
+fn stable_hash(key: &str, seed: u64) -> u64 {
+    let mut hash = 0xcbf29ce484222325u64;
+    for byte in key.bytes() {
+        hash = (hash ^ byte as u64).wrapping_mul(0x100000001b3);
+    }
+    Rng::new(hash ^ seed).next_u64()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        BACKGROUND_COLORS, FOREGROUND_COLORS, MatchOptions, color_keys, colorize, compile_patterns,
+        match_line, pattern_color_count,
+    };
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::name("red", false, "\x1b[31m")]
+    #[case::name_background("Cyan", true, "\x1b[46m")]
+    #[case::bright("bright-black", false, "\x1b[90m")]
+    #[case::bright_background("bright-white", true, "\x1b[107m")]
+    #[case::index("208", false, "\x1b[38;5;208m")]
+    #[case::index_background(" 0 ", true, "\x1b[48;5;0m")]
+    #[case::x11_name("orange", false, "\x1b[38;5;214m")]
+    #[case::x11_name_background("DarkCyan", true, "\x1b[48;5;30m")]
+    fn test_parse_color(#[case] spec: &str, #[case] background: bool, #[case] expected: &str) {
+        assert_eq!(parse_color(spec, background).unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case::unknown_name("blurple")]
+    #[case::index_out_of_range("256")]
+    #[case::empty("")]
+    fn test_parse_color_invalid(#[case] spec: &str) {
+        let err = parse_color(spec, false).unwrap_err();
+        assert!(
+            matches!(err, ColorexpError::BadColorSpec { spec: ref s, .. } if s == spec.trim()),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn test_parse_color_suggestion() {
+        let err = parse_color("darkcyn", false).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid color `darkcyn`: unknown color name, did you mean `darkcyan`?"
+        );
+    }
+
+    #[test]
+    fn test_palette_defaults() {
+        let colors = palette(&[], &[], true, true, None, TerminalBackground::Dark).unwrap();
+        assert_eq!(
+            colors.len(),
+            FOREGROUND_COLORS.len() + BACKGROUND_COLORS.len()
+        );
+        assert_eq!(
+            palette(&[], &[], false, true, None, TerminalBackground::Dark).unwrap()[0].on,
+            BACKGROUND_COLORS[0]
+        );
+    }
+
+    /// assert_distinct asserts that the colors of the same kind are at least min_distance apart.
+    fn assert_distinct(specs: &[(String, bool)], min_distance: f64) {
+        for (i, (a, a_bg)) in specs.iter().enumerate() {
+            for (b, b_bg) in &specs[i + 1..] {
+                if a_bg == b_bg {
+                    let distance = color_distance(color_rgb(a).unwrap(), color_rgb(b).unwrap());
+                    assert!(distance >= min_distance, "{a} {b}: {distance:.1}");
+                }
+            }
+        }
+    }
+
+    #[rstest]
+    #[case::defaults(&[], 12, 40.0)]
+    #[case::many_patterns(&[], 20, 30.0)]
+    #[case::similar(&["red", "darkred", "firebrick", "blue"], 2, 40.0)]
+    fn test_distinct_specs(
+        #[case] fg_specs: &[&str],
+        #[case] count: usize,
+        #[case] min_distance: f64,
+    ) {
+        let fg_specs = fg_specs.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let specs = palette_specs(&fg_specs, &[], true, true, TerminalBackground::Dark);
+        let distinct =
+            distinct_specs(&specs, count, min_distance, TerminalBackground::Dark).unwrap();
+        assert!(distinct.len() >= count, "{distinct:?}");
+        assert_distinct(&distinct, min_distance);
+    }
+
+    #[test]
+    fn test_distinct_specs_drops_similar() {
+        let specs = [
+            ("red", false),
+            ("darkred", false),
+            ("blue", false),
+            ("red", true),
+        ]
+        .map(|(spec, background)| (spec.to_string(), background));
+        let distinct = distinct_specs(&specs, 0, 40.0, TerminalBackground::Dark).unwrap();
+        let names = distinct
+            .iter()
+            .map(|(spec, bg)| (spec.as_str(), *bg))
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec![("red", false), ("blue", false), ("red", true)]);
+    }
+
+    #[test]
+    fn test_distinct_specs_unsatisfiable() {
+        let specs = [("red".to_string(), false)];
+        let distinct = distinct_specs(&specs, 10, 1000.0, TerminalBackground::Dark).unwrap();
+        assert_eq!(distinct.len(), 1);
+    }
+
+    #[test]
+    fn test_palette_light_background() {
+        let dark = palette(&[], &[], true, true, None, TerminalBackground::Dark).unwrap();
+        let light = palette(&[], &[], true, true, None, TerminalBackground::Light).unwrap();
+        assert_eq!(light.len(), dark.len());
+        assert!(light.iter().zip(&dark).all(|(l, d)| l.on != d.on));
+        let names = LIGHT_FOREGROUND_NAMES
+            .iter()
+            .map(|name| (name, false))
+            .chain(LIGHT_BACKGROUND_NAMES.iter().map(|name| (name, true)));
+        for (name, background) in names {
+            let contrast = contrast(name, background, TerminalBackground::Light).unwrap();
+            assert!(contrast >= MIN_CONTRAST, "{name}: {contrast:.2}");
+        }
+    }
+
+    #[rstest]
+    #[case::basic("red", (205, 0, 0))]
+    #[case::bright("bright-blue", (92, 92, 255))]
+    #[case::index("208", (255, 135, 0))]
+    #[case::x11_name("darkred", (135, 0, 0))]
+    fn test_color_rgb(#[case] spec: &str, #[case] expected: (u8, u8, u8)) {
+        assert_eq!(color_rgb(spec).unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case::yellow_on_white("yellow", false, TerminalBackground::Light, false)]
+    #[case::yellow_on_black("yellow", false, TerminalBackground::Dark, true)]
+    #[case::black_text_on_blue("blue", true, TerminalBackground::Light, false)]
+    #[case::white_text_on_blue("blue", true, TerminalBackground::Dark, true)]
+    fn test_contrast(
+        #[case] spec: &str,
+        #[case] background: bool,
+        #[case] terminal: TerminalBackground,
+        #[case] readable: bool,
+    ) {
+        assert_eq!(
+            contrast(spec, background, terminal).unwrap() >= MIN_CONTRAST,
+            readable
+        );
+    }
+
+    #[test]
+    fn test_palette_custom_colors() {
+        let fg = vec!["green".to_string()];
+        let bg = vec!["blue".to_string(), "yellow".to_string()];
+        let colors = palette(&fg, &bg, true, true, None, TerminalBackground::Dark).unwrap();
+
+        let patterns = ["a".to_string(), "b".to_string(), "c".to_string()];
+        let regexps = compile_patterns(&patterns, false, false).unwrap();
+        let mut line = "abc".to_string();
+        let ranges = match_line(&line, &regexps, &MatchOptions::default());
+        colorize(
+            &mut line,
+            &colors,
+            &[],
+            &ranges,
+            pattern_color_count(&regexps, false),
+        );
+        assert_eq!(line, "\x1b[32ma\x1b[0m\x1b[44mb\x1b[49m\x1b[43mc\x1b[49m");
+    }
+
+    #[test]
+    fn test_default_names() {
+        for (name, color) in FOREGROUND_NAMES.iter().zip(FOREGROUND_COLORS) {
+            assert_eq!(parse_color(name, false).unwrap(), *color);
+        }
+        for (name, color) in BACKGROUND_NAMES.iter().zip(BACKGROUND_COLORS) {
+            assert_eq!(parse_color(name, true).unwrap(), *color);
+        }
+    }
+
+    fn markup_colorize(patterns: &[&str], vary_group_colors: bool, line: &str) -> String {
+        let markup = Markup {
+            open: "[[".to_string(),
+            close: "]]".to_string(),
+        };
+        let colors = palette(
+            &[],
+            &[],
+            true,
+            true,
+            Some(&markup),
+            TerminalBackground::Dark,
+        )
+        .unwrap();
+        let patterns = patterns.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        let regexps = compile_patterns(&patterns, false, false).unwrap();
+        let options = MatchOptions {
+            vary_group_colors,
+            ..MatchOptions::default()
+        };
+        let mut line = line.to_string();
+        let ranges = match_line(&line, &regexps, &options);
+        let pattern_color_count = pattern_color_count(&regexps, vary_group_colors);
+        colorize(&mut line, &colors, &[], &ranges, pattern_color_count);
+        line
+    }
+
+    #[rstest]
+    #[case::single(&["b"], false, "a[[red]]b[[/]]c")]
+    #[case::patterns(&["a", "c"], false, "[[red]]a[[/]]b[[green]]c[[/]]")]
+    #[case::groups(&["(a)b(c)"], true, "[[red]]a[[/]]b[[green]]c[[/]]")]
+    #[case::groups_same_color(&["(a)(b)"], false, "[[red]]ab[[/]]c")]
+    #[case::nested_groups(&["(a(b))"], true, "[[red]]ab[[/]]c")]
+    fn test_markup(
+        #[case] patterns: &[&str],
+        #[case] vary_group_colors: bool,
+        #[case] expected: &str,
+    ) {
+        assert_eq!(
+            markup_colorize(patterns, vary_group_colors, "abc"),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_markup_delimiters() {
+        let markup = Markup {
+            open: "<".to_string(),
+            close: ">".to_string(),
+        };
+        let color = color_set(" 208", true, Some(&markup)).unwrap();
+        assert_eq!(color, ColorSet::new("<bg-208>", "</>"));
+        assert!(color_set("nope", false, Some(&markup)).is_err());
+    }
+
+    fn stable_names(keys: &[&str], seed: u64) -> Vec<usize> {
+        let colors = palette(&[], &[], true, true, None, TerminalBackground::Dark).unwrap();
+        let keys = keys.iter().map(|k| k.to_string()).collect::<Vec<_>>();
+        stable_palette(&colors, &keys, seed)
+            .iter()
+            .map(|c| colors.iter().position(|color| color == c).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_stable_palette() {
+        let keys = ["error", "warn", "info"];
+        let picks = stable_names(&keys, 0);
+        assert_eq!(picks, stable_names(&keys, 0));
+        // a pattern keeps its color when other patterns are added or removed
+        assert_eq!(stable_names(&["info"], 0), picks[2..]);
+        assert_eq!(stable_names(&["debug", "warn"], 0)[1], picks[1]);
+
+        let reseeded = stable_names(&keys, 1);
+        assert_eq!(reseeded, stable_names(&keys, 1));
+        assert_ne!(reseeded, picks);
+    }
+
+    #[test]
+    fn test_stable_palette_colorize() {
+        let colors = [ColorSet::new("<0>", "</0>"), ColorSet::new("<1>", "</1>")];
+        let patterns = vec!["a".to_string(), "b".to_string()];
+        let regexps = compile_patterns(&patterns, false, false).unwrap();
+        let keys = color_keys(&patterns, &regexps, false);
+        let stable = stable_palette(&colors, &keys, 0);
+        let expected = keys
+            .iter()
+            .map(|k| &colors[(stable_hash(k, 0) % 2) as usize])
+            .collect::<Vec<_>>();
+        // the hashes happen to pick different colors for the two patterns
+        assert_ne!(expected[0], expected[1]);
+        let mut line = "ab".to_string();
+        let ranges = match_line(&line, &regexps, &MatchOptions::default());
+        colorize(&mut line, &stable, &[], &ranges, 2);
+        assert_eq!(
+            line,
+            format!(
+                "{}a{}{}b{}",
+                expected[0].on, expected[0].off, expected[1].on, expected[1].off
+            )
+        );
+    }
+}
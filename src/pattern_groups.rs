@@ -0,0 +1,150 @@
+use crate::RangeWithId;
+use crate::input::split_patterns;
+use regex::escape;
+use std::ops::Range;
+
+/// PatternGroup is a named set of patterns sharing a color, given by --group.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PatternGroup {
+    pub name: String,
+    /// The color of the group, if not the next color of the palette.
+    pub color: Option<String>,
+    pub patterns: Vec<String>,
+}
+
+impl PatternGroup {
+    /// parse parses a spec like `errors=red:ERROR:FATAL`: the name of the group, optionally
+    /// followed by `=` and its color, and its patterns, separated by colons like in
+    /// COLOREXP_PATTERNS, see split_patterns.
+    pub fn parse(spec: &str) -> Self {
+        let mut parts = split_patterns(spec).into_iter();
+        let head = parts.next().unwrap_or_default();
+        let (name, color) = match head.split_once('=') {
+            Some((name, color)) => (name.to_string(), Some(color.to_string())),
+            None => (head, None),
+        };
+        Self {
+            name,
+            color,
+            patterns: parts.collect(),
+        }
+    }
+
+    /// pattern returns a pattern matching any of the patterns of the group, which are taken
+    /// literally if fixed_strings is set. A group without patterns matches nothing.
+    pub fn pattern(&self, fixed_strings: bool) -> String {
+        if self.patterns.is_empty() {
+            return r"\b\B".to_string();
+        }
+        self.patterns
+            .iter()
+            .map(|p| {
+                if fixed_strings {
+                    escape(p)
+                } else {
+                    format!("(?:{p})")
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("|")
+    }
+
+    /// legend returns the name of the group, followed by its patterns, with a range with the given
+    /// id for the name.
+    pub fn legend(&self, id: usize) -> (String, Vec<RangeWithId>) {
+        let legend = format!("{}: {}", self.name, self.patterns.join(" "));
+        let range = RangeWithId {
+            start_idx: 0,
+            end_idx: self.name.len(),
+            id,
+        };
+        (legend, vec![range])
+    }
+}
+
+/// GroupColors gives the ranges of the patterns of groups with a color of their own that color.
+/// It maps the ids of the colors of such a pattern to the id of the color of its group.
+pub struct GroupColors(pub Vec<(Range<usize>, usize)>);
+
+impl GroupColors {
+    pub fn recolor(&self, ranges: &mut [RangeWithId]) {
+        for r in ranges {
+            if let Some((_, id)) = self.0.iter().find(|(ids, _)| ids.contains(&r.id)) {
+                r.id = *id;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MatchOptions, compile_patterns, match_line};
+    use rstest::rstest;
+
+    fn r(start_idx: usize, end_idx: usize, id: usize) -> RangeWithId {
+        RangeWithId {
+            start_idx,
+            end_idx,
+            id,
+        }
+    }
+
+    #[rstest]
+    #[case::with_color("errors=red:ERROR:FATAL", "errors", Some("red"), vec!["ERROR", "FATAL"])]
+    #[case::without_color("network:timeout", "network", None, vec!["timeout"])]
+    #[case::escaped_colon(r"times:\d\:\d", "times", None, vec![r"\d:\d"])]
+    #[case::no_patterns("empty=blue", "empty", Some("blue"), vec![])]
+    fn test_parse(
+        #[case] spec: &str,
+        #[case] name: &str,
+        #[case] color: Option<&str>,
+        #[case] patterns: Vec<&str>,
+    ) {
+        let group = PatternGroup::parse(spec);
+        assert_eq!(group.name, name);
+        assert_eq!(group.color.as_deref(), color);
+        assert_eq!(group.patterns, patterns);
+    }
+
+    #[rstest]
+    #[case::regex(false, "(?:a+)|(?:b.)")]
+    #[case::fixed_strings(true, r"a\+|b\.")]
+    fn test_pattern(#[case] fixed_strings: bool, #[case] expected: &str) {
+        let group = PatternGroup::parse("g:a+:b.");
+        assert_eq!(group.pattern(fixed_strings), expected);
+    }
+
+    #[test]
+    fn test_patterns_share_color() {
+        let errors = PatternGroup::parse("errors:ERROR:FATAL");
+        let patterns = vec![errors.pattern(false), "timeout".to_string()];
+        let regexps = compile_patterns(&patterns, false, false).unwrap();
+        let ranges = match_line("ERROR timeout FATAL", &regexps, &MatchOptions::default());
+        assert_eq!(ranges, vec![r(0, 5, 1), r(6, 13, 0), r(14, 19, 1)]);
+    }
+
+    #[test]
+    fn test_empty_group_matches_nothing() {
+        let group = PatternGroup::parse("empty");
+        let regexps = compile_patterns(&[group.pattern(false)], false, false).unwrap();
+        assert!(match_line("empty", &regexps, &MatchOptions::default()).is_empty());
+    }
+
+    #[test]
+    fn test_legend() {
+        let group = PatternGroup::parse("errors=red:ERROR:FATAL");
+        assert_eq!(
+            group.legend(7),
+            ("errors: ERROR FATAL".to_string(), vec![r(0, 6, 7)])
+        );
+    }
+
+    #[test]
+    fn test_group_colors() {
+        let group_colors = GroupColors(vec![(2..4, 9)]);
+        let mut ranges = vec![r(0, 1, 1), r(1, 2, 2), r(2, 3, 3), r(3, 4, 4)];
+        group_colors.recolor(&mut ranges);
+        assert_eq!(ranges, vec![r(0, 1, 1), r(1, 2, 9), r(2, 3, 9), r(3, 4, 4)]);
+    }
+}
@@ -0,0 +1,125 @@
+use crate::RangeWithId;
+use crate::manifest::{json_matches, json_string};
+
+/// format_positions formats the ranges of a line as `line:start:end:pattern` tuples, one per line,
+/// for editor plugins that apply their own highlighting. Offsets are in bytes, with the end being
+/// exclusive, and line numbers are 1-based, counting from the start of the line's file. The tuples
+/// start with the file, like `file:line:start:end:pattern`, if given. The name of a range's pattern
+/// is looked up by its id, and comes last, as patterns may contain colons.
+pub fn format_positions<'a>(
+    file: Option<&str>,
+    line_number: usize,
+    ranges: &[RangeWithId],
+    name: impl Fn(usize) -> &'a str,
+) -> String {
+    let file = file.map(|file| format!("{file}:")).unwrap_or_default();
+    ranges
+        .iter()
+        .map(|r| {
+            format!(
+                "{file}{line_number}:{}:{}:{}\n",
+                r.start_idx,
+                r.end_idx,
+                name(r.id)
+            )
+        })
+        .collect()
+}
+
+/// format_ndjson formats a line and its ranges as a JSON object on a line of its own, like
+/// `{"line":3,"text":"an error","matches":[{"start":3,"end":8,"pattern":"error","text":"error"}]}`,
+/// for tools processing the matches as they stream in. Every line gets an object, with an empty
+/// array of matches if it has none. Line numbers and offsets are like in format_positions, and the
+/// object starts with a "file" member, like the records of --manifest, if the file is given.
+pub fn format_ndjson<'a>(
+    file: Option<&str>,
+    line_number: usize,
+    line: &str,
+    ranges: &[RangeWithId],
+    name: impl Fn(usize) -> &'a str,
+) -> String {
+    let file = file
+        .map(|file| format!("\"file\":{},", json_string(file)))
+        .unwrap_or_default();
+    format!(
+        "{{{file}\"line\":{line_number},\"text\":{},\"matches\":{}}}\n",
+        json_string(line),
+        json_matches(line, ranges, name)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MatchOptions, compile_patterns, match_line};
+
+    #[test]
+    fn test_format_positions_multiple_matches() {
+        let patterns = vec!["a+".to_string(), "b:c".to_string()];
+        let regexps = compile_patterns(&patterns, false, false).unwrap();
+        let ranges = match_line("xaa b:c a", &regexps, &MatchOptions::default());
+        // ids are in the reverse order of the patterns
+        let positions = format_positions(None, 3, &ranges, |id| &patterns[patterns.len() - 1 - id]);
+        assert_eq!(positions, "3:1:3:a+\n3:4:7:b:c\n3:8:9:a+\n");
+    }
+
+    #[test]
+    fn test_format_positions_no_ranges() {
+        assert_eq!(format_positions(None, 1, &[], |_| "x"), "");
+    }
+
+    #[test]
+    fn test_format_ndjson_one_record_per_line() {
+        let patterns = vec!["error".to_string(), r"\d+".to_string()];
+        let regexps = compile_patterns(&patterns, false, false).unwrap();
+        let name = |id: usize| patterns[patterns.len() - 1 - id].as_str();
+        let records = ["an \"error\" 42", "fine"]
+            .iter()
+            .enumerate()
+            .map(|(idx, line)| {
+                let ranges = match_line(line, &regexps, &MatchOptions::default());
+                format_ndjson(None, idx + 1, line, &ranges, name)
+            })
+            .collect::<String>();
+        assert_eq!(
+            records.lines().collect::<Vec<_>>(),
+            vec![
+                r#"{"line":1,"text":"an \"error\" 42","matches":[{"start":4,"end":9,"pattern":"error","text":"error"},{"start":11,"end":13,"pattern":"\\d+","text":"42"}]}"#,
+                r#"{"line":2,"text":"fine","matches":[]}"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_format_positions_file() {
+        let ranges = [
+            RangeWithId {
+                start_idx: 0,
+                end_idx: 2,
+                id: 0,
+            },
+            RangeWithId {
+                start_idx: 3,
+                end_idx: 4,
+                id: 0,
+            },
+        ];
+        assert_eq!(
+            format_positions(Some("a.log"), 2, &ranges, |_| "x"),
+            "a.log:2:0:2:x\na.log:2:3:4:x\n"
+        );
+    }
+
+    #[test]
+    fn test_format_ndjson_file() {
+        let ranges = [RangeWithId {
+            start_idx: 0,
+            end_idx: 2,
+            id: 0,
+        }];
+        assert_eq!(
+            format_ndjson(Some("a.log"), 2, "ab", &ranges, |_| "x"),
+            "{\"file\":\"a.log\",\"line\":2,\"text\":\"ab\",\"matches\":[{\"start\":0,\"end\":2,\"pattern\":\"x\",\"text\":\"ab\"}]}\n"
+        );
+    }
+}
@@ -0,0 +1,336 @@
+use crate::palette::Markup;
+use crate::{ColorSet, RangeWithId, add_range};
+use regex::Regex;
+use std::sync::LazyLock;
+
+pub const KEY_COLOR: &str = "bright-cyan";
+pub const VALUE_COLOR: &str = "bright-yellow";
+pub const NUMBER_COLOR: &str = "bright-green";
+pub const DUPLICATE_COLOR: &str = "bright-red";
+/// the background color of malformed escape sequences
+pub const ESCAPE_ERROR_COLOR: &str = "red";
+/// the background colors of the lines of the inputs, see tint_line
+pub const TINT_COLORS: &[&str] = &["236", "17", "22", "52", "53", "23"];
+/// the background color of lines repeating an earlier line
+pub const REPEATED_LINE_COLOR: &str = "238";
+/// the colors of the pairs of brackets by their depth, see BracketPairs
+pub const BRACKET_COLORS: &[&str] = &["bright-yellow", "bright-magenta", "bright-cyan"];
+/// the background color of control characters made visible by --show-control
+pub const CONTROL_COLOR: &str = "blue";
+/// the background color of non-ASCII characters
+pub const NON_ASCII_COLOR: &str = "bright-magenta";
+
+/// GapStyle is the style of the text between the matches given by --gap-style, which is added to
+/// the colors of the terminal rather than replacing them.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum GapStyle {
+    /// Faint text, which most terminals show in a dimmer color.
+    Dim,
+}
+
+impl GapStyle {
+    /// color_set returns the escape sequences turning the style on and off, or markup tags named
+    /// after the style if markup is given.
+    pub fn color_set(self, markup: Option<&Markup>) -> ColorSet {
+        let (name, on, off) = match self {
+            GapStyle::Dim => ("dim", "\x1b[2m", "\x1b[22m"),
+        };
+        match markup {
+            Some(Markup { open, close }) => {
+                ColorSet::new(format!("{open}{name}{close}"), format!("{open}/{close}"))
+            }
+            None => ColorSet::new(on, off),
+        }
+    }
+}
+
+// keys start with a letter or underscore, so that e.g. times like 12:34 are not taken for pairs;
+// values are either quoted, possibly containing escaped quotes, or extend up to the next whitespace
+static KEY_VALUE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r#"(?x)
+        ([A-Za-z_][\w.-]*)
+        (?:\s*=\s*|:\s+)
+        ("(?:[^"\\]|\\.)*"|'(?:[^'\\]|\\.)*'|[^\s"',;]+)
+        "#,
+    )
+    .unwrap()
+});
+
+static NON_ASCII: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"[^\x00-\x7F]+").unwrap());
+
+// numbers with optional thousands separators and decimals; a group of a different length than
+// three after a separator makes the number end before that separator
+static NUMBER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\b(?:\d{1,3}(?:,\d{3})+\b|\d+)(?:\.\d+)?\b").unwrap());
+static NUMBER_DECIMAL_COMMA: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\b(?:\d{1,3}(?:\.\d{3})+\b|\d+)(?:,\d+)?\b").unwrap());
+
+/// match_key_values adds ranges for the keys and values of `key=value` and `key: value` pairs in
+/// the line, with the given ids. Existing ranges take precedence.
+pub fn match_key_values(line: &str, ranges: &mut Vec<RangeWithId>, key_id: usize, value_id: usize) {
+    for caps in KEY_VALUE.captures_iter(line) {
+        for (g_idx, id) in [(1, key_id), (2, value_id)] {
+            if let Some(g) = caps.get(g_idx) {
+                add_range(
+                    ranges,
+                    RangeWithId {
+                        start_idx: g.start(),
+                        end_idx: g.end(),
+                        id,
+                    },
+                );
+            }
+        }
+    }
+}
+
+/// match_numbers adds ranges for the numbers in the line, with the given id. Digits may be grouped
+/// by `,` with `.` being the decimal separator, or the other way around if decimal_comma is set.
+/// Existing ranges take precedence.
+pub fn match_numbers(line: &str, ranges: &mut Vec<RangeWithId>, id: usize, decimal_comma: bool) {
+    let re = if decimal_comma {
+        &NUMBER_DECIMAL_COMMA
+    } else {
+        &NUMBER
+    };
+    for m in re.find_iter(line) {
+        add_range(
+            ranges,
+            RangeWithId {
+                start_idx: m.start(),
+                end_idx: m.end(),
+                id,
+            },
+        );
+    }
+}
+
+/// match_non_ascii adds ranges for the runs of non-ASCII characters in the line, with the given
+/// id. Existing ranges take precedence.
+pub fn match_non_ascii(line: &str, ranges: &mut Vec<RangeWithId>, id: usize) {
+    for m in NON_ASCII.find_iter(line) {
+        add_range(
+            ranges,
+            RangeWithId {
+                start_idx: m.start(),
+                end_idx: m.end(),
+                id,
+            },
+        );
+    }
+}
+
+/// tint_line adds a range covering the whole line with the given id, which makes up the background
+/// of the line between the existing ranges. --gap-style uses it to style the gaps between matches.
+pub fn tint_line(line: &str, ranges: &mut Vec<RangeWithId>, id: usize) {
+    if !line.is_empty() {
+        add_range(
+            ranges,
+            RangeWithId {
+                start_idx: 0,
+                end_idx: line.len(),
+                id,
+            },
+        );
+    }
+}
+
+/// append_count appends the number of matches of the line to it, like ` [3]`, for --count-inline,
+/// with a range with the given id. As it is appended after matching, it is never matched itself.
+pub fn append_count(line: &mut String, ranges: &mut Vec<RangeWithId>, count: usize, id: usize) {
+    let start_idx = line.len();
+    line.push_str(&format!(" [{count}]"));
+    ranges.push(RangeWithId {
+        start_idx: start_idx + 1,
+        end_idx: line.len(),
+        id,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn key_values(line: &str) -> Vec<(&str, usize)> {
+        let mut ranges = Vec::new();
+        match_key_values(line, &mut ranges, 0, 1);
+        ranges
+            .iter()
+            .map(|r| (&line[r.start_idx..r.end_idx], r.id))
+            .collect()
+    }
+
+    #[test]
+    fn test_match_key_values() {
+        assert_eq!(
+            key_values(r#"foo=bar baz="hello world""#),
+            vec![("foo", 0), ("bar", 1), ("baz", 0), (r#""hello world""#, 1)]
+        );
+    }
+
+    #[rstest]
+    #[case::colon("level: info", vec![("level", 0), ("info", 1)])]
+    #[case::spaces_around_equals("a = 1", vec![("a", 0), ("1", 1)])]
+    #[case::single_quotes("msg='it''s'", vec![("msg", 0), ("'it'", 1)])]
+    #[case::escaped_quote(r#"msg="say \"hi\"""#, vec![("msg", 0), (r#""say \"hi\"""#, 1)])]
+    #[case::separators("a=1,b=2;", vec![("a", 0), ("1", 1), ("b", 0), ("2", 1)])]
+    #[case::dotted_key("http.status=200", vec![("http.status", 0), ("200", 1)])]
+    #[case::time("at 12:34:56", vec![])]
+    #[case::url("see http://example.com", vec![])]
+    fn test_match_key_values_formats(#[case] line: &str, #[case] expected: Vec<(&str, usize)>) {
+        assert_eq!(key_values(line), expected);
+    }
+
+    #[test]
+    fn test_match_key_values_existing_ranges() {
+        let mut ranges = vec![RangeWithId {
+            start_idx: 4,
+            end_idx: 7,
+            id: 5,
+        }];
+        match_key_values("foo=bar", &mut ranges, 0, 1);
+        assert_eq!(ranges.len(), 2);
+        assert_eq!(ranges[1].id, 5);
+    }
+
+    #[rstest]
+    #[case::accented("caf\u{e9} ok", vec!["\u{e9}"])]
+    #[case::run("na\u{ef}ve \u{c3}\u{a9}", vec!["\u{ef}", "\u{c3}\u{a9}"])]
+    #[case::ascii("plain text", vec![])]
+    fn test_match_non_ascii(#[case] line: &str, #[case] expected: Vec<&str>) {
+        let mut ranges = Vec::new();
+        match_non_ascii(line, &mut ranges, 0);
+        let matched = ranges
+            .iter()
+            .map(|r| &line[r.start_idx..r.end_idx])
+            .collect::<Vec<_>>();
+        assert_eq!(matched, expected);
+    }
+
+    #[test]
+    fn test_colorize_non_ascii() {
+        use crate::{ColorSet, colorize};
+
+        let mut line = "caf\u{e9}!".to_string();
+        let mut ranges = Vec::new();
+        match_non_ascii(&line, &mut ranges, 0);
+        let reserved = [ColorSet::new("<n>", "</n>")];
+        colorize(&mut line, &[], &reserved, &ranges, 0);
+        assert_eq!(line, "caf<n>\u{e9}</n>!");
+    }
+
+    #[test]
+    fn test_tint_line() {
+        use crate::{ColorSet, colorize};
+
+        // the lines of two inputs, with the tint id picked by the input
+        let reserved = [
+            ColorSet::new("<t0>", "</t0>"),
+            ColorSet::new("<t1>", "</t1>"),
+        ];
+        let colors = [ColorSet::new("<m>", "</m>")];
+        let lines = [("a b", 0), ("b c", 1)];
+        let tinted = lines
+            .iter()
+            .map(|&(text, input)| {
+                let mut ranges = vec![RangeWithId {
+                    start_idx: text.find('b').unwrap(),
+                    end_idx: text.find('b').unwrap() + 1,
+                    id: 0,
+                }];
+                tint_line(text, &mut ranges, 1 + input);
+                let mut line = text.to_string();
+                colorize(&mut line, &colors, &reserved, &ranges, 1);
+                line
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(tinted, vec!["<t0>a </t0><m>b</m>", "<m>b</m><t1> c</t1>"]);
+    }
+
+    #[test]
+    fn test_gap_style() {
+        use crate::colorize;
+
+        let reserved = [GapStyle::Dim.color_set(None)];
+        let colors = [ColorSet::new("\x1b[31m", "\x1b[39m")];
+        let mut line = "a b c".to_string();
+        let mut ranges = vec![RangeWithId {
+            start_idx: 2,
+            end_idx: 3,
+            id: 0,
+        }];
+        tint_line(&line, &mut ranges, 1);
+        colorize(&mut line, &colors, &reserved, &ranges, 1);
+        // the match keeps its color, the gaps around it are dim
+        assert_eq!(line, "\x1b[2ma \x1b[22m\x1b[31mb\x1b[39m\x1b[2m c\x1b[22m");
+    }
+
+    #[test]
+    fn test_gap_style_markup() {
+        let markup = Markup {
+            open: "[[".to_string(),
+            close: "]]".to_string(),
+        };
+        assert_eq!(
+            GapStyle::Dim.color_set(Some(&markup)),
+            ColorSet::new("[[dim]]", "[[/]]")
+        );
+    }
+
+    #[test]
+    fn test_tint_line_empty() {
+        let mut ranges = Vec::new();
+        tint_line("", &mut ranges, 3);
+        assert!(ranges.is_empty());
+    }
+
+    #[test]
+    fn test_append_count() {
+        use crate::{ColorSet, MatchOptions, colorize, compile_patterns, match_line};
+
+        let regexps = compile_patterns(&[r"\d".to_string()], false, false).unwrap();
+        let mut line = "1 2 3".to_string();
+        let mut ranges = match_line(&line, &regexps, &MatchOptions::default());
+        let count = ranges.len();
+        append_count(&mut line, &mut ranges, count, 1);
+        assert_eq!(line, "1 2 3 [3]");
+        colorize(
+            &mut line,
+            &[ColorSet::new("<m>", "</m>")],
+            &[ColorSet::new("<dim>", "</dim>")],
+            &ranges,
+            1,
+        );
+        assert_eq!(line, "<m>1</m> <m>2</m> <m>3</m> <dim>[3]</dim>");
+    }
+
+    fn numbers(line: &str, decimal_comma: bool) -> Vec<&str> {
+        let mut ranges = Vec::new();
+        match_numbers(line, &mut ranges, 0, decimal_comma);
+        ranges
+            .iter()
+            .map(|r| &line[r.start_idx..r.end_idx])
+            .collect()
+    }
+
+    #[rstest]
+    #[case::grouped_decimal("total: 1,234.56 EUR", vec!["1,234.56"])]
+    #[case::millions("1,000,000 and 42", vec!["1,000,000", "42"])]
+    #[case::decimal("pi is 3.14", vec!["3.14"])]
+    #[case::list("1, 2,3", vec!["1", "2", "3"])]
+    #[case::bad_grouping("1,2345", vec!["1", "2345"])]
+    #[case::in_word("v2 x86", vec![])]
+    fn test_match_numbers(#[case] line: &str, #[case] expected: Vec<&str>) {
+        assert_eq!(numbers(line, false), expected);
+    }
+
+    #[rstest]
+    #[case::grouped_decimal("1.234,56", vec!["1.234,56"])]
+    #[case::millions("1.000.000", vec!["1.000.000"])]
+    #[case::decimal("3,14", vec!["3,14"])]
+    fn test_match_numbers_decimal_comma(#[case] line: &str, #[case] expected: Vec<&str>) {
+        assert_eq!(numbers(line, true), expected);
+    }
+}
@@ -0,0 +1,170 @@
+use crate::RangeWithId;
+use regex::Regex;
+use std::borrow::Cow;
+use std::cmp::{Reverse, max};
+
+/// Priorities maps the ids of the ranges of patterns to the priorities of the patterns, which are
+/// their positions among the given patterns: like for overlapping matches, patterns given later
+/// take precedence.
+pub struct Priorities {
+    /// The priority of the pattern of every color, in the order of the colors.
+    color_priorities: Vec<usize>,
+    /// The first id of the colors used by --alternate, if any.
+    alternate_offset: Option<usize>,
+}
+
+impl Priorities {
+    /// new takes the regexps in the order they are compiled in, that is, the last given pattern
+    /// first.
+    pub fn new(
+        regexps: &[Regex],
+        vary_group_colors: bool,
+        alternate_offset: Option<usize>,
+    ) -> Self {
+        let color_priorities = regexps
+            .iter()
+            .rev()
+            .enumerate()
+            .flat_map(|(priority, re)| {
+                let colors = if vary_group_colors {
+                    max(1, re.captures_len().saturating_sub(1))
+                } else {
+                    1
+                };
+                std::iter::repeat_n(priority, colors)
+            })
+            .collect();
+        Self {
+            color_priorities,
+            alternate_offset,
+        }
+    }
+
+    /// line_priority returns the highest priority of the patterns with ranges in the line, or None
+    /// if there are none. Ranges with other ids, like those of --kv, are ignored.
+    pub fn line_priority(&self, ranges: &[RangeWithId]) -> Option<usize> {
+        let pattern_color_count = self.color_priorities.len();
+        ranges
+            .iter()
+            .filter_map(|r| match self.alternate_offset {
+                _ if r.id < pattern_color_count => Some(r.id),
+                Some(offset) if (offset..offset + pattern_color_count).contains(&r.id) => {
+                    Some(r.id - offset)
+                }
+                _ => None,
+            })
+            .map(|id| self.color_priorities[pattern_color_count - 1 - id])
+            .max()
+    }
+}
+
+/// sort_by_priority sorts the lines by their priorities, highest first, followed by the lines
+/// without a priority. Lines of the same priority keep their order.
+pub fn sort_by_priority<T>(lines: &mut [(Option<usize>, T)]) {
+    lines.sort_by_key(|(priority, _)| (priority.is_none(), Reverse(*priority)));
+}
+
+/// PrioritizedLine is a line with its priority and its terminator.
+pub type PrioritizedLine<L> = (Option<usize>, (L, Cow<'static, str>));
+
+/// terminate_lines gives the lines without a terminator, like the last line of an input without a
+/// trailing newline, the terminator of the other lines, or a newline, so that they do not run into
+/// the lines printed after them once sorted.
+pub fn terminate_lines<L>(lines: &mut [PrioritizedLine<L>]) {
+    let terminator = lines
+        .iter()
+        .map(|(_, (_, terminator))| terminator)
+        .find(|terminator| !terminator.is_empty())
+        .cloned()
+        .unwrap_or(Cow::Borrowed("\n"));
+    for (_, (_, line_terminator)) in lines.iter_mut() {
+        if line_terminator.is_empty() {
+            *line_terminator = terminator.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile_patterns;
+    use rstest::rstest;
+
+    fn r(start_idx: usize, end_idx: usize, id: usize) -> RangeWithId {
+        RangeWithId {
+            start_idx,
+            end_idx,
+            id,
+        }
+    }
+
+    #[rstest]
+    // with the patterns "a" and "b", "b" has id 0
+    #[case::last_pattern(vec![r(0, 1, 0)], Some(1))]
+    #[case::first_pattern(vec![r(0, 1, 1)], Some(0))]
+    #[case::both(vec![r(0, 1, 1), r(2, 3, 0)], Some(1))]
+    #[case::alternate(vec![r(0, 1, 10)], Some(1))]
+    #[case::other_id(vec![r(0, 1, 5)], None)]
+    #[case::no_ranges(vec![], None)]
+    fn test_line_priority(#[case] ranges: Vec<RangeWithId>, #[case] expected: Option<usize>) {
+        let regexps = compile_patterns(&["a".into(), "b".into()], false, false).unwrap();
+        let priorities = Priorities::new(&regexps, false, Some(10));
+        assert_eq!(priorities.line_priority(&ranges), expected);
+    }
+
+    #[rstest]
+    #[case::first_pattern_groups(vec![r(0, 1, 2), r(1, 2, 3)], Some(0))]
+    #[case::last_pattern_group(vec![r(0, 1, 1)], Some(1))]
+    fn test_line_priority_vary_group_colors(
+        #[case] ranges: Vec<RangeWithId>,
+        #[case] expected: Option<usize>,
+    ) {
+        // "(a)(b)" has the ids 2 and 3, "(c)(d)" the ids 0 and 1
+        let regexps = compile_patterns(&["(a)(b)".into(), "(c)(d)".into()], false, false).unwrap();
+        let priorities = Priorities::new(&regexps, true, None);
+        assert_eq!(priorities.line_priority(&ranges), expected);
+    }
+
+    #[test]
+    fn test_sort_by_priority() {
+        let mut lines = vec![
+            (None, "none 1"),
+            (Some(0), "low 1"),
+            (Some(2), "high"),
+            (None, "none 2"),
+            (Some(0), "low 2"),
+            (Some(1), "medium"),
+        ];
+        sort_by_priority(&mut lines);
+        assert_eq!(
+            lines.iter().map(|(_, line)| *line).collect::<Vec<_>>(),
+            vec!["high", "medium", "low 1", "low 2", "none 1", "none 2"]
+        );
+    }
+
+    #[rstest]
+    #[case::newline("\n", "high b\nlow a\nnothing\n")]
+    #[case::crlf("\r\n", "high b\r\nlow a\r\nnothing\r\n")]
+    fn test_terminate_lines(#[case] terminator: &'static str, #[case] expected: &str) {
+        // the highest priority line is the last line of the input, without a terminator
+        let mut lines = vec![
+            (Some(0), ("low a", Cow::Borrowed(terminator))),
+            (None, ("nothing", Cow::Borrowed(terminator))),
+            (Some(1), ("high b", Cow::Borrowed(""))),
+        ];
+        terminate_lines(&mut lines);
+        sort_by_priority(&mut lines);
+        let printed = lines
+            .iter()
+            .map(|(_, (line, terminator))| format!("{line}{terminator}"))
+            .collect::<String>();
+        assert_eq!(printed, expected);
+    }
+
+    #[test]
+    fn test_terminate_lines_single() {
+        let mut lines = vec![(Some(0), ("a", Cow::Borrowed("")))];
+        terminate_lines(&mut lines);
+        assert_eq!(lines[0].1.1, "\n");
+    }
+}
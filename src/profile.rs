@@ -0,0 +1,152 @@
+use crate::{MatchOptions, match_line_into};
+use regex::Regex;
+use std::time::{Duration, Instant};
+
+/// slow_line_report returns the report for a line whose matching took longer than the threshold,
+/// or None if it was fast enough. Line numbers are 1-based.
+pub fn slow_line_report(
+    line_number: usize,
+    elapsed: Duration,
+    threshold: Duration,
+) -> Option<String> {
+    (elapsed > threshold).then(|| {
+        format!(
+            "line {line_number}: {:.3}ms",
+            elapsed.as_secs_f64() * 1000.0
+        )
+    })
+}
+
+/// PatternTimes adds up the time spent matching each pattern on its own, for the report printed by
+/// --benchmark-patterns.
+#[derive(Debug)]
+pub struct PatternTimes {
+    pub lines: usize,
+    /// The total time per pattern, in the order the patterns are given in.
+    pub totals: Vec<Duration>,
+}
+
+impl PatternTimes {
+    pub fn new(pattern_count: usize) -> Self {
+        Self {
+            lines: 0,
+            totals: vec![Duration::ZERO; pattern_count],
+        }
+    }
+
+    /// time_line matches each of the regexps, which are in the order they are compiled in, that
+    /// is, reversed, against the line on its own, and adds up the times.
+    pub fn time_line(&mut self, line: &str, regexps: &[Regex], options: &MatchOptions) {
+        let mut ranges = Vec::new();
+        let elapsed = regexps
+            .iter()
+            .rev()
+            .map(|re| {
+                let start = Instant::now();
+                match_line_into(line, std::slice::from_ref(re), options, &mut ranges);
+                start.elapsed()
+            })
+            .collect::<Vec<_>>();
+        self.add_line(&elapsed);
+    }
+
+    /// add_line adds the times it took to match each pattern against a line.
+    pub fn add_line(&mut self, elapsed: &[Duration]) {
+        self.lines += 1;
+        for (total, elapsed) in self.totals.iter_mut().zip(elapsed) {
+            *total += *elapsed;
+        }
+    }
+
+    /// report formats the total time of each pattern and its share of the time of all patterns,
+    /// the most expensive pattern first.
+    pub fn report(&self, patterns: &[String]) -> String {
+        let sum = self.totals.iter().sum::<Duration>().as_secs_f64();
+        let mut by_time = patterns.iter().zip(&self.totals).collect::<Vec<_>>();
+        by_time.sort_by(|(_, a), (_, b)| b.cmp(a));
+        let mut report = format!("pattern matching times for {} lines:", self.lines);
+        for (pattern, total) in by_time {
+            let share = if sum > 0.0 {
+                total.as_secs_f64() * 100.0 / sum
+            } else {
+                0.0
+            };
+            report.push_str(&format!(
+                "\n{share:5.1}% {:10.3}ms  {pattern}",
+                total.as_secs_f64() * 1000.0
+            ));
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile_patterns;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::fast(Duration::from_micros(10), None)]
+    #[case::at_threshold(Duration::from_millis(1), None)]
+    #[case::slow(Duration::from_micros(1500), Some("line 7: 1.500ms"))]
+    #[case::very_slow(Duration::from_secs(2), Some("line 7: 2000.000ms"))]
+    fn test_slow_line_report(#[case] elapsed: Duration, #[case] expected: Option<&str>) {
+        let report = slow_line_report(7, elapsed, Duration::from_millis(1));
+        assert_eq!(report.as_deref(), expected);
+    }
+
+    #[test]
+    fn test_pattern_times_report() {
+        let patterns = vec!["fast".to_string(), "slow".to_string(), "unused".to_string()];
+        let mut times = PatternTimes::new(patterns.len());
+        times.add_line(&[
+            Duration::from_millis(1),
+            Duration::from_millis(5),
+            Duration::ZERO,
+        ]);
+        times.add_line(&[
+            Duration::from_millis(1),
+            Duration::from_millis(3),
+            Duration::ZERO,
+        ]);
+        assert_eq!(
+            times.totals,
+            vec![
+                Duration::from_millis(2),
+                Duration::from_millis(8),
+                Duration::ZERO
+            ]
+        );
+        assert_eq!(
+            times.report(&patterns).lines().collect::<Vec<_>>(),
+            vec![
+                "pattern matching times for 2 lines:",
+                " 80.0%      8.000ms  slow",
+                " 20.0%      2.000ms  fast",
+                "  0.0%      0.000ms  unused",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pattern_times_no_time() {
+        let times = PatternTimes::new(1);
+        assert_eq!(
+            times.report(&["a".to_string()]),
+            "pattern matching times for 0 lines:\n  0.0%      0.000ms  a"
+        );
+    }
+
+    #[test]
+    fn test_pattern_times_time_line() {
+        let patterns = vec!["a".to_string(), "b".to_string()];
+        let regexps = compile_patterns(&patterns, false, false).unwrap();
+        let mut times = PatternTimes::new(patterns.len());
+        for line in ["a b", "aaaa", ""] {
+            times.time_line(line, &regexps, &MatchOptions::default());
+        }
+        assert_eq!(times.lines, 3);
+        assert_eq!(times.totals.len(), 2);
+    }
+}
@@ -0,0 +1,209 @@
+use crate::ColorSet;
+use regex::Regex;
+use regex_syntax::ParserBuilder;
+use regex_syntax::hir::{Hir, HirKind};
+
+// the syntax supported by the regex crate, see https://docs.rs/regex/latest/regex/#syntax
+const ENTRIES: &[(&str, &str)] = &[
+    (".", "any character except newline"),
+    (
+        r"\d \w \s",
+        "digit, word character, whitespace (Unicode aware)",
+    ),
+    (r"\D \W \S", "not a digit, word character, whitespace"),
+    (r"\b \B", "word boundary, not a word boundary"),
+    ("[abc] [^abc]", "any of, none of the characters"),
+    ("[a-z] [[:alpha:]]", "character range, ASCII class"),
+    (r"\p{Greek}", "Unicode class"),
+    ("^ $", "start, end of line"),
+    ("x* x+ x?", "zero or more, one or more, zero or one x"),
+    ("x{n,m}", "between n and m times x"),
+    (
+        "x*? x+? x??",
+        "lazy versions, matching as little as possible",
+    ),
+    ("a|b", "a or b"),
+    ("(x)", "capturing group, highlighted in its own color"),
+    ("(?<name>x)", "named capturing group"),
+    ("(?:x)", "non-capturing group, not highlighted separately"),
+    (
+        "(?i)",
+        "case-insensitive from here on, see also --ignore-case",
+    ),
+];
+
+const UNSUPPORTED: &str = "Not supported, as matching is guaranteed to take linear time: \
+    look-around like (?=x) or (?<!x), and backreferences like \\1.";
+
+/// regex_help returns a short reference of the regular expression syntax, with the syntax of each
+/// entry in the given color.
+pub fn regex_help(color: Option<&ColorSet>) -> String {
+    let width = ENTRIES.iter().map(|(syntax, _)| syntax.len()).max();
+    let width = width.unwrap_or_default();
+    let mut help = String::new();
+    for (syntax, description) in ENTRIES {
+        let padding = " ".repeat(width - syntax.len());
+        match color {
+            Some(color) => help.push_str(&format!("  {}{syntax}{}", color.on, color.off)),
+            None => help.push_str(&format!("  {syntax}")),
+        }
+        help.push_str(&format!("{padding}  {description}\n"));
+    }
+    help.push('\n');
+    help.push_str(UNSUPPORTED);
+    help.push('\n');
+    help
+}
+
+/// regex_debug returns the structure of the regexp as parsed by regex-syntax, one node per line,
+/// followed by some of its properties, for --regex-debug. It explains e.g. why a pattern matches
+/// more than expected, or what a case-insensitive pattern turns into.
+pub fn regex_debug(re: &Regex, ignore_case: bool) -> String {
+    let mut debug = format!("pattern `{}`:\n", re.as_str());
+    let hir = match ParserBuilder::new()
+        .case_insensitive(ignore_case)
+        .build()
+        .parse(re.as_str())
+    {
+        Ok(hir) => hir,
+        Err(err) => return format!("{debug}  cannot be parsed: {err}\n"),
+    };
+    push_node(&mut debug, &hir, 1);
+    let properties = hir.properties();
+    let max_len = match properties.maximum_len() {
+        Some(max_len) => max_len.to_string(),
+        None => "unbounded".to_string(),
+    };
+    debug.push_str(&format!(
+        "  capturing groups: {}, match length: {} to {max_len} bytes{}\n",
+        properties.explicit_captures_len(),
+        properties.minimum_len().unwrap_or_default(),
+        if properties.is_literal() {
+            ", literal"
+        } else {
+            ""
+        },
+    ));
+    debug
+}
+
+/// push_node pushes a line describing the node, and lines for its children, indented further.
+fn push_node(debug: &mut String, hir: &Hir, depth: usize) {
+    let indent = "  ".repeat(depth);
+    let subs: &[Hir] = match hir.kind() {
+        HirKind::Empty => {
+            debug.push_str(&format!("{indent}empty\n"));
+            &[]
+        }
+        HirKind::Literal(literal) => {
+            let text = String::from_utf8_lossy(&literal.0);
+            debug.push_str(&format!("{indent}literal {text:?}\n"));
+            &[]
+        }
+        HirKind::Class(_) => {
+            debug.push_str(&format!("{indent}class {hir}\n"));
+            &[]
+        }
+        HirKind::Look(look) => {
+            debug.push_str(&format!("{indent}assertion {look:?}\n"));
+            &[]
+        }
+        HirKind::Repetition(repetition) => {
+            let max = repetition
+                .max
+                .map(|max| max.to_string())
+                .unwrap_or_default();
+            let lazy = if repetition.greedy { "" } else { ", lazy" };
+            debug.push_str(&format!(
+                "{indent}repetition {{{},{max}}}{lazy}\n",
+                repetition.min
+            ));
+            std::slice::from_ref(&repetition.sub)
+        }
+        HirKind::Capture(capture) => {
+            let name = match &capture.name {
+                Some(name) => format!(" <{name}>"),
+                None => String::new(),
+            };
+            debug.push_str(&format!("{indent}capture {}{name}\n", capture.index));
+            std::slice::from_ref(&capture.sub)
+        }
+        HirKind::Concat(subs) => {
+            debug.push_str(&format!("{indent}concatenation\n"));
+            subs
+        }
+        HirKind::Alternation(subs) => {
+            debug.push_str(&format!("{indent}alternation\n"));
+            subs
+        }
+    };
+    for sub in subs {
+        push_node(debug, sub, depth + 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_regex_help_mentions_unsupported_syntax() {
+        let help = regex_help(None);
+        assert!(help.contains("backreferences"), "{help}");
+        assert!(help.contains("look-around"), "{help}");
+    }
+
+    #[test]
+    fn test_regex_help_color() {
+        let color = ColorSet::new("\x01", "\x02");
+        let help = regex_help(Some(&color));
+        assert!(help.starts_with("  \x01.\x02 "), "{help}");
+        assert_eq!(help.matches('\x01').count(), ENTRIES.len());
+    }
+
+    #[test]
+    fn test_regex_debug_alternation() {
+        let re = Regex::new("foo|ba[rz]+").unwrap();
+        assert_eq!(
+            regex_debug(&re, false),
+            "pattern `foo|ba[rz]+`:\n\
+            \x20 alternation\n\
+            \x20   literal \"foo\"\n\
+            \x20   concatenation\n\
+            \x20     literal \"ba\"\n\
+            \x20     repetition {1,}\n\
+            \x20       class [rz]\n\
+            \x20 capturing groups: 0, match length: 3 to unbounded bytes\n"
+        );
+    }
+
+    #[test]
+    fn test_regex_debug_groups() {
+        let re = Regex::new("(?<word>a)(b)?").unwrap();
+        let debug = regex_debug(&re, true);
+        assert!(debug.contains("capture 1 <word>\n"), "{debug}");
+        assert!(debug.contains("class [Aa]\n"), "{debug}");
+        assert!(debug.contains("repetition {0,1}\n"), "{debug}");
+        assert!(
+            debug.contains("capturing groups: 2, match length: 1 to 2 bytes"),
+            "{debug}"
+        );
+    }
+
+    #[test]
+    fn test_regex_help_is_accurate() {
+        for syntax in [
+            r"\d",
+            r"\p{Greek}",
+            "[[:alpha:]]",
+            "x{1,2}",
+            "x*?",
+            "(?<name>x)",
+        ] {
+            assert!(Regex::new(syntax).is_ok(), "{syntax}");
+        }
+        for syntax in ["(?=x)", "(?<!x)", r"(a)\1"] {
+            assert!(Regex::new(syntax).is_err(), "{syntax}");
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use std::collections::HashSet;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+/// RepeatedLines tells which lines of the stream are exact duplicates of an earlier line, for
+/// --highlight-repeated-lines. Only hashes of the lines are kept, and at most max_lines of them if
+/// given, after which new lines are not remembered anymore, while repeats of the remembered ones
+/// are still found.
+pub struct RepeatedLines {
+    seen: HashSet<u64>,
+    max_lines: Option<usize>,
+}
+
+impl RepeatedLines {
+    pub fn new(max_lines: Option<usize>) -> Self {
+        Self {
+            seen: HashSet::new(),
+            max_lines,
+        }
+    }
+
+    /// repeated returns whether the line was seen before, and remembers it otherwise.
+    pub fn repeated(&mut self, line: &str) -> bool {
+        let mut hasher = DefaultHasher::new();
+        line.hash(&mut hasher);
+        let hash = hasher.finish();
+        if self.seen.contains(&hash) {
+            return true;
+        }
+        if self
+            .max_lines
+            .is_none_or(|max_lines| self.seen.len() < max_lines)
+        {
+            self.seen.insert(hash);
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::scattered(None, &["a", "b", "c", "a", "b", "a"], vec![false, false, false, true, true, true])]
+    #[case::adjacent(None, &["a", "a"], vec![false, true])]
+    #[case::exact(None, &["a", "a ", "A"], vec![false, false, false])]
+    #[case::capped(Some(2), &["a", "b", "c", "c", "a"], vec![false, false, false, false, true])]
+    fn test_repeated(
+        #[case] max_lines: Option<usize>,
+        #[case] lines: &[&str],
+        #[case] expected: Vec<bool>,
+    ) {
+        let mut repeated_lines = RepeatedLines::new(max_lines);
+        assert_eq!(
+            lines
+                .iter()
+                .map(|line| repeated_lines.repeated(line))
+                .collect::<Vec<_>>(),
+            expected
+        );
+    }
+}
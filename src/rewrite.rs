@@ -0,0 +1,318 @@
+use crate::{MatchOptions, RangeWithId, add_range, group_id, regexp_ids};
+use regex::Regex;
+use std::ops::Range;
+
+/// GroupRef refers to a capturing group of a match, by number or by name.
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum GroupRef {
+    Index(usize),
+    Name(String),
+}
+
+/// Segment is a part of a template: text taken as it is, or the text of a group of the match.
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Segment {
+    Text(String),
+    Group(GroupRef),
+}
+
+/// Template is a --rewrite template like `$3/$2/$1`. Like in the replacement strings of the regex
+/// crate, groups are written as `$1` or `$name`, or as `${1}` or `${name}` when followed by
+/// characters that could be part of the reference, and `$$` is a literal `$`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Template(Vec<Segment>);
+
+impl Template {
+    pub fn parse(template: &str) -> Self {
+        let mut segments = Vec::new();
+        let mut text = String::new();
+        let mut rest = template;
+        while let Some(dollar) = rest.find('$') {
+            text.push_str(&rest[..dollar]);
+            let after = &rest[dollar + 1..];
+            let (reference, len) = if after.starts_with('$') {
+                (None, 1)
+            } else if let Some(braced) = after.strip_prefix('{')
+                && let Some(end) = braced.find('}')
+            {
+                (Some(&braced[..end]), end + 2)
+            } else {
+                let end = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                (Some(&after[..end]).filter(|r| !r.is_empty()), end)
+            };
+            match reference {
+                Some(reference) => {
+                    if !text.is_empty() {
+                        segments.push(Segment::Text(std::mem::take(&mut text)));
+                    }
+                    segments.push(Segment::Group(match reference.parse() {
+                        Ok(idx) => GroupRef::Index(idx),
+                        Err(_) => GroupRef::Name(reference.to_string()),
+                    }));
+                }
+                None => text.push('$'),
+            }
+            rest = &after[len..];
+        }
+        text.push_str(rest);
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+        Self(segments)
+    }
+
+    /// unknown_group returns the first reference in the template to a group the regexp does not
+    /// have, written like in the template, as the match would otherwise silently lose its text.
+    pub fn unknown_group(&self, re: &Regex) -> Option<String> {
+        self.0.iter().find_map(|segment| match segment {
+            Segment::Group(GroupRef::Index(idx)) if *idx >= re.captures_len() => {
+                Some(format!("${idx}"))
+            }
+            Segment::Group(GroupRef::Name(name))
+                if !re.capture_names().any(|n| n == Some(name)) =>
+            {
+                Some(format!("${{{name}}}"))
+            }
+            _ => None,
+        })
+    }
+}
+
+/// Rewriter replaces the matches of regexps by their templates, giving the text of every group in
+/// the replacement the color of that group, so that e.g. the parts of a reformatted date keep
+/// their colors.
+pub struct Rewriter {
+    /// The template of every regexp, if it has one.
+    pub templates: Vec<Option<Template>>,
+}
+
+impl Rewriter {
+    /// rewrite replaces the matches of the regexps with templates in the line, and moves the
+    /// ranges of the text around them accordingly. Matches of regexps that come first take
+    /// precedence over the ones overlapping with them.
+    pub fn rewrite(
+        &self,
+        line: &mut String,
+        regexps: &[Regex],
+        options: &MatchOptions,
+        ranges: &mut Vec<RangeWithId>,
+    ) {
+        let ids = regexp_ids(regexps, options);
+        let mut matches = Vec::new();
+        for ((re, template), ids) in regexps.iter().zip(&self.templates).zip(&ids) {
+            let Some(template) = template else {
+                continue;
+            };
+            for caps in re.captures_iter(line) {
+                let m = caps.get(0).unwrap();
+                if m.is_empty()
+                    || matches
+                        .iter()
+                        .any(|(range, ..): &(Range<usize>, _, _, _, _)| {
+                            range.start < m.end() && m.start() < range.end
+                        })
+                {
+                    continue;
+                }
+                matches.push((m.range(), caps, re, template, ids));
+            }
+        }
+        if matches.is_empty() {
+            return;
+        }
+        matches.sort_by_key(|(range, ..)| range.start);
+
+        let mut rewritten = String::with_capacity(line.len());
+        let mut rewritten_ranges = Vec::with_capacity(ranges.len());
+        // copies the text between the matches, with the parts of the ranges within it
+        let copy = |rewritten: &mut String, rewritten_ranges: &mut Vec<_>, copied: Range<_>| {
+            let offset = rewritten.len() as isize - copied.start as isize;
+            for r in ranges.iter() {
+                let start_idx = r.start_idx.max(copied.start);
+                let end_idx = r.end_idx.min(copied.end);
+                if start_idx < end_idx {
+                    rewritten_ranges.push(RangeWithId {
+                        start_idx: (start_idx as isize + offset) as usize,
+                        end_idx: (end_idx as isize + offset) as usize,
+                        id: r.id,
+                    });
+                }
+            }
+            rewritten.push_str(&line[copied]);
+        };
+        let mut copied = 0;
+        for (range, caps, re, template, ids) in &matches {
+            copy(&mut rewritten, &mut rewritten_ranges, copied..range.start);
+            for segment in &template.0 {
+                let (text, g_idx) = match segment {
+                    Segment::Text(text) => {
+                        rewritten.push_str(text);
+                        continue;
+                    }
+                    Segment::Group(GroupRef::Index(idx)) => (caps.get(*idx), Some(*idx)),
+                    Segment::Group(GroupRef::Name(name)) => (
+                        caps.name(name),
+                        re.capture_names().position(|n| n == Some(name)),
+                    ),
+                };
+                let (Some(text), Some(g_idx)) = (text, g_idx) else {
+                    continue;
+                };
+                let start_idx = rewritten.len();
+                rewritten.push_str(text.as_str());
+                if !text.is_empty() {
+                    add_range(
+                        &mut rewritten_ranges,
+                        RangeWithId {
+                            start_idx,
+                            end_idx: rewritten.len(),
+                            id: group_id(ids, g_idx),
+                        },
+                    );
+                }
+            }
+            copied = range.end;
+        }
+        copy(&mut rewritten, &mut rewritten_ranges, copied..line.len());
+        *line = rewritten;
+        *ranges = rewritten_ranges;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compile_patterns, match_line_into};
+    use rstest::rstest;
+
+    fn r(start_idx: usize, end_idx: usize, id: usize) -> RangeWithId {
+        RangeWithId {
+            start_idx,
+            end_idx,
+            id,
+        }
+    }
+
+    fn text(s: &str) -> Segment {
+        Segment::Text(s.to_string())
+    }
+
+    fn group(idx: usize) -> Segment {
+        Segment::Group(GroupRef::Index(idx))
+    }
+
+    fn name(s: &str) -> Segment {
+        Segment::Group(GroupRef::Name(s.to_string()))
+    }
+
+    #[rstest]
+    #[case::indexes("$3/$2/$1", vec![group(3), text("/"), group(2), text("/"), group(1)])]
+    #[case::names("$day.${month}x", vec![name("day"), text("."), name("month"), text("x")])]
+    #[case::dollar("$$1 costs $", vec![text("$1 costs $")])]
+    #[case::braced_index("${1}0", vec![group(1), text("0")])]
+    #[case::unclosed_brace("${1", vec![text("${1")])]
+    #[case::text_only("plain", vec![text("plain")])]
+    fn test_parse(#[case] template: &str, #[case] expected: Vec<Segment>) {
+        assert_eq!(Template::parse(template), Template(expected));
+    }
+
+    #[rstest]
+    #[case::index_beyond("(a)(b)", "$3", Some("$3"))]
+    #[case::index_known("(a)(b)", "$2$1$0", None)]
+    #[case::name_unknown("(?<k>a)", "${k}${v}", Some("${v}"))]
+    #[case::name_known("(?<k>a)", "$k", None)]
+    // groups made non-capturing are gone
+    #[case::non_capturing("(?:a)", "$1", Some("$1"))]
+    #[case::text_only("a", "x", None)]
+    fn test_unknown_group(
+        #[case] pattern: &str,
+        #[case] template: &str,
+        #[case] expected: Option<&str>,
+    ) {
+        let re = Regex::new(pattern).unwrap();
+        assert_eq!(
+            Template::parse(template).unknown_group(&re).as_deref(),
+            expected
+        );
+    }
+
+    fn rewrite(
+        patterns: &[&str],
+        templates: &[Option<&str>],
+        line: &str,
+        options: MatchOptions,
+    ) -> (String, Vec<RangeWithId>) {
+        let patterns = patterns.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        let regexps = compile_patterns(&patterns, false, false).unwrap();
+        // the templates are given in the order of the patterns, the regexps are in reverse order
+        let rewriter = Rewriter {
+            templates: templates
+                .iter()
+                .rev()
+                .map(|t| t.map(Template::parse))
+                .collect(),
+        };
+        let mut line = line.to_string();
+        let mut ranges = Vec::new();
+        match_line_into(&line, &regexps, &options, &mut ranges);
+        rewriter.rewrite(&mut line, &regexps, &options, &mut ranges);
+        (line, ranges)
+    }
+
+    #[test]
+    fn test_rewrite_date() {
+        let options = MatchOptions {
+            vary_group_colors: true,
+            ..MatchOptions::default()
+        };
+        let (line, ranges) = rewrite(
+            &[r"(\d{4})-(\d\d)-(\d\d)"],
+            &[Some("$3/$2/$1")],
+            "on 2023-01-02.",
+            options,
+        );
+        assert_eq!(line, "on 02/01/2023.");
+        // the day, month and year keep the colors of their groups
+        assert_eq!(ranges, vec![r(3, 5, 0), r(6, 8, 1), r(9, 13, 2)]);
+    }
+
+    #[test]
+    fn test_rewrite_named_groups() {
+        let (line, ranges) = rewrite(
+            &[r"(?<key>\w+)=(?<value>\w+)"],
+            &[Some("${value} <- $key")],
+            "a=1",
+            MatchOptions {
+                vary_group_colors: true,
+                ..MatchOptions::default()
+            },
+        );
+        assert_eq!(line, "1 <- a");
+        assert_eq!(ranges, vec![r(0, 1, 0), r(5, 6, 1)]);
+    }
+
+    #[test]
+    fn test_rewrite_moves_other_ranges() {
+        // only the first pattern has a template
+        let (line, ranges) = rewrite(
+            &[r"(\d+)ms", "took"],
+            &[Some("$1 milliseconds"), None],
+            "took 5ms, took 10ms",
+            MatchOptions::default(),
+        );
+        assert_eq!(line, "took 5 milliseconds, took 10 milliseconds");
+        assert_eq!(
+            ranges,
+            vec![r(0, 4, 0), r(5, 6, 1), r(21, 25, 0), r(26, 28, 1)]
+        );
+    }
+
+    #[test]
+    fn test_rewrite_without_matches() {
+        let (line, ranges) = rewrite(&["x(y)"], &[Some("$1")], "abc", MatchOptions::default());
+        assert_eq!(line, "abc");
+        assert!(ranges.is_empty());
+    }
+}
@@ -0,0 +1,105 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Rng is a small SplitMix64 pseudo random number generator. It is not suitable for anything but
+/// picking samples.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// from_time returns a generator seeded from the current time.
+    pub fn from_time() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default();
+        Self::new(nanos)
+    }
+
+    /// below returns a number in the range 0..n, which must not be empty.
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+}
+
+/// Reservoir keeps a uniformly distributed random sample of a fixed number of the items offered to
+/// it, without knowing the total number of items in advance.
+pub struct Reservoir<T> {
+    capacity: usize,
+    seen: u64,
+    items: Vec<(u64, T)>,
+    rng: Rng,
+}
+
+impl<T> Reservoir<T> {
+    /// new creates a reservoir of up to capacity items. It grows as items are offered, as the
+    /// capacity may be far bigger than the number of items.
+    pub fn new(capacity: usize, rng: Rng) -> Self {
+        Self {
+            capacity,
+            seen: 0,
+            items: Vec::new(),
+            rng,
+        }
+    }
+
+    pub fn offer(&mut self, item: T) {
+        let seq = self.seen;
+        self.seen += 1;
+        if self.items.len() < self.capacity {
+            self.items.push((seq, item));
+        } else {
+            let i = self.rng.below(self.seen) as usize;
+            if i < self.capacity {
+                self.items[i] = (seq, item);
+            }
+        }
+    }
+
+    /// into_items returns the sampled items, in the order they were offered.
+    pub fn into_items(mut self) -> Vec<T> {
+        self.items.sort_unstable_by_key(|(seq, _)| *seq);
+        self.items.into_iter().map(|(_, item)| item).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(capacity: usize, seed: u64, n: usize) -> Vec<usize> {
+        let mut reservoir = Reservoir::new(capacity, Rng::new(seed));
+        for i in 0..n {
+            reservoir.offer(i);
+        }
+        reservoir.into_items()
+    }
+
+    #[test]
+    fn test_fewer_items_than_capacity() {
+        assert_eq!(sample(5, 1, 3), vec![0, 1, 2]);
+        assert_eq!(sample(0, 1, 3), Vec::<usize>::new());
+        // the capacity is not allocated up front
+        assert_eq!(sample(usize::MAX, 1, 3), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_fixed_seed() {
+        let items = sample(5, 42, 1000);
+        assert_eq!(items.len(), 5);
+        assert!(items.is_sorted());
+        assert_eq!(items, sample(5, 42, 1000));
+        assert_ne!(items, sample(5, 43, 1000));
+        assert_eq!(items, vec![442, 607, 670, 902, 910]);
+    }
+}
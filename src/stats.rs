@@ -0,0 +1,215 @@
+use regex::Regex;
+use std::collections::HashSet;
+use std::time::Duration;
+
+/// Stats counts the lines read and the lines with matches, for the summary printed by --stats.
+#[derive(Debug, Default)]
+pub struct Stats {
+    pub lines: usize,
+    pub matching_lines: usize,
+}
+
+impl Stats {
+    /// add_line counts a line, and whether it has any matches.
+    pub fn add_line(&mut self, matched: bool) {
+        self.lines += 1;
+        if matched {
+            self.matching_lines += 1;
+        }
+    }
+
+    /// summary formats the stats together with the match counts per pattern and the elapsed time.
+    pub fn summary(&self, patterns: &[String], counts: &[usize], elapsed: Duration) -> String {
+        let per_pattern = patterns
+            .iter()
+            .zip(counts)
+            .map(|(p, c)| format!("{p}: {c}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!(
+            "lines: {}, matching lines: {}, matches: {} ({per_pattern}), elapsed: {:.3}s",
+            self.lines,
+            self.matching_lines,
+            counts.iter().sum::<usize>(),
+            elapsed.as_secs_f64()
+        )
+    }
+}
+
+/// Measure adds up the sizes of the printed lines and of the escape sequences highlighting them,
+/// for the report printed by --measure.
+#[derive(Debug, Default)]
+pub struct Measure {
+    pub content_bytes: usize,
+    pub escape_bytes: usize,
+}
+
+impl Measure {
+    /// add_line counts a line of the given length, which is rendered_len long once highlighted.
+    pub fn add_line(&mut self, len: usize, rendered_len: usize) {
+        self.content_bytes += len;
+        self.escape_bytes += rendered_len.saturating_sub(len);
+    }
+
+    /// report formats the sizes, and the overhead of the escape sequences in percent.
+    pub fn report(&self) -> String {
+        let overhead = if self.content_bytes == 0 {
+            0.0
+        } else {
+            self.escape_bytes as f64 * 100.0 / self.content_bytes as f64
+        };
+        format!(
+            "content: {} bytes, escape sequences: {} bytes ({overhead:.1}% overhead)",
+            self.content_bytes, self.escape_bytes
+        )
+    }
+}
+
+/// DistinctMatches collects the distinct texts matched by every pattern, for the report printed
+/// by --count-distinct.
+#[derive(Debug)]
+pub struct DistinctMatches {
+    /// The matched texts per pattern, in the order the patterns are given in.
+    pub values: Vec<HashSet<String>>,
+}
+
+impl DistinctMatches {
+    pub fn new(pattern_count: usize) -> Self {
+        Self {
+            values: vec![HashSet::new(); pattern_count],
+        }
+    }
+
+    /// add_line adds the texts of the matches of the regexps in the line. The regexps are in the
+    /// order they are compiled in, that is, reversed, like for count_matches.
+    pub fn add_line(&mut self, line: &str, regexps: &[Regex]) {
+        for (re, values) in regexps.iter().rev().zip(&mut self.values) {
+            for m in re.find_iter(line) {
+                if !values.contains(m.as_str()) {
+                    values.insert(m.as_str().to_string());
+                }
+            }
+        }
+    }
+
+    /// report formats the number of distinct matches per pattern, followed by the matched texts of
+    /// each pattern, sorted, if list is set.
+    pub fn report(&self, patterns: &[String], list: bool) -> String {
+        let counts = patterns
+            .iter()
+            .zip(&self.values)
+            .map(|(p, values)| format!("{p}: {}", values.len()))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut report = format!("distinct matches: {counts}");
+        if list {
+            for (p, values) in patterns.iter().zip(&self.values) {
+                let mut values = values.iter().collect::<Vec<_>>();
+                values.sort();
+                report.push_str(&format!("\n{p}:"));
+                for value in values {
+                    report.push_str(&format!("\n  {value}"));
+                }
+            }
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::palette::{TerminalBackground, palette};
+    use crate::{MatchOptions, colorize, compile_patterns, count_matches, match_line};
+
+    #[test]
+    fn test_summary() {
+        let patterns = vec!["a".to_string(), "b+".to_string()];
+        let regexps = compile_patterns(&patterns, false, false).unwrap();
+        let mut stats = Stats::default();
+        let mut counts = vec![0; patterns.len()];
+        for line in ["a a b", "none", "bb", ""] {
+            count_matches(line, &regexps, &mut counts);
+            stats.add_line(regexps.iter().any(|re| re.is_match(line)));
+        }
+        assert_eq!(
+            stats.summary(&patterns, &counts, Duration::from_millis(1500)),
+            "lines: 4, matching lines: 2, matches: 4 (a: 2, b+: 2), elapsed: 1.500s"
+        );
+    }
+
+    #[test]
+    fn test_measure() {
+        let patterns = vec!["a".to_string(), "b+".to_string()];
+        let regexps = compile_patterns(&patterns, false, false).unwrap();
+        let colors = palette(
+            &["red".into(), "green".into()],
+            &[],
+            true,
+            false,
+            None,
+            TerminalBackground::Dark,
+        )
+        .unwrap();
+        let mut measure = Measure::default();
+        for line in ["a a bb\n", "none\n"] {
+            let ranges = match_line(line, &regexps, &MatchOptions::default());
+            let mut rendered = line.to_string();
+            colorize(&mut rendered, &colors, &[], &ranges, regexps.len());
+            measure.add_line(line.len(), rendered.len());
+        }
+        // three matches, each turning a color on with ESC[3Xm and off with ESC[0m
+        assert_eq!(measure.content_bytes, 12);
+        assert_eq!(measure.escape_bytes, 3 * (5 + 4));
+        assert_eq!(
+            measure.report(),
+            "content: 12 bytes, escape sequences: 27 bytes (225.0% overhead)"
+        );
+    }
+
+    #[test]
+    fn test_measure_empty() {
+        assert_eq!(
+            Measure::default().report(),
+            "content: 0 bytes, escape sequences: 0 bytes (0.0% overhead)"
+        );
+    }
+
+    #[test]
+    fn test_distinct_matches() {
+        let patterns = vec![r"\d+\.\d+\.\d+\.\d+".to_string(), "GET|POST".to_string()];
+        let regexps = compile_patterns(&patterns, false, false).unwrap();
+        let mut distinct = DistinctMatches::new(patterns.len());
+        for line in [
+            "10.0.0.1 GET /",
+            "10.0.0.2 GET /a",
+            "10.0.0.1 POST /b",
+            "10.0.0.1 10.0.0.1 GET /c",
+        ] {
+            distinct.add_line(line, &regexps);
+        }
+        let values = |idx: usize| {
+            let mut values = distinct.values[idx].iter().cloned().collect::<Vec<_>>();
+            values.sort();
+            values
+        };
+        assert_eq!(values(0), vec!["10.0.0.1", "10.0.0.2"]);
+        assert_eq!(values(1), vec!["GET", "POST"]);
+        assert_eq!(
+            distinct.report(&patterns, false),
+            r"distinct matches: \d+\.\d+\.\d+\.\d+: 2, GET|POST: 2"
+        );
+    }
+
+    #[test]
+    fn test_distinct_matches_list() {
+        let patterns = vec!["[ab]".to_string(), "x".to_string()];
+        let regexps = compile_patterns(&patterns, false, false).unwrap();
+        let mut distinct = DistinctMatches::new(patterns.len());
+        distinct.add_line("b a b a", &regexps);
+        assert_eq!(
+            distinct.report(&patterns, true),
+            "distinct matches: [ab]: 2, x: 0\n[ab]:\n  a\n  b\nx:"
+        );
+    }
+}
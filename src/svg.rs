@@ -0,0 +1,337 @@
+use crate::Result;
+use crate::escapes::sequence_len;
+use crate::named_colors::{hex, rgb_256};
+use crate::palette::parse_color;
+use unicode_width::UnicodeWidthChar;
+
+// the width of a character and the height of a line, relative to the font size
+const CHAR_WIDTH: f64 = 0.6;
+const LINE_HEIGHT: f64 = 1.2;
+// the space around the text, in characters
+const PADDING: f64 = 1.0;
+
+type Rgb = (u8, u8, u8);
+
+/// SvgOptions configures the image rendered by render_svg.
+#[derive(Clone, Debug)]
+pub struct SvgOptions {
+    pub font_size: f64,
+    pub background: Rgb,
+    pub foreground: Rgb,
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        Self {
+            font_size: 14.0,
+            background: rgb_256(0),
+            foreground: rgb_256(7),
+        }
+    }
+}
+
+/// Style is the state of the SGR (Select Graphic Rendition) escape sequences at a point of the
+/// text. Colors not set are the default ones.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+struct Style {
+    fg: Option<Rgb>,
+    bg: Option<Rgb>,
+    bold: bool,
+    reverse: bool,
+}
+
+impl Style {
+    /// apply applies the parameters of an SGR sequence like `ESC[1;38;5;214m`. Unsupported
+    /// parameters are ignored.
+    fn apply(&mut self, params: &str) {
+        let params = params
+            .split(';')
+            .map(|p| p.parse::<u16>().unwrap_or(0))
+            .collect::<Vec<_>>();
+        let mut i = 0;
+        while i < params.len() {
+            let color = |i: &mut usize| match params.get(*i + 1) {
+                Some(5) => {
+                    *i += 2;
+                    params.get(*i).map(|&idx| rgb_256(idx as u8))
+                }
+                Some(2) => {
+                    *i += 4;
+                    match params.get(*i - 2..=*i) {
+                        Some(&[r, g, b]) => Some((r as u8, g as u8, b as u8)),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            };
+            match params[i] {
+                0 => *self = Self::default(),
+                1 => self.bold = true,
+                22 => self.bold = false,
+                7 => self.reverse = true,
+                27 => self.reverse = false,
+                p @ 30..=37 => self.fg = Some(rgb_256((p - 30) as u8)),
+                38 => self.fg = color(&mut i),
+                39 => self.fg = None,
+                p @ 40..=47 => self.bg = Some(rgb_256((p - 40) as u8)),
+                48 => self.bg = color(&mut i),
+                49 => self.bg = None,
+                p @ 90..=97 => self.fg = Some(rgb_256((p - 90 + 8) as u8)),
+                p @ 100..=107 => self.bg = Some(rgb_256((p - 100 + 8) as u8)),
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+
+    /// colors returns the foreground and background colors, the background being None if it is
+    /// the default one.
+    fn colors(&self, options: &SvgOptions) -> (Rgb, Option<Rgb>) {
+        if self.reverse {
+            (
+                self.bg.unwrap_or(options.background),
+                Some(self.fg.unwrap_or(options.foreground)),
+            )
+        } else {
+            (self.fg.unwrap_or(options.foreground), self.bg)
+        }
+    }
+}
+
+/// Span is a run of text of one style, starting at the given column.
+#[derive(Debug)]
+struct Span {
+    column: usize,
+    columns: usize,
+    text: String,
+    style: Style,
+}
+
+/// spans splits a line of colored text into spans of the same style. Escape sequences other than
+/// SGR sequences are dropped. The style is carried over from the previous line.
+fn spans(line: &str, style: &mut Style) -> Vec<Span> {
+    let mut spans: Vec<Span> = Vec::new();
+    let mut column = 0;
+    let mut idx = 0;
+    while idx < line.len() {
+        if let Some(len) = sequence_len(&line[idx..]) {
+            let sequence = &line[idx..idx + len];
+            if let Some(params) = sequence
+                .strip_prefix("\x1b[")
+                .and_then(|s| s.strip_suffix('m'))
+            {
+                style.apply(params);
+            }
+            idx += len;
+            continue;
+        }
+        let c = line[idx..].chars().next().unwrap();
+        idx += c.len_utf8();
+        let width = c.width().unwrap_or(0);
+        match spans.last_mut() {
+            Some(span) if span.style == *style => {
+                span.text.push(c);
+                span.columns += width;
+            }
+            _ => spans.push(Span {
+                column,
+                columns: width,
+                text: c.to_string(),
+                style: *style,
+            }),
+        }
+        column += width;
+    }
+    spans
+}
+
+/// escape_xml escapes the characters that are special in XML text and attributes, and drops the
+/// control characters XML does not allow.
+fn escape_xml(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\t' => escaped.push(' '),
+            c if c.is_control() => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// svg_color returns the color for the given spec, like the background of --bg-colors.
+pub fn svg_color(spec: &str) -> Result<Rgb> {
+    let sequence = parse_color(spec, true)?;
+    let mut style = Style::default();
+    style.apply(&sequence[2..sequence.len() - 1]);
+    Ok(style.bg.unwrap_or_default())
+}
+
+/// render_svg renders colored text, like the output of colorexp, as a self-contained SVG image of
+/// a terminal: monospace text with the colors of the SGR escape sequences, on a background.
+pub fn render_svg(text: &str, options: &SvgOptions) -> String {
+    let text = text.strip_suffix('\n').unwrap_or(text);
+    let mut style = Style::default();
+    let lines = if text.is_empty() {
+        Vec::new()
+    } else {
+        text.split('\n')
+            .map(|line| spans(line.strip_suffix('\r').unwrap_or(line), &mut style))
+            .collect::<Vec<_>>()
+    };
+    let columns = lines
+        .iter()
+        .filter_map(|spans| spans.last().map(|s| s.column + s.columns))
+        .max()
+        .unwrap_or(0);
+    let char_width = options.font_size * CHAR_WIDTH;
+    let line_height = options.font_size * LINE_HEIGHT;
+    let x = |column: usize| (PADDING + column as f64) * char_width;
+    let width = x(columns) + PADDING * char_width;
+    let height = (lines.len() as f64 + 2.0 * PADDING) * line_height;
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width:.1}\" height=\"{height:.1}\" \
+         font-family=\"monospace\" font-size=\"{}\">\n\
+         <rect width=\"100%\" height=\"100%\" fill=\"{}\"/>\n",
+        options.font_size,
+        hex(options.background)
+    );
+    for (line_idx, spans) in lines.iter().enumerate() {
+        let top = (PADDING + line_idx as f64) * line_height;
+        for span in spans {
+            if let (_, Some(bg)) = span.style.colors(options) {
+                svg.push_str(&format!(
+                    "<rect x=\"{:.1}\" y=\"{top:.1}\" width=\"{:.1}\" height=\"{line_height:.1}\" fill=\"{}\"/>\n",
+                    x(span.column),
+                    span.columns as f64 * char_width,
+                    hex(bg)
+                ));
+            }
+        }
+        if spans.is_empty() {
+            continue;
+        }
+        // the baseline, leaving room for descenders
+        let y = top + options.font_size;
+        svg.push_str(&format!("<text y=\"{y:.1}\" xml:space=\"preserve\">"));
+        for span in spans {
+            let (fg, _) = span.style.colors(options);
+            let weight = if span.style.bold {
+                " font-weight=\"bold\""
+            } else {
+                ""
+            };
+            svg.push_str(&format!(
+                "<tspan x=\"{:.1}\" fill=\"{}\"{weight}>{}</tspan>",
+                x(span.column),
+                hex(fg),
+                escape_xml(&span.text)
+            ));
+        }
+        svg.push_str("</text>\n");
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::basic("31", Style { fg: Some((205, 0, 0)), ..Style::default() })]
+    #[case::bright_bg("104", Style { bg: Some((92, 92, 255)), ..Style::default() })]
+    #[case::indexed("38;5;214", Style { fg: Some((255, 175, 0)), ..Style::default() })]
+    #[case::truecolor("48;2;1;2;3", Style { bg: Some((1, 2, 3)), ..Style::default() })]
+    #[case::combined("1;7;32", Style { fg: Some((0, 205, 0)), bold: true, reverse: true, ..Style::default() })]
+    #[case::reset("31;0", Style::default())]
+    #[case::empty_is_reset("31;", Style::default())]
+    #[case::reset_fg("31;41;39", Style { bg: Some((205, 0, 0)), ..Style::default() })]
+    #[case::truncated("38;5", Style::default())]
+    fn test_style_apply(#[case] params: &str, #[case] expected: Style) {
+        let mut style = Style::default();
+        style.apply(params);
+        assert_eq!(style, expected);
+    }
+
+    #[test]
+    fn test_spans() {
+        let mut style = Style::default();
+        let spans = spans("a \x1b[31mbc\x1b[0m 界d\x1b]8;;x\x1b\\", &mut style);
+        assert_eq!(
+            spans
+                .iter()
+                .map(|s| (s.column, s.columns, s.text.as_str(), s.style.fg))
+                .collect::<Vec<_>>(),
+            vec![
+                (0, 2, "a ", None),
+                (2, 2, "bc", Some((205, 0, 0))),
+                (4, 4, " 界d", None)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_spans_style_carries_over() {
+        let mut style = Style::default();
+        spans("\x1b[32ma", &mut style);
+        let spans = spans("b", &mut style);
+        assert_eq!(spans[0].style.fg, Some((0, 205, 0)));
+    }
+
+    #[test]
+    fn test_render_svg() {
+        let svg = render_svg(
+            "an \x1b[31merror\x1b[0m & <more>\n\x1b[41mbg\x1b[49m\n",
+            &SvgOptions::default(),
+        );
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"159.6\""));
+        assert!(svg.contains("<rect width=\"100%\" height=\"100%\" fill=\"#000000\"/>"));
+        assert!(svg.contains("<tspan x=\"33.6\" fill=\"#cd0000\">error</tspan>"));
+        assert!(svg.contains("<tspan x=\"75.6\" fill=\"#e5e5e5\"> &amp; &lt;more&gt;</tspan>"));
+        // the background of the second line
+        assert!(svg.contains(
+            "<rect x=\"8.4\" y=\"33.6\" width=\"16.8\" height=\"16.8\" fill=\"#cd0000\"/>"
+        ));
+        assert_eq!(svg.matches("<text ").count(), 2);
+        assert!(svg.ends_with("</svg>\n"));
+    }
+
+    #[test]
+    fn test_render_svg_options() {
+        let options = SvgOptions {
+            font_size: 10.0,
+            background: (255, 255, 255),
+            foreground: (0, 0, 0),
+        };
+        let svg = render_svg("\x1b[7mx\x1b[27m", &options);
+        assert!(svg.contains("font-size=\"10\""));
+        assert!(svg.contains("fill=\"#ffffff\"/>"));
+        // reverse video swaps the default colors
+        assert!(svg.contains(
+            "<rect x=\"6.0\" y=\"12.0\" width=\"6.0\" height=\"12.0\" fill=\"#000000\"/>"
+        ));
+        assert!(svg.contains("<tspan x=\"6.0\" fill=\"#ffffff\">x</tspan>"));
+    }
+
+    #[test]
+    fn test_render_svg_empty() {
+        let svg = render_svg("", &SvgOptions::default());
+        assert!(!svg.contains("<text"));
+    }
+
+    #[rstest]
+    #[case::name("white", (229, 229, 229))]
+    #[case::bright("bright-blue", (92, 92, 255))]
+    #[case::x11("orange", (255, 175, 0))]
+    #[case::index("17", (0, 0, 95))]
+    fn test_svg_color(#[case] spec: &str, #[case] expected: Rgb) {
+        assert_eq!(svg_color(spec).unwrap(), expected);
+    }
+}
@@ -0,0 +1,174 @@
+use crate::{MatchOptions, RangeWithId, inc_ranges, match_line_into};
+use regex::Regex;
+use std::ops::Range;
+
+/// Tag is an opening, closing or self-closing XML or HTML tag found by scan_tags.
+#[derive(Debug, Eq, PartialEq)]
+struct Tag<'a> {
+    range: Range<usize>,
+    name: &'a str,
+    closing: bool,
+    self_closing: bool,
+}
+
+/// scan_tags returns the tags of the line, like `<msg level="warn">`, `</msg>` or `<br/>`. This is
+/// a scanner, not a parser: a `<` only starts a tag if it is followed by a name, so that e.g. `a < b`
+/// is text, and quoted attribute values may contain `>`. Comments and declarations like
+/// `<!DOCTYPE html>` are not tags.
+fn scan_tags(line: &str) -> Vec<Tag<'_>> {
+    let bytes = line.as_bytes();
+    let mut tags = Vec::new();
+    let mut idx = 0;
+    while let Some(offset) = line[idx..].find('<') {
+        let start = idx + offset;
+        idx = start + 1;
+        let closing = bytes.get(idx) == Some(&b'/');
+        let name_start = idx + closing as usize;
+        let name_len = line[name_start..]
+            .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, ':' | '_' | '.' | '-')))
+            .unwrap_or(line.len() - name_start);
+        if name_len == 0 || !bytes[name_start].is_ascii_alphabetic() {
+            continue;
+        }
+        // the end of the tag, skipping quoted attribute values
+        let mut quote = None;
+        let Some(end) = (name_start + name_len..line.len()).find(|&i| match (quote, bytes[i]) {
+            (None, b'"' | b'\'') => {
+                quote = Some(bytes[i]);
+                false
+            }
+            (Some(q), b) if b == q => {
+                quote = None;
+                false
+            }
+            (None, b'>') => true,
+            _ => false,
+        }) else {
+            break;
+        };
+        tags.push(Tag {
+            range: start..end + 1,
+            name: &line[name_start..name_start + name_len],
+            closing,
+            self_closing: bytes[end - 1] == b'/',
+        });
+        idx = end + 1;
+    }
+    tags
+}
+
+/// tag_text returns the ranges of the text within the tags of the given name in the line, without
+/// the markup of any tags nested in them. Tag names are compared ignoring case, like in HTML.
+/// Tags that are not closed on the line extend to its end.
+fn tag_text(line: &str, name: &str) -> Vec<Range<usize>> {
+    let mut text = Vec::new();
+    let mut depth = 0usize;
+    let mut text_start = 0;
+    for tag in scan_tags(line) {
+        if depth > 0 && text_start < tag.range.start {
+            text.push(text_start..tag.range.start);
+        }
+        if tag.name.eq_ignore_ascii_case(name) {
+            if tag.closing {
+                depth = depth.saturating_sub(1);
+            } else if !tag.self_closing {
+                depth += 1;
+            }
+        }
+        text_start = tag.range.end;
+    }
+    if depth > 0 && text_start < line.len() {
+        text.push(text_start..line.len());
+    }
+    text
+}
+
+/// match_in_tag is like match_line_into, but only matches the regexps within the text of the tags
+/// of the given name in the line, see tag_text.
+pub fn match_in_tag(
+    line: &str,
+    tag: &str,
+    regexps: &[Regex],
+    options: &MatchOptions,
+    ranges: &mut Vec<RangeWithId>,
+) {
+    ranges.clear();
+    let mut text_ranges = Vec::new();
+    for text in tag_text(line, tag) {
+        match_line_into(&line[text.clone()], regexps, options, &mut text_ranges);
+        inc_ranges(&mut text_ranges, text.start);
+        ranges.extend_from_slice(&text_ranges);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn r(start_idx: usize, end_idx: usize, id: usize) -> RangeWithId {
+        RangeWithId {
+            start_idx,
+            end_idx,
+            id,
+        }
+    }
+
+    #[test]
+    fn test_scan_tags() {
+        let line = r#"a < b <msg level="a>b">x</msg><br/> <!-- c -->"#;
+        assert_eq!(
+            scan_tags(line),
+            vec![
+                Tag {
+                    range: 6..23,
+                    name: "msg",
+                    closing: false,
+                    self_closing: false
+                },
+                Tag {
+                    range: 24..30,
+                    name: "msg",
+                    closing: true,
+                    self_closing: false
+                },
+                Tag {
+                    range: 30..35,
+                    name: "br",
+                    closing: false,
+                    self_closing: true
+                },
+            ]
+        );
+    }
+
+    #[rstest]
+    #[case::simple("<msg>hi</msg>", vec!["hi"])]
+    #[case::attributes(r#"<log><msg id="1" lang='en'>hi</msg></log>"#, vec!["hi"])]
+    #[case::nested_markup("<msg>a <b>bold</b> c</msg>", vec!["a ", "bold", " c"])]
+    #[case::nested_same_tag("<msg>a<msg>b</msg>c</msg>d", vec!["a", "b", "c"])]
+    #[case::several("<msg>a</msg> x <msg>b</msg>", vec!["a", "b"])]
+    #[case::self_closing("<msg/>text", vec![])]
+    #[case::ignoring_case("<MSG>a</Msg>", vec!["a"])]
+    #[case::unclosed("<msg>to the end", vec!["to the end"])]
+    #[case::other_tags("<message>a</message>", vec![])]
+    fn test_tag_text(#[case] line: &str, #[case] expected: Vec<&str>) {
+        assert_eq!(
+            tag_text(line, "msg")
+                .into_iter()
+                .map(|range| &line[range])
+                .collect::<Vec<_>>(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_match_in_tag() {
+        let regexps = vec![Regex::new("msg|error").unwrap()];
+        let line = r#"<msg type="error">an error</msg> error"#;
+        let mut ranges = vec![r(0, 1, 5)];
+        match_in_tag(line, "msg", &regexps, &MatchOptions::default(), &mut ranges);
+        // neither the markup nor the text after the tag is matched
+        assert_eq!(ranges, vec![r(21, 26, 0)]);
+    }
+}
@@ -0,0 +1,152 @@
+use std::char::REPLACEMENT_CHARACTER;
+use std::io::{self, Read};
+
+/// InputEncoding is an encoding of the input other than UTF-8, given by --encoding.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum InputEncoding {
+    /// ISO-8859-1, in which every byte is the Unicode character of the same number.
+    Latin1,
+    Utf16Le,
+    Utf16Be,
+}
+
+/// Transcoder reads input in the given encoding, and returns it decoded to UTF-8, so that it can
+/// be matched like any other input. Byte offsets are those of the UTF-8 text. Malformed input, like
+/// unpaired surrogates or an odd number of bytes in UTF-16, is decoded as U+FFFD. A byte order
+/// mark at the start of UTF-16 input is dropped.
+pub struct Transcoder<R> {
+    reader: R,
+    encoding: InputEncoding,
+    /// The bytes read, but not decoded yet, as they are the start of an incomplete character.
+    pending: Vec<u8>,
+    /// The decoded bytes, from pos on not returned yet.
+    decoded: Vec<u8>,
+    pos: usize,
+    at_start: bool,
+    eof: bool,
+}
+
+impl<R: Read> Transcoder<R> {
+    pub fn new(reader: R, encoding: InputEncoding) -> Self {
+        Self {
+            reader,
+            encoding,
+            pending: Vec::new(),
+            decoded: Vec::new(),
+            pos: 0,
+            at_start: true,
+            eof: false,
+        }
+    }
+
+    /// decode_pending decodes the complete characters of the pending bytes, or all of them at
+    /// the end of the input.
+    fn decode_pending(&mut self) {
+        let (text, consumed) = match self.encoding {
+            InputEncoding::Latin1 => (
+                self.pending
+                    .iter()
+                    .map(|&b| char::from(b))
+                    .collect::<String>(),
+                self.pending.len(),
+            ),
+            InputEncoding::Utf16Le | InputEncoding::Utf16Be => {
+                let mut units = self
+                    .pending
+                    .chunks_exact(2)
+                    .map(|pair| match self.encoding {
+                        InputEncoding::Utf16Le => u16::from_le_bytes([pair[0], pair[1]]),
+                        _ => u16::from_be_bytes([pair[0], pair[1]]),
+                    })
+                    .collect::<Vec<_>>();
+                // a high surrogate may be followed by its low surrogate in the next read
+                if !self.eof && units.last().is_some_and(|u| (0xd800..0xdc00).contains(u)) {
+                    units.pop();
+                }
+                let mut text = char::decode_utf16(units.iter().copied())
+                    .map(|c| c.unwrap_or(REPLACEMENT_CHARACTER))
+                    .collect::<String>();
+                let mut consumed = 2 * units.len();
+                if self.eof && consumed < self.pending.len() {
+                    text.push(REPLACEMENT_CHARACTER);
+                    consumed = self.pending.len();
+                }
+                if self.at_start && !text.is_empty() {
+                    self.at_start = false;
+                    if let Some(rest) = text.strip_prefix('\u{feff}') {
+                        text = rest.to_string();
+                    }
+                }
+                (text, consumed)
+            }
+        };
+        self.pending.drain(..consumed);
+        self.decoded.extend_from_slice(text.as_bytes());
+    }
+}
+
+impl<R: Read> Read for Transcoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.decoded.len() {
+            if self.eof {
+                return Ok(0);
+            }
+            self.decoded.clear();
+            self.pos = 0;
+            let mut chunk = [0u8; 8192];
+            let len = self.reader.read(&mut chunk)?;
+            self.eof = len == 0;
+            self.pending.extend_from_slice(&chunk[..len]);
+            self.decode_pending();
+        }
+        let len = buf.len().min(self.decoded.len() - self.pos);
+        buf[..len].copy_from_slice(&self.decoded[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    /// OneByteReader returns one byte per read, so that characters are split across reads.
+    struct OneByteReader<'a>(&'a [u8]);
+
+    impl Read for OneByteReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let Some((first, rest)) = self.0.split_first() else {
+                return Ok(0);
+            };
+            buf[0] = *first;
+            self.0 = rest;
+            Ok(1)
+        }
+    }
+
+    fn transcode(bytes: &[u8], encoding: InputEncoding) -> String {
+        let mut text = String::new();
+        Transcoder::new(OneByteReader(bytes), encoding)
+            .read_to_string(&mut text)
+            .unwrap();
+        text
+    }
+
+    #[rstest]
+    #[case::latin1(b"caf\xe9 \xb5s\n", InputEncoding::Latin1, "café µs\n")]
+    #[case::utf16le(b"a\0\xe9\0\n\0", InputEncoding::Utf16Le, "aé\n")]
+    #[case::utf16be(b"\0a\0\xe9\0\n", InputEncoding::Utf16Be, "aé\n")]
+    #[case::bom(b"\xff\xfea\0", InputEncoding::Utf16Le, "a")]
+    #[case::surrogate_pair(b"\x3d\xd8\x00\xde", InputEncoding::Utf16Le, "\u{1f600}")]
+    #[case::unpaired_surrogate(b"\x3d\xd8a\0", InputEncoding::Utf16Le, "\u{fffd}a")]
+    #[case::odd_length(b"a\0b", InputEncoding::Utf16Le, "a\u{fffd}")]
+    #[case::empty(b"", InputEncoding::Utf16Le, "")]
+    fn test_transcoder(
+        #[case] bytes: &[u8],
+        #[case] encoding: InputEncoding,
+        #[case] expected: &str,
+    ) {
+        assert_eq!(transcode(bytes, encoding), expected);
+    }
+}
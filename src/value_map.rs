@@ -0,0 +1,145 @@
+use crate::{ColorexpError, RangeWithId, Result, add_range, compile_pattern};
+use regex::Regex;
+
+/// parse_rule splits a --map rule like `5\d\d=red` into the regex matching the values, anchored so
+/// that it must match all of a value, and the color spec.
+pub fn parse_rule(rule: &str) -> Result<(Regex, &str)> {
+    let Some((value, color)) = rule.rsplit_once('=') else {
+        return Err(ColorexpError::BadColorSpec {
+            spec: rule.to_string(),
+            reason: "expected a rule like VALUE=COLOR".to_string(),
+        });
+    };
+    let re = compile_pattern(&format!("^(?:{value})$"), false, false).map_err(|err| match err {
+        // report the value as given, not the anchored one
+        ColorexpError::InvalidPattern { source, .. } => ColorexpError::InvalidPattern {
+            pattern: value.to_string(),
+            source,
+        },
+        err => err,
+    })?;
+    Ok((re, color))
+}
+
+/// ValueMap colors the given capturing group (a number or a name) of every match by its text: the
+/// group gets the id of the first rule whose regex matches it.
+pub struct ValueMap {
+    pub group: String,
+    /// The regexes matching the values, with the ids to give them.
+    pub rules: Vec<(Regex, usize)>,
+}
+
+impl ValueMap {
+    /// id returns the id of the first rule matching the value, if any.
+    fn id(&self, value: &str) -> Option<usize> {
+        self.rules
+            .iter()
+            .find(|(re, _)| re.is_match(value))
+            .map(|(_, id)| *id)
+    }
+
+    /// recolor adds a range for the group of each match of the regexps whose text is matched by a
+    /// rule. These ranges take precedence over the existing ones, which are kept elsewhere.
+    pub fn recolor(&self, line: &str, regexps: &[Regex], ranges: &mut Vec<RangeWithId>) {
+        let group_idx = self.group.parse::<usize>().ok();
+        let mut mapped = Vec::new();
+        for re in regexps {
+            for caps in re.captures_iter(line) {
+                let group = match group_idx {
+                    Some(idx) => caps.get(idx),
+                    None => caps.name(&self.group),
+                };
+                let Some(group) = group.filter(|g| !g.is_empty()) else {
+                    continue;
+                };
+                if let Some(id) = self.id(group.as_str()) {
+                    add_range(
+                        &mut mapped,
+                        RangeWithId {
+                            start_idx: group.start(),
+                            end_idx: group.end(),
+                            id,
+                        },
+                    );
+                }
+            }
+        }
+        if mapped.is_empty() {
+            return;
+        }
+        for r in std::mem::take(ranges) {
+            add_range(&mut mapped, r);
+        }
+        *ranges = mapped;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MatchOptions, compile_patterns, match_line_into};
+    use rstest::rstest;
+
+    fn r(start_idx: usize, end_idx: usize, id: usize) -> RangeWithId {
+        RangeWithId {
+            start_idx,
+            end_idx,
+            id,
+        }
+    }
+
+    fn status_map(group: &str) -> ValueMap {
+        let rules = [r"2\d\d=green", r"4\d\d=yellow", r"5\d\d=red"]
+            .iter()
+            .enumerate()
+            .map(|(i, rule)| (parse_rule(rule).unwrap().0, 10 + i))
+            .collect();
+        ValueMap {
+            group: group.to_string(),
+            rules,
+        }
+    }
+
+    #[rstest]
+    #[case::simple("2xx=green", "2xx", "green")]
+    #[case::equals_in_value("a=b=red", "a=b", "red")]
+    fn test_parse_rule(#[case] rule: &str, #[case] value: &str, #[case] color: &str) {
+        let (re, c) = parse_rule(rule).unwrap();
+        assert!(re.is_match(value));
+        assert!(!re.is_match(&format!("{value}0")));
+        assert_eq!(c, color);
+    }
+
+    #[rstest]
+    #[case::no_equals("2xx")]
+    #[case::invalid_value("(=red")]
+    fn test_parse_rule_invalid(#[case] rule: &str) {
+        assert!(parse_rule(rule).is_err());
+    }
+
+    #[rstest]
+    #[case::ok("GET / 200", vec![r(6, 9, 10)])]
+    #[case::not_found("GET / 404", vec![r(6, 9, 11)])]
+    #[case::error("GET / 503", vec![r(6, 9, 12)])]
+    #[case::unmapped("GET / 301", vec![r(6, 9, 0)])]
+    #[case::several("200 404", vec![r(0, 3, 10), r(4, 7, 11)])]
+    fn test_recolor(#[case] line: &str, #[case] expected: Vec<RangeWithId>) {
+        let regexps = compile_patterns(&[r"\d{3}".into()], false, false).unwrap();
+        let mut ranges = Vec::new();
+        match_line_into(line, &regexps, &MatchOptions::default(), &mut ranges);
+        status_map("0").recolor(line, &regexps, &mut ranges);
+        assert_eq!(ranges, expected);
+    }
+
+    #[rstest]
+    #[case::number("1")]
+    #[case::name("status")]
+    fn test_recolor_group(#[case] group: &str) {
+        let line = "status=500 took=200";
+        let regexps = compile_patterns(&[r"status=(?<status>\d+)".into()], false, false).unwrap();
+        let mut ranges = Vec::new();
+        match_line_into(line, &regexps, &MatchOptions::default(), &mut ranges);
+        status_map(group).recolor(line, &regexps, &mut ranges);
+        assert_eq!(ranges, vec![r(7, 10, 12)]);
+    }
+}
@@ -0,0 +1,179 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread::sleep;
+use std::time::{Duration, SystemTime};
+
+/// CLEAR_SCREEN moves the cursor to the top left corner of the terminal, and clears it.
+pub const CLEAR_SCREEN: &str = "\x1b[H\x1b[2J";
+/// how long to wait for more changes after one, so that a file being written is rendered once
+pub const DEBOUNCE: Duration = Duration::from_millis(100);
+// how often the file is checked for changes if there are no notifications
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Stamp tells versions of a file apart by their modification times and sizes. It is None if the
+/// file does not exist, e.g. while it is being replaced.
+type Stamp = Option<(SystemTime, u64)>;
+
+fn stamp(path: &Path) -> Stamp {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// Watcher waits for a file to change, for --watch. It is woken up by filesystem notifications
+/// where they are available, and otherwise polls the file.
+pub struct Watcher {
+    path: PathBuf,
+    stamp: Stamp,
+    #[cfg(target_os = "linux")]
+    notifications: Option<inotify::Inotify>,
+}
+
+impl Watcher {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            stamp: stamp(path),
+            #[cfg(target_os = "linux")]
+            notifications: inotify::Inotify::new(path),
+        }
+    }
+
+    /// changed returns whether the file changed since the watcher was created, or since the last
+    /// call to changed.
+    pub fn changed(&mut self) -> bool {
+        let stamp = stamp(&self.path);
+        let changed = stamp != self.stamp;
+        self.stamp = stamp;
+        changed
+    }
+
+    /// wait blocks until the file changes, and then until it has not changed for debounce.
+    pub fn wait(&mut self, debounce: Duration) {
+        while !self.changed() {
+            self.sleep(POLL_INTERVAL);
+        }
+        loop {
+            sleep(debounce);
+            if !self.changed() {
+                break;
+            }
+        }
+    }
+
+    /// sleep sleeps for the timeout, or until a notification about the file arrives.
+    fn sleep(&self, timeout: Duration) {
+        #[cfg(target_os = "linux")]
+        if let Some(notifications) = &self.notifications {
+            notifications.wait(timeout);
+            return;
+        }
+        sleep(timeout);
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod inotify {
+    use std::ffi::CString;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+    use std::time::Duration;
+
+    /// Inotify receives notifications about the changes in the directory of a file. The directory
+    /// is watched rather than the file, as files are often replaced rather than written to.
+    pub struct Inotify(OwnedFd);
+
+    impl Inotify {
+        /// new returns None if the notifications are not available, e.g. because the limit of
+        /// watches has been reached.
+        pub fn new(path: &Path) -> Option<Self> {
+            let dir = match path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            };
+            let dir = CString::new(dir.as_os_str().as_bytes()).ok()?;
+            // SAFETY: inotify_init1 has no preconditions
+            let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+            if fd < 0 {
+                return None;
+            }
+            // SAFETY: the fd is valid, and owned by nobody else
+            let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+            let mask = libc::IN_MODIFY
+                | libc::IN_CLOSE_WRITE
+                | libc::IN_CREATE
+                | libc::IN_DELETE
+                | libc::IN_MOVED_TO
+                | libc::IN_ATTRIB;
+            // SAFETY: the path is a valid C string
+            if unsafe { libc::inotify_add_watch(fd.as_raw_fd(), dir.as_ptr(), mask) } < 0 {
+                return None;
+            }
+            Some(Self(fd))
+        }
+
+        /// wait waits for the timeout, or until notifications arrive, which are then discarded, as
+        /// the watcher checks the file itself.
+        pub fn wait(&self, timeout: Duration) {
+            let mut pollfd = libc::pollfd {
+                fd: self.0.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            // SAFETY: pollfd is valid for one entry
+            unsafe { libc::poll(&mut pollfd, 1, timeout.as_millis() as libc::c_int) };
+            let mut buf = [0u8; 4096];
+            // SAFETY: the buffer is valid for its length; the fd is non-blocking, so this stops
+            // once all notifications are read
+            while unsafe { libc::read(self.0.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) } > 0
+            {
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("colorexp-{}-watch-{name}", std::process::id()))
+    }
+
+    #[test]
+    fn test_changed() {
+        let path = temp_path("changed");
+        fs::write(&path, "first\n").unwrap();
+        let mut watcher = Watcher::new(&path);
+        assert!(!watcher.changed());
+        // successive versions of the file, each of which is a change
+        fs::write(&path, "second version\n").unwrap();
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+        fs::remove_file(&path).unwrap();
+        assert!(watcher.changed());
+        fs::write(&path, "third\n").unwrap();
+        assert!(watcher.changed());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_wait() {
+        let path = temp_path("wait");
+        fs::write(&path, "first\n").unwrap();
+        let mut watcher = Watcher::new(&path);
+        let writer = {
+            let path = path.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                fs::write(&path, "second version\n").unwrap();
+            })
+        };
+        watcher.wait(Duration::from_millis(10));
+        writer.join().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second version\n");
+        assert!(!watcher.changed());
+        fs::remove_file(&path).unwrap();
+    }
+}